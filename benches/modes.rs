@@ -0,0 +1,278 @@
+// modes.rs - Ardura 2023
+// CPU cost of every saturation and console mode, mono and stereo, from 32 to 2048 sample buffers and
+// with the editor's meters on or off, through the same engine Gain::process runs
+//  cargo bench --bench modes writes the cost in ns per sample frame to benches/results/<version>.csv
+//  and prints how it moved against the results of the version before
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use criterion::{black_box, BenchmarkId, Criterion, Throughput};
+use duro_dsp::Mode;
+use Duro_Console::{
+    chain::ChainSettings,
+    convolution::Convolver,
+    duro_process::{ConsoleMode, SaturationModeEnum},
+    dynamic_convolution::DynamicConvolver,
+    engine::{Engine, Handoff},
+    GainParams,
+};
+
+const SAMPLE_RATE: f32 = 48000.0;
+const BUFFER_SIZES: [usize; 4] = [32, 128, 512, 2048];
+// A medium sized room, long enough that the convolution consoles do real work
+const IMPULSE_SECONDS: f32 = 0.5;
+// Slower than this against the last version gets flagged in the summary
+const REGRESSION: f64 = 0.10;
+const CSV_HEADER: &str = "kind,mode,channels,buffer,meters,ns_per_sample";
+
+// One benchmark's place in the results file
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Case {
+    kind: &'static str,
+    mode: String,
+    channels: usize,
+    buffer: usize,
+    meters: bool,
+}
+
+impl Case {
+    fn key(&self) -> String {
+        format!("{},{},{},{},{}", self.kind, self.mode, self.channels, self.buffer, if self.meters { "on" } else { "off" })
+    }
+}
+
+thread_local! {
+    // Criterion's full id of every benchmark that ran, a filter on the command line skips the rest
+    static RAN: RefCell<Vec<(String, Case)>> = const { RefCell::new(Vec::new()) };
+}
+
+// Display name of a mode without the quotes some of them have
+fn name<T: Mode>(mode: T) -> String {
+    T::variants()[mode.to_index()].replace('"', "")
+}
+
+// Small deterministic noise source so every run plays the same signal
+fn noise(length: usize, seed: u32) -> Vec<f32> {
+    let mut state = seed.max(1);
+    (0..length)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32 - 0.5
+        })
+        .collect()
+}
+
+// Decaying noise standing in for a loaded impulse response
+fn impulse() -> Vec<f32> {
+    let length = (SAMPLE_RATE * IMPULSE_SECONDS) as usize;
+    noise(length, 7).iter().enumerate().map(|(n, x)| x * (-6.0 * n as f32 / length as f32).exp()).collect()
+}
+
+// Everything the plugin has set up by the time it processes: impulse responses, curve, formula and the
+// generated console, handed over the way the loaders and the editor do
+fn new_engine(params: &GainParams, sat_type: SaturationModeEnum, console_type: ConsoleMode) -> Engine {
+    let settings = with_modes(params.settings(), sat_type, console_type);
+    let mut engine = Engine::new(SAMPLE_RATE, Handoff::new(settings.console_seed, settings.console_tilt));
+    let impulse = impulse();
+    let quiet: Vec<f32> = impulse.iter().map(|x| x * 0.5).collect();
+    engine.handoff.convolver_slot.lock().unwrap().offer(Box::new(Convolver::new(&impulse)));
+    engine.handoff.dynamic_slot.lock().unwrap().offer(Box::new(DynamicConvolver::new(&[(-24.0, quiet), (-6.0, impulse)], SAMPLE_RATE)));
+    engine.chain.update_ceiling(settings.ceiling_mode, settings.ceiling);
+    engine.start_block(&settings, true);
+    engine.chain.jump_to(&settings);
+    engine
+}
+
+fn with_modes(mut settings: ChainSettings, sat_type: SaturationModeEnum, console_type: ConsoleMode) -> ChainSettings {
+    settings.sat_type = sat_type;
+    settings.console_type = console_type;
+    // Some harmonics so the harmonic based modes have something to add
+    settings.harmonic_levels[0] = 0.1;
+    settings.harmonic_levels[1] = 0.05;
+    settings
+}
+
+// One host buffer the way Gain::process goes through it, interleaved input to interleaved output
+fn process_buffer(
+    engine: &mut Engine,
+    params: &GainParams,
+    metering: bool,
+    (sat_type, console_type): (SaturationModeEnum, ConsoleMode),
+    channels: usize,
+    input: &[f32],
+    output: &mut [f32],
+) {
+    engine.start_block(&with_modes(params.settings(), sat_type, console_type), true);
+    for (input, output) in input.chunks_exact(channels).zip(output.chunks_exact_mut(channels)) {
+        output.copy_from_slice(input);
+        engine.process_frame(output, &with_modes(params.next_settings(), sat_type, console_type), metering);
+    }
+}
+
+fn bench_mode(c: &mut Criterion, kind: &'static str, sat_type: SaturationModeEnum, console_type: ConsoleMode) {
+    let mode = if kind == "saturation" { name(sat_type) } else { name(console_type) };
+    let mut group = c.benchmark_group(format!("{kind} {mode}"));
+    let params = GainParams::default();
+    for channels in [1, 2] {
+        for buffer in BUFFER_SIZES {
+            for metering in [false, true] {
+                let case = Case { kind, mode: mode.clone(), channels, buffer, meters: metering };
+                let layout = if channels == 1 { "mono" } else { "stereo" };
+                let function = format!("{layout} meters {}", if metering { "on" } else { "off" });
+                let full_id = format!("{kind} {mode}/{function}/{buffer}");
+                let id = BenchmarkId::new(function, buffer);
+
+                let mut engine = new_engine(&params, sat_type, console_type);
+                let input = noise(buffer * channels, 1);
+                let mut output = vec![0.0; buffer * channels];
+                group.throughput(Throughput::Elements(buffer as u64));
+                group.bench_function(id, |b| {
+                    RAN.with(|ran| ran.borrow_mut().push((full_id.clone(), case.clone())));
+                    b.iter(|| {
+                        process_buffer(&mut engine, &params, metering, (sat_type, console_type), channels, black_box(&input), &mut output);
+                        black_box(&output);
+                    })
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+fn bench_saturation(c: &mut Criterion) {
+    for index in 0..SaturationModeEnum::COUNT {
+        bench_mode(c, "saturation", SaturationModeEnum::from_index(index), ConsoleMode::BYPASS);
+    }
+}
+
+fn bench_console(c: &mut Criterion) {
+    for index in 0..ConsoleMode::COUNT {
+        bench_mode(c, "console", SaturationModeEnum::NONESAT, ConsoleMode::from_index(index));
+    }
+}
+
+// Where criterion keeps its results. Pinned before it starts so a workspace or a custom target
+// directory can't put them somewhere the summary doesn't look
+fn criterion_dir() -> PathBuf {
+    match std::env::var_os("CRITERION_HOME") {
+        Some(home) => PathBuf::from(home),
+        None => {
+            let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("criterion");
+            std::env::set_var("CRITERION_HOME", &dir);
+            dir
+        }
+    }
+}
+
+// Every benchmark.json criterion wrote for the latest run, by full id, with the mean in ns per iteration
+fn collect_estimates(dir: &Path, found: &mut BTreeMap<String, f64>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_estimates(&path, found);
+        } else if path.ends_with("new/benchmark.json") {
+            let read = |file: &Path| fs::read_to_string(file).ok().and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok());
+            let (Some(benchmark), Some(estimates)) = (read(&path), read(&path.with_file_name("estimates.json"))) else { continue };
+            if let (Some(id), Some(mean)) = (benchmark["full_id"].as_str(), estimates["mean"]["point_estimate"].as_f64()) {
+                found.insert(id.to_string(), mean);
+            }
+        }
+    }
+}
+
+fn read_results(path: &Path) -> BTreeMap<String, f64> {
+    let Ok(text) = fs::read_to_string(path) else { return BTreeMap::new() };
+    text.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let (key, value) = line.rsplit_once(',')?;
+            Some((key.to_string(), value.parse().ok()?))
+        })
+        .collect()
+}
+
+// Newest results file from a version before this one
+fn previous_results(dir: &Path, version: &str) -> Option<(String, PathBuf)> {
+    let parse = |version: &str| -> Option<Vec<u64>> { version.split('.').map(|part| part.parse().ok()).collect() };
+    let current = parse(version)?;
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let stem = path.file_stem()?.to_str()?.to_string();
+            let parsed = parse(&stem)?;
+            (path.extension()? == "csv" && parsed < current).then_some((parsed, stem, path))
+        })
+        .max()
+        .map(|(_, stem, path)| (stem, path))
+}
+
+// Fold this run into the version's results file and compare it with the version before
+fn write_summary(criterion_dir: &Path) {
+    let mut estimates = BTreeMap::new();
+    collect_estimates(criterion_dir, &mut estimates);
+    let version = env!("CARGO_PKG_VERSION");
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("benches").join("results");
+    let path = dir.join(format!("{version}.csv"));
+    let mut results = read_results(&path);
+    let previous = previous_results(&dir, version).map(|(version, path)| (version, read_results(&path)));
+
+    println!("\n{:<60} {:>12} {:>10}", "ns per sample frame", version, "change");
+    let ran = RAN.with(|ran| ran.borrow().clone());
+    let mut cases: BTreeMap<Case, String> = BTreeMap::new();
+    for (full_id, case) in ran {
+        cases.insert(case, full_id);
+    }
+    for (case, full_id) in cases {
+        let Some(mean) = estimates.get(&full_id) else { continue };
+        let ns_per_sample = mean / case.buffer as f64;
+        let key = case.key();
+        let change = previous.as_ref().and_then(|(_, old)| old.get(&key)).map(|old| ns_per_sample / old - 1.0);
+        let change_text = change.map_or(String::new(), |change| {
+            format!("{:+.1}%{}", change * 100.0, if change > REGRESSION { " slower" } else { "" })
+        });
+        println!("{:<60} {:>12.2} {:>10}", full_id, ns_per_sample, change_text);
+        results.insert(key, ns_per_sample);
+    }
+    if let Some((old_version, _)) = previous {
+        println!("change is against {old_version}");
+    }
+
+    let mut text = String::from(CSV_HEADER);
+    for (key, ns_per_sample) in results {
+        text.push_str(&format!("\n{key},{ns_per_sample:.3}"));
+    }
+    text.push('\n');
+    if let Err(error) = fs::create_dir_all(&dir).and_then(|_| fs::write(&path, text)) {
+        eprintln!("could not write {}: {error}", path.display());
+    } else {
+        println!("wrote {}", path.display());
+    }
+}
+
+fn main() {
+    let criterion_dir = criterion_dir();
+    let mut criterion = Criterion::default()
+        .sample_size(20)
+        .warm_up_time(Duration::from_millis(500))
+        .measurement_time(Duration::from_secs(1))
+        .configure_from_args();
+    bench_saturation(&mut criterion);
+    bench_console(&mut criterion);
+    criterion.final_summary();
+
+    // cargo test --benches runs every benchmark once without measuring, nothing to store then
+    if std::env::args().any(|arg| arg == "--bench") {
+        write_summary(&criterion_dir);
+    }
+}
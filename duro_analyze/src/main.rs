@@ -0,0 +1,345 @@
+// main.rs - Ardura 2023
+// duro-analyze: measures every saturation and console combination with test tones
+//  Writes THD, THD+N, harmonic, IMD and gain tables as CSV and plots of them as SVG
+
+mod measure;
+mod svg;
+
+use std::{fmt::Write as _, fs, path::{Path, PathBuf}, process::ExitCode};
+
+use duro_dsp::Mode;
+use Duro_Console::{
+    chain::{ChainSettings, DuroChain},
+    convolution::{self, Convolver},
+    duro_process::{ConsoleMode, SaturationModeEnum},
+    dynamic_convolution::{self, DynamicConvolver},
+    GainParams,
+};
+
+use measure::{to_db, Analyzer, Distortion, HIGHEST_HARMONIC};
+
+const SAMPLE_RATE: f32 = 48000.0;
+const FFT_SIZE: usize = 65536;
+// Long enough for the compressor, the DC filter and the delay lines to settle before measuring
+const SETTLE_SECONDS: f32 = 1.0;
+const LEVELS_DBFS: [f32; 5] = [-24.0, -18.0, -12.0, -6.0, 0.0];
+const FREQUENCIES: [f32; 3] = [100.0, 1000.0, 5000.0];
+// The tone the spectrum and THD plots use
+const PLOT_FREQUENCY: f32 = 1000.0;
+const PLOT_LEVEL_DBFS: f32 = -6.0;
+// Plots stop here, anything lower is rounding noise
+const PLOT_FLOOR_DB: f64 = -140.0;
+// Low and high tone, the low one 12dB louder
+const SMPTE_TONES: (f32, f32) = (60.0, 7000.0);
+// Two equal tones 1kHz apart at the top of the audio band
+const CCIF_TONES: (f32, f32) = (19000.0, 20000.0);
+// Defaults that actually drive the curves, the plugin's own start at 0
+const DEFAULT_OVERRIDES: [(&str, &str); 2] = [("drive", "100"), ("console_drive", "100")];
+
+const USAGE: &str = "Usage: duro-analyze [OPTIONS]
+
+Options:
+  -o, --output <FOLDER>  Where the CSV and SVG files go (default: analysis)
+  --<id> <VALUE>         Set a parameter like duro-render does, drive and console_drive default to 100%
+  --ir <FILE>            Impulse response for the Impulse Response console
+  --kernels <FOLDER>     Kernel folder for the Dynamic Response console
+  -h, --help             Show this help";
+
+struct Setup {
+    base: ChainSettings,
+    impulse: Option<Vec<f32>>,
+    kernels: Option<Vec<(f32, Vec<f32>)>>,
+}
+
+struct ToneResult {
+    sat_type: SaturationModeEnum,
+    console_type: ConsoleMode,
+    frequency: f32,
+    level: f32,
+    distortion: Distortion,
+}
+
+struct ImdResult {
+    sat_type: SaturationModeEnum,
+    console_type: ConsoleMode,
+    level: f32,
+    smpte: f64,
+    ccif: f64,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    match parse_args(&args).and_then(|(setup, output)| analyze(&setup, &output)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("duro-analyze: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<(Setup, PathBuf), String> {
+    let params = GainParams::default();
+    let mut base = params.settings();
+    for (id, value) in DEFAULT_OVERRIDES {
+        params.set_from_string(&mut base, id, value)?;
+    }
+    let mut setup = Setup {
+        base,
+        impulse: None,
+        kernels: None,
+    };
+    let mut output = PathBuf::from("analysis");
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let flag = arg
+            .strip_prefix("--")
+            .or_else(|| arg.strip_prefix('-').filter(|flag| *flag == "o"))
+            .ok_or_else(|| format!("Unexpected '{arg}', see --help"))?;
+        let value = args.next().ok_or_else(|| format!("{arg} needs a value"))?;
+        match flag {
+            "o" | "output" => output = PathBuf::from(value),
+            "ir" => setup.impulse = Some(convolution::load_impulse(value, SAMPLE_RATE)?),
+            "kernels" => setup.kernels = Some(dynamic_convolution::load_kernels(value, SAMPLE_RATE)?),
+            _ => params.set_from_string(&mut setup.base, flag, value)?,
+        }
+    }
+    Ok((setup, output))
+}
+
+fn analyze(setup: &Setup, output: &Path) -> Result<(), String> {
+    fs::create_dir_all(output).map_err(|e| format!("Could not create {}: {e}", output.display()))?;
+    let analyzer = Analyzer::new(FFT_SIZE, SAMPLE_RATE);
+    let sat_types: Vec<SaturationModeEnum> = (0..SaturationModeEnum::COUNT).map(SaturationModeEnum::from_index).collect();
+    let console_types: Vec<ConsoleMode> = (0..ConsoleMode::COUNT).map(ConsoleMode::from_index).collect();
+
+    let mut tones = Vec::new();
+    let mut imds = Vec::new();
+    for sat_type in sat_types.iter().copied() {
+        println!("Measuring {}", sat_name(sat_type));
+        for console_type in console_types.iter().copied() {
+            for level in LEVELS_DBFS {
+                let amplitude = 10f32.powf(level / 20.0);
+                for frequency in FREQUENCIES {
+                    let bin = analyzer.tone_bin(frequency);
+                    let output = render(setup, sat_type, console_type, &analyzer, |phase| amplitude * tone(phase, bin));
+                    tones.push(ToneResult {
+                        sat_type,
+                        console_type,
+                        frequency: analyzer.frequency(bin),
+                        level,
+                        distortion: analyzer.distortion(&output, bin, amplitude as f64),
+                    });
+                }
+
+                let (low, high) = (analyzer.tone_bin(SMPTE_TONES.0), analyzer.tone_bin(SMPTE_TONES.1));
+                let smpte = render(setup, sat_type, console_type, &analyzer, |phase| {
+                    amplitude * (0.8 * tone(phase, low) + 0.2 * tone(phase, high))
+                });
+                let (first, second) = (analyzer.tone_bin(CCIF_TONES.0), analyzer.tone_bin(CCIF_TONES.1));
+                let ccif = render(setup, sat_type, console_type, &analyzer, |phase| {
+                    amplitude * 0.5 * (tone(phase, first) + tone(phase, second))
+                });
+                imds.push(ImdResult {
+                    sat_type,
+                    console_type,
+                    level,
+                    smpte: analyzer.smpte(&smpte, low, high),
+                    ccif: analyzer.ccif(&ccif, first, second),
+                });
+            }
+        }
+    }
+
+    write_file(output, "thd.csv", &tone_csv(&tones))?;
+    write_file(output, "imd.csv", &imd_csv(&imds))?;
+    write_plots(output, &tones, &imds, &sat_types, &console_types)?;
+    println!("Wrote the results to {}", output.display());
+    Ok(())
+}
+
+// Sine that fits a whole number of cycles in the FFT window, phase is the sample index in the window
+fn tone(phase: usize, bin: usize) -> f32 {
+    (2.0 * std::f64::consts::PI * (bin * phase) as f64 / FFT_SIZE as f64).sin() as f32
+}
+
+// Run a test signal through a fresh mono chain and keep the FFT window after it settled
+fn render(
+    setup: &Setup,
+    sat_type: SaturationModeEnum,
+    console_type: ConsoleMode,
+    analyzer: &Analyzer,
+    signal: impl Fn(usize) -> f32,
+) -> Vec<f32> {
+    let mut settings = setup.base;
+    settings.sat_type = sat_type;
+    settings.console_type = console_type;
+
+    let mut chain = DuroChain::new(SAMPLE_RATE);
+    chain.update_ceiling(settings.ceiling_mode, settings.ceiling);
+    chain.jump_to(&settings);
+    chain.generate_console(&settings);
+    chain.start_block(&settings, false);
+    if let Some(impulse) = &setup.impulse {
+        chain.console_mut().swap_convolver(Box::new(Convolver::new(impulse)));
+    }
+    if let Some(kernels) = &setup.kernels {
+        chain.console_mut().swap_dynamic(Box::new(DynamicConvolver::new(kernels, SAMPLE_RATE)));
+    }
+
+    let size = analyzer.size();
+    let settle = (SETTLE_SECONDS * SAMPLE_RATE) as usize;
+    let mut output = Vec::with_capacity(size);
+    for index in 0..settle + size {
+        let mut frame = [signal(index % size)];
+        chain.process_frame(&mut frame, &settings);
+        if index >= settle {
+            output.push(frame[0]);
+        }
+    }
+    output
+}
+
+fn sat_name(sat_type: SaturationModeEnum) -> &'static str {
+    SaturationModeEnum::variants()[sat_type.to_index()]
+}
+
+fn console_name(console_type: ConsoleMode) -> &'static str {
+    ConsoleMode::variants()[console_type.to_index()]
+}
+
+// Mode names as they can go in a CSV cell or a file name
+fn csv_name(name: &str) -> String {
+    name.replace('"', "")
+}
+
+fn file_name(name: &str) -> String {
+    csv_name(name).to_lowercase().replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+}
+
+fn plot_db(ratio: f64) -> f64 {
+    to_db(ratio).max(PLOT_FLOOR_DB)
+}
+
+fn write_file(folder: &Path, name: &str, contents: &str) -> Result<(), String> {
+    let path = folder.join(name);
+    fs::write(&path, contents).map_err(|e| format!("Could not write {}: {e}", path.display()))
+}
+
+fn tone_csv(tones: &[ToneResult]) -> String {
+    let mut csv = String::from("saturation,console,frequency_hz,level_dbfs,gain_db,thd_percent,thd_db,thdn_percent,thdn_db");
+    for harmonic in 2..=HIGHEST_HARMONIC {
+        let _ = write!(csv, ",h{harmonic}_db");
+    }
+    csv.push('\n');
+    for result in tones {
+        let distortion = &result.distortion;
+        let _ = write!(
+            csv,
+            "{},{},{:.2},{},{:.3},{:.5},{:.2},{:.5},{:.2}",
+            csv_name(sat_name(result.sat_type)),
+            csv_name(console_name(result.console_type)),
+            result.frequency,
+            result.level,
+            to_db(distortion.gain),
+            distortion.thd * 100.0,
+            to_db(distortion.thd),
+            distortion.thd_n * 100.0,
+            to_db(distortion.thd_n),
+        );
+        for level in distortion.harmonics.iter() {
+            let _ = write!(csv, ",{:.2}", to_db(*level));
+        }
+        csv.push('\n');
+    }
+    csv
+}
+
+fn imd_csv(imds: &[ImdResult]) -> String {
+    let mut csv = String::from("saturation,console,level_dbfs,smpte_percent,smpte_db,ccif_percent,ccif_db\n");
+    for result in imds {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{:.5},{:.2},{:.5},{:.2}",
+            csv_name(sat_name(result.sat_type)),
+            csv_name(console_name(result.console_type)),
+            result.level,
+            result.smpte * 100.0,
+            to_db(result.smpte),
+            result.ccif * 100.0,
+            to_db(result.ccif),
+        );
+    }
+    csv
+}
+
+// Saturation plots are with the console bypassed and console plots without saturation, so each
+// shows only what that mode does
+fn write_plots(
+    folder: &Path,
+    tones: &[ToneResult],
+    imds: &[ImdResult],
+    sat_types: &[SaturationModeEnum],
+    console_types: &[ConsoleMode],
+) -> Result<(), String> {
+    let at_plot_frequency = |result: &&ToneResult| (result.frequency - PLOT_FREQUENCY).abs() < 1.0;
+    let harmonic_labels: Vec<String> = (2..=HIGHEST_HARMONIC).map(|harmonic| format!("H{harmonic}")).collect();
+
+    for sat_type in sat_types.iter().copied() {
+        let Some(result) = tones.iter().filter(at_plot_frequency).find(|result| {
+            result.sat_type == sat_type && result.console_type == ConsoleMode::BYPASS && result.level == PLOT_LEVEL_DBFS
+        }) else {
+            continue;
+        };
+        let levels: Vec<f64> = result.distortion.harmonics.iter().map(|level| plot_db(*level)).collect();
+        let title = format!("{} harmonics, 1kHz at {PLOT_LEVEL_DBFS}dBFS", csv_name(sat_name(sat_type)));
+        let chart = svg::bar_chart(&title, "dB relative to the fundamental", &harmonic_labels, &levels);
+        write_file(folder, &format!("spectrum_{}.svg", file_name(sat_name(sat_type))), &chart)?;
+    }
+
+    let thd_series = |name: &str, matches: &dyn Fn(&ToneResult) -> bool| svg::Series {
+        name: csv_name(name),
+        points: tones
+            .iter()
+            .filter(at_plot_frequency)
+            .filter(|result| matches(result))
+            .map(|result| (result.level as f64, plot_db(result.distortion.thd)))
+            .collect(),
+    };
+    let sat_lines: Vec<svg::Series> = sat_types
+        .iter()
+        .map(|sat_type| thd_series(sat_name(*sat_type), &|result| result.sat_type == *sat_type && result.console_type == ConsoleMode::BYPASS))
+        .collect();
+    let chart = svg::line_chart("Saturation THD at 1kHz", "Input level (dBFS)", "THD (dB)", &sat_lines);
+    write_file(folder, "thd_saturation.svg", &chart)?;
+
+    let console_lines: Vec<svg::Series> = console_types
+        .iter()
+        .map(|console_type| {
+            thd_series(console_name(*console_type), &|result| {
+                result.console_type == *console_type && result.sat_type == SaturationModeEnum::NONESAT
+            })
+        })
+        .collect();
+    let chart = svg::line_chart("Console THD at 1kHz", "Input level (dBFS)", "THD (dB)", &console_lines);
+    write_file(folder, "thd_console.svg", &chart)?;
+
+    let labels: Vec<String> = sat_types.iter().map(|sat_type| csv_name(sat_name(*sat_type))).collect();
+    let smpte: Vec<f64> = sat_types
+        .iter()
+        .map(|sat_type| {
+            imds.iter()
+                .find(|result| result.sat_type == *sat_type && result.console_type == ConsoleMode::BYPASS && result.level == PLOT_LEVEL_DBFS)
+                .map(|result| plot_db(result.smpte))
+                .unwrap_or(f64::NAN)
+        })
+        .collect();
+    let chart = svg::bar_chart(&format!("SMPTE IMD at {PLOT_LEVEL_DBFS}dBFS"), "IMD (dB)", &labels, &smpte);
+    write_file(folder, "imd_smpte.svg", &chart)?;
+    Ok(())
+}
@@ -0,0 +1,120 @@
+// measure.rs - Ardura 2023
+// Distortion measurements from one FFT of the steady state output
+//  Every test tone sits exactly on an FFT bin, so with a whole number of cycles in the window no
+//  window function is needed and each harmonic or product lands on a single bin
+
+use std::sync::Arc;
+
+use realfft::{RealFftPlanner, RealToComplex};
+
+// Harmonics 2 up to this one are reported, ones past Nyquist are read where they alias to
+pub const HIGHEST_HARMONIC: usize = 10;
+// SMPTE sidebands either side of the high tone that go into the result
+const SMPTE_SIDEBANDS: usize = 4;
+
+pub struct Analyzer {
+    size: usize,
+    sample_rate: f32,
+    fft: Arc<dyn RealToComplex<f32>>,
+}
+
+pub struct Distortion {
+    // Output fundamental over input fundamental
+    pub gain: f64,
+    // Harmonics 2 to HIGHEST_HARMONIC together over the fundamental
+    pub thd: f64,
+    // Everything but DC and the fundamental over the fundamental
+    pub thd_n: f64,
+    // Each harmonic's level over the fundamental, starting at the 2nd
+    pub harmonics: Vec<f64>,
+}
+
+impl Analyzer {
+    pub fn new(size: usize, sample_rate: f32) -> Self {
+        Self {
+            size,
+            sample_rate,
+            fft: RealFftPlanner::<f32>::new().plan_fft_forward(size),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn frequency(&self, bin: usize) -> f32 {
+        bin as f32 * self.sample_rate / self.size as f32
+    }
+
+    // Bin closest to a frequency, odd so no two low harmonics can alias onto the same bin
+    pub fn tone_bin(&self, frequency: f32) -> usize {
+        let bin = (frequency * self.size as f32 / self.sample_rate).round() as usize;
+        (bin | 1).min(self.size / 2 - 1)
+    }
+
+    // Amplitude of every bin up to Nyquist
+    pub fn spectrum(&self, signal: &[f32]) -> Vec<f64> {
+        let mut input = signal.to_vec();
+        let mut output = self.fft.make_output_vec();
+        self.fft.process(&mut input, &mut output).unwrap();
+        let scale = 2.0 / self.size as f64;
+        output.iter().map(|bin| bin.norm() as f64 * scale).collect()
+    }
+
+    // Where a component at bin lands after aliasing
+    fn fold(&self, bin: usize) -> usize {
+        let bin = bin % self.size;
+        if bin > self.size / 2 {
+            self.size - bin
+        } else {
+            bin
+        }
+    }
+
+    // THD, THD+N and gain of a single tone at tone_bin with the given input amplitude
+    pub fn distortion(&self, output: &[f32], tone_bin: usize, input_amplitude: f64) -> Distortion {
+        let spectrum = self.spectrum(output);
+        let fundamental = spectrum[tone_bin];
+        let harmonics: Vec<f64> = (2..=HIGHEST_HARMONIC)
+            .map(|harmonic| spectrum[self.fold(harmonic * tone_bin)] / fundamental)
+            .collect();
+        let thd = harmonics.iter().map(|level| level * level).sum::<f64>().sqrt();
+        let rest: f64 = spectrum
+            .iter()
+            .enumerate()
+            .filter(|(bin, _)| *bin != 0 && *bin != tone_bin)
+            .map(|(_, level)| level * level)
+            .sum();
+        Distortion {
+            gain: fundamental / input_amplitude,
+            thd,
+            thd_n: rest.sqrt() / fundamental,
+            harmonics,
+        }
+    }
+
+    // SMPTE IMD: sidebands of the high tone made by the low tone, over the high tone
+    pub fn smpte(&self, output: &[f32], low_bin: usize, high_bin: usize) -> f64 {
+        let spectrum = self.spectrum(output);
+        let sidebands: f64 = (1..=SMPTE_SIDEBANDS)
+            .flat_map(|n| [high_bin - n * low_bin, high_bin + n * low_bin])
+            .map(|bin| spectrum[self.fold(bin)].powi(2))
+            .sum();
+        sidebands.sqrt() / spectrum[high_bin]
+    }
+
+    // CCIF twin tone IMD: the difference tone and the 3rd order products next to the tones, over
+    // the sum of the two tones
+    pub fn ccif(&self, output: &[f32], low_bin: usize, high_bin: usize) -> f64 {
+        let spectrum = self.spectrum(output);
+        let products: f64 = [high_bin - low_bin, 2 * low_bin - high_bin, 2 * high_bin - low_bin]
+            .iter()
+            .map(|bin| spectrum[self.fold(*bin)].powi(2))
+            .sum();
+        products.sqrt() / (spectrum[low_bin] + spectrum[high_bin])
+    }
+}
+
+pub fn to_db(ratio: f64) -> f64 {
+    20.0 * ratio.max(1e-12).log10()
+}
@@ -0,0 +1,212 @@
+// svg.rs - Ardura 2023
+// Bare bones line and bar charts written straight to SVG text
+
+use std::fmt::Write;
+
+const WIDTH: f32 = 900.0;
+const HEIGHT: f32 = 560.0;
+const LEFT: f32 = 70.0;
+const RIGHT: f32 = 220.0;
+const TOP: f32 = 50.0;
+// Room for the slanted bar labels
+const BOTTOM: f32 = 110.0;
+// The plugin's own colors first, then enough others to tell every mode apart
+const COLORS: [&str; 18] = [
+    "#219ebc", "#ffb703", "#fb8500", "#023047", "#8ecae6", "#e63946", "#2a9d8f", "#6a4c93", "#8ac926",
+    "#ff595e", "#1982c4", "#b5838d", "#606c38", "#bc6c25", "#3a0ca3", "#f72585", "#4d908e", "#577590",
+];
+
+pub struct Series {
+    pub name: String,
+    pub points: Vec<(f64, f64)>,
+}
+
+struct Frame {
+    svg: String,
+    x_range: (f64, f64),
+    y_range: (f64, f64),
+}
+
+impl Frame {
+    fn new(title: &str, x_label: &str, y_label: &str, x_range: (f64, f64), y_range: (f64, f64)) -> Self {
+        let mut svg = String::new();
+        let _ = write!(
+            svg,
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" font-family="sans-serif" font-size="12"><rect width="100%" height="100%" fill="white"/><text x="{}" y="28" font-size="16" text-anchor="middle">{}</text><text x="{}" y="{}" text-anchor="middle">{}</text><text transform="translate(18 {}) rotate(-90)" text-anchor="middle">{}</text>"##,
+            LEFT + (WIDTH - LEFT - RIGHT) / 2.0,
+            escape(title),
+            LEFT + (WIDTH - LEFT - RIGHT) / 2.0,
+            HEIGHT - 15.0,
+            escape(x_label),
+            TOP + (HEIGHT - TOP - BOTTOM) / 2.0,
+            escape(y_label),
+        );
+        let mut frame = Self { svg, x_range, y_range };
+        frame.grid();
+        frame
+    }
+
+    fn x(&self, value: f64) -> f32 {
+        let (low, high) = self.x_range;
+        LEFT + ((value - low) / (high - low)) as f32 * (WIDTH - LEFT - RIGHT)
+    }
+
+    fn y(&self, value: f64) -> f32 {
+        let (low, high) = self.y_range;
+        HEIGHT - BOTTOM - ((value.clamp(low, high) - low) / (high - low)) as f32 * (HEIGHT - TOP - BOTTOM)
+    }
+
+    // Horizontal lines at round steps with their values down the left
+    fn grid(&mut self) {
+        let (low, high) = self.y_range;
+        let step = nice_step(high - low);
+        let mut value = (low / step).ceil() * step;
+        while value <= high + step * 1e-6 {
+            let y = self.y(value);
+            let _ = write!(
+                self.svg,
+                r##"<line x1="{LEFT}" x2="{}" y1="{y}" y2="{y}" stroke="#ddd"/><text x="{}" y="{}" text-anchor="end">{}</text>"##,
+                WIDTH - RIGHT,
+                LEFT - 6.0,
+                y + 4.0,
+                format_tick(value),
+            );
+            value += step;
+        }
+        let _ = write!(
+            self.svg,
+            r##"<rect x="{LEFT}" y="{TOP}" width="{}" height="{}" fill="none" stroke="#333"/>"##,
+            WIDTH - LEFT - RIGHT,
+            HEIGHT - TOP - BOTTOM,
+        );
+    }
+
+    fn x_tick(&mut self, value: f64, label: &str) {
+        let x = self.x(value);
+        let _ = write!(
+            self.svg,
+            r##"<text x="{x}" y="{}" text-anchor="middle">{}</text>"##,
+            HEIGHT - BOTTOM + 18.0,
+            escape(label),
+        );
+    }
+
+    // Bar labels are slanted so long mode names don't run into each other
+    fn slanted_tick(&mut self, value: f64, label: &str) {
+        let x = self.x(value);
+        let y = HEIGHT - BOTTOM + 14.0;
+        let _ = write!(
+            self.svg,
+            r##"<text transform="translate({x} {y}) rotate(-40)" text-anchor="end">{}</text>"##,
+            escape(label),
+        );
+    }
+
+    fn legend(&mut self, index: usize, name: &str) {
+        let y = TOP + 10.0 + index as f32 * 18.0;
+        let x = WIDTH - RIGHT + 14.0;
+        let _ = write!(
+            self.svg,
+            r##"<rect x="{x}" y="{}" width="12" height="12" fill="{}"/><text x="{}" y="{}">{}</text>"##,
+            y - 10.0,
+            COLORS[index % COLORS.len()],
+            x + 18.0,
+            y,
+            escape(name),
+        );
+    }
+
+    fn finish(mut self) -> String {
+        self.svg.push_str("</svg>\n");
+        self.svg
+    }
+}
+
+// One line per series, the x axis labelled at the first series' points
+pub fn line_chart(title: &str, x_label: &str, y_label: &str, series: &[Series]) -> String {
+    let x_values = series.iter().flat_map(|s| s.points.iter().map(|p| p.0));
+    let y_values = series.iter().flat_map(|s| s.points.iter().map(|p| p.1));
+    let mut frame = Frame::new(title, x_label, y_label, range(x_values, false), range(y_values, true));
+    if let Some(first) = series.first() {
+        for (x, _) in first.points.iter() {
+            frame.x_tick(*x, &format_tick(*x));
+        }
+    }
+    for (index, line) in series.iter().enumerate() {
+        let points: Vec<String> = line
+            .points
+            .iter()
+            .filter(|(_, y)| y.is_finite())
+            .map(|(x, y)| format!("{:.1},{:.1}", frame.x(*x), frame.y(*y)))
+            .collect();
+        let _ = write!(
+            frame.svg,
+            r##"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"/>"##,
+            points.join(" "),
+            COLORS[index % COLORS.len()],
+        );
+        frame.legend(index, &line.name);
+    }
+    frame.finish()
+}
+
+// One bar per label rising from the bottom of the range, so lower levels in dB are shorter bars
+pub fn bar_chart(title: &str, y_label: &str, labels: &[String], values: &[f64]) -> String {
+    let y_range = range(values.iter().copied(), true);
+    let mut frame = Frame::new(title, "", y_label, (0.0, labels.len() as f64), y_range);
+    let width = (frame.x(1.0) - frame.x(0.0)) * 0.7;
+    for (index, (label, value)) in labels.iter().zip(values.iter()).enumerate() {
+        let center = index as f64 + 0.5;
+        frame.slanted_tick(center, label);
+        if !value.is_finite() {
+            continue;
+        }
+        let top = frame.y(*value);
+        let bottom = frame.y(y_range.0);
+        let _ = write!(
+            frame.svg,
+            r##"<rect x="{}" y="{top}" width="{width}" height="{}" fill="{}"/>"##,
+            frame.x(center) - width / 2.0,
+            (bottom - top).max(0.0),
+            COLORS[0],
+        );
+    }
+    frame.finish()
+}
+
+// Span of the values with some room around them, y ranges start at round numbers
+fn range(values: impl Iterator<Item = f64>, round: bool) -> (f64, f64) {
+    let (low, high) = values
+        .filter(|value| value.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), value| (low.min(value), high.max(value)));
+    if !low.is_finite() {
+        return (0.0, 1.0);
+    }
+    let (low, high) = if high - low < 1e-9 { (low - 1.0, high + 1.0) } else { (low, high) };
+    if !round {
+        return (low, high);
+    }
+    let step = nice_step(high - low);
+    ((low / step).floor() * step, (high / step).ceil() * step)
+}
+
+// A 1, 2 or 5 times power of ten step giving five to ten grid lines
+fn nice_step(span: f64) -> f64 {
+    let rough = span / 6.0;
+    let power = 10f64.powf(rough.log10().floor());
+    [1.0, 2.0, 5.0, 10.0].iter().map(|m| m * power).find(|step| *step >= rough).unwrap_or(power * 10.0)
+}
+
+fn format_tick(value: f64) -> String {
+    if value.abs() >= 1000.0 && value % 1000.0 == 0.0 {
+        format!("{}k", value / 1000.0)
+    } else if value.fract() == 0.0 {
+        format!("{value}")
+    } else {
+        format!("{value:.1}")
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,164 @@
+// bus_compressor.rs - Ardura 2023
+// A glue compressor that sits on the console path
+
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+use crate::util;
+use crate::fast_math::flush_denormal;
+
+modes! {
+    pub enum DetectorMode {
+        #[name = "Peak"]
+        PEAK,
+        #[name = "RMS"]
+        RMS,
+    }
+}
+
+// Averaging window of the RMS detector
+const RMS_WINDOW_MS: f32 = 10.0;
+// Time constant of the slow stage used by auto release
+const AUTO_RELEASE_SLOW_MS: f32 = 1500.0;
+
+pub struct BusCompressor {
+    detector: DetectorMode,
+    threshold_db: f32,
+    ratio: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    auto_release: bool,
+    knee_db: f32,
+    makeup_db: f32,
+    sample_rate: f32,
+    // The console is fed every channel one after the other, so our envelope updates this many times per sample period
+    channels: usize,
+    attack_coeff: f32,
+    release_coeff: f32,
+    rms_coeff: f32,
+    slow_coeff: f32,
+    rms_state: f32,
+    gain_reduction_db: f32,
+    slow_gain_reduction_db: f32,
+}
+
+impl BusCompressor {
+    pub fn new(ratio: f32, sample_rate: f32) -> Self {
+        let mut compressor = Self {
+            detector: DetectorMode::PEAK,
+            threshold_db: 0.0,
+            ratio: ratio.max(1.0),
+            attack_ms: 10.0,
+            release_ms: 100.0,
+            auto_release: false,
+            knee_db: 6.0,
+            makeup_db: 0.0,
+            sample_rate,
+            channels: 1,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            rms_coeff: 0.0,
+            slow_coeff: 0.0,
+            rms_state: 0.0,
+            gain_reduction_db: 0.0,
+            slow_gain_reduction_db: 0.0,
+        };
+        compressor.calculate_coefficients();
+        compressor
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_vals(
+        &mut self,
+        detector: DetectorMode,
+        threshold_db: f32,
+        ratio: f32,
+        attack_ms: f32,
+        release_ms: f32,
+        auto_release: bool,
+        knee_db: f32,
+        makeup_db: f32,
+        sample_rate: f32,
+        channels: usize,
+    ) {
+        self.detector = detector;
+        self.threshold_db = threshold_db;
+        self.ratio = ratio.max(1.0);
+        self.auto_release = auto_release;
+        self.knee_db = knee_db.max(0.0);
+        self.makeup_db = makeup_db;
+
+        // Only recalculate the time constants when something they depend on moved
+        let channels = channels.max(1);
+        if attack_ms != self.attack_ms || release_ms != self.release_ms || sample_rate != self.sample_rate || channels != self.channels {
+            self.attack_ms = attack_ms;
+            self.release_ms = release_ms;
+            self.sample_rate = sample_rate;
+            self.channels = channels;
+            self.calculate_coefficients();
+        }
+    }
+
+    // One pole coefficients for each time constant at our effective update rate
+    fn calculate_coefficients(&mut self) {
+        let update_rate = self.sample_rate * self.channels as f32;
+        let coeff = |ms: f32| -> f32 { (-1.0 / (ms.max(0.01) * 0.001 * update_rate)).exp() };
+        self.attack_coeff = coeff(self.attack_ms);
+        self.release_coeff = coeff(self.release_ms);
+        self.rms_coeff = coeff(RMS_WINDOW_MS);
+        self.slow_coeff = coeff(AUTO_RELEASE_SLOW_MS);
+    }
+
+    // Static gain computer with a soft knee, returns the wanted gain reduction in dB (positive)
+    fn gain_computer(&self, level_db: f32) -> f32 {
+        let over = level_db - self.threshold_db;
+        let slope = 1.0 - 1.0 / self.ratio;
+        if 2.0 * over < -self.knee_db {
+            0.0
+        } else if self.knee_db > 0.0 && 2.0 * over.abs() <= self.knee_db {
+            let knee_over = over + self.knee_db / 2.0;
+            slope * knee_over * knee_over / (2.0 * self.knee_db)
+        } else {
+            slope * over
+        }
+    }
+
+    // Release all gain reduction at once
+    pub fn reset(&mut self) {
+        self.rms_state = 0.0;
+        self.gain_reduction_db = 0.0;
+        self.slow_gain_reduction_db = 0.0;
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let level = match self.detector {
+            DetectorMode::PEAK => sample.abs(),
+            DetectorMode::RMS => {
+                self.rms_state = flush_denormal(self.rms_coeff * self.rms_state + (1.0 - self.rms_coeff) * sample * sample);
+                self.rms_state.sqrt()
+            }
+        };
+        let target = self.gain_computer(util::gain_to_db(level));
+
+        if target > self.gain_reduction_db {
+            self.gain_reduction_db = self.attack_coeff * self.gain_reduction_db + (1.0 - self.attack_coeff) * target;
+        } else if self.auto_release {
+            // Program dependent release: quickly fall back to the long term average gain reduction, then
+            // let that average take it the rest of the way so sustained material doesn't pump
+            let floor = target.max(self.slow_gain_reduction_db.min(self.gain_reduction_db));
+            self.gain_reduction_db = self.release_coeff * self.gain_reduction_db + (1.0 - self.release_coeff) * floor;
+        } else {
+            self.gain_reduction_db = self.release_coeff * self.gain_reduction_db + (1.0 - self.release_coeff) * target;
+        }
+        self.slow_gain_reduction_db = self.slow_coeff * self.slow_gain_reduction_db + (1.0 - self.slow_coeff) * target;
+        // Both settle towards 0dB in silence
+        self.gain_reduction_db = flush_denormal(self.gain_reduction_db);
+        self.slow_gain_reduction_db = flush_denormal(self.slow_gain_reduction_db);
+
+        sample * util::db_to_gain(self.makeup_db - self.gain_reduction_db)
+    }
+
+    // Current gain reduction in dB for metering
+    pub fn gain_reduction_db(&self) -> f32 {
+        self.gain_reduction_db
+    }
+}
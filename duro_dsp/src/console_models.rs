@@ -0,0 +1,402 @@
+// console_models.rs - Ardura 2023
+// Tapped delay line coefficients for each console model and the channel variance that perturbs them
+//  Each tap adds history[i] * (linear + nonlinear * |history[i]|) to the sample, signs are folded into the pair
+
+use core::f32::consts::PI;
+use core::sync::atomic::{fence, AtomicU32, Ordering};
+use atomic_float::AtomicF32;
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+use crate::modes::Mode;
+use crate::duro_process::ConsoleMode;
+
+// Longest tap set of any console model
+pub const MAX_TAPS: usize = 33;
+// Tap count of a generated console
+pub const GENERATED_TAPS: usize = 24;
+// Loudest any frequency of a generated console may get at unity drive, +6dB
+const GENERATED_MAX_GAIN: f32 = 2.0;
+// Difference between the lows and highs at full tilt
+const GENERATED_MAX_TILT_DB: f32 = 6.0;
+// Frequencies checked against the gain limit
+const GENERATED_RESPONSE_POINTS: usize = 512;
+// How many times the random part is shrunk to meet the gain limit before giving up on it
+const GENERATED_ATTEMPTS: usize = 24;
+
+// Airwindows inspired console jank creating some console model
+// Transfer function Direct form? - left rotated ascii num rage,43121,12257 then +- rand
+pub const DURO_TAPS: [(f32, f32); 11] = [
+    (0.12463,  0.0009082),
+    (-0.24631, -0.0007892),
+    (0.46312,  -0.0004984),
+    (0.63124,  0.0008833),
+    (-0.31246, -0.0007061),
+    (0.43121,  -0.0003605),
+    (-0.31214, -0.0008056),
+    (0.12143,  0.0006117),
+    (-0.21431, -0.0005775),
+    (0.14312,  0.0002237),
+    (-0.12257, -0.0005422),
+];
+
+// Airwindows inspired console jank creating random console
+pub const LEAF_TAPS: [(f32, f32); 19] = [
+    (0.20641,   -0.0007895),
+    (-0.34072,  -0.0004034),
+    (0.43302,   -0.0003548),
+    (0.14097,   -0.0003409),
+    (-0.00658,  -0.0003328),
+    (0.58875,   -0.0003155),
+    (-0.28183,  -0.0003045),
+    (0.00555,   -0.0003044),
+    (-0.024370, -0.0003031),
+    (0.00401,   -0.0003029),
+    (-0.01781,  -0.0004679),
+    (0.03884,   -0.0003539),
+    (0.00221,   0.0008839),
+    (0.00451,   -0.0009749),
+    (0.03501,   -0.0003122),
+    (0.06568,   -0.0002257),
+    (0.05355,   -0.0009112),
+    (0.00522,   -0.0001911),
+    (0.03569,   -0.0001945),
+];
+
+// Vine console - Ardura created Sound
+// Tables from here on keep the digits they were measured with, f32 rounds them when it compiles
+#[allow(clippy::excessive_precision)]
+pub const VINE_TAPS: [(f32, f32); 20] = [
+    (0.0436325893992795,  -0.000575411073043639),
+    (-0.0398664344439780, -0.000401805174100580),
+    (0.0423995851137356,  -0.000397649382328122),
+    (0.0510140498581183,  -0.000353184194832542),
+    (-0.0769342576758843, -0.000291725137291541),
+    (0.0401007008556487,  -0.000215534790074162),
+    (-0.0373217915258955, -0.000270605983316673),
+    (0.0287532033895229,  -0.000346928125413722),
+    (-0.0342323841982068, -0.000481560773128127),
+    (0.0324797624174322,  -0.000259173731735134),
+    (-0.0444824631252694, -0.000476415778491772),
+    (0.0238301394147183,  -0.000361592576652208),
+    (0.0232701919791822,  0.000352466711624552),
+    (0.0375055553818633,  -0.000326762074484403),
+    (0.0237069785097524,  -0.000251524844696344),
+    (0.0448529936846194,  -0.000257515409563439),
+    (0.0355616566388069,  -0.000249453764586831),
+    (0.0248772252552266,  -0.000323542566487897),
+    (0.0212456446513081,  -0.000232976462487141),
+    (0.0172523541136474,  -0.000399899365529506),
+];
+
+#[allow(clippy::excessive_precision)]
+pub const NEVE_TAPS: [(f32, f32); 33] = [
+    (0.20641602693167951,  -0.00078952185394898),
+    (-0.07601816702459827, -0.00022786334179951),
+    (0.03929765560019285,  -0.00054517993246352),
+    (0.00298333157711103,  -0.00033083756545638),
+    (-0.00724006282304610, -0.00045483683460812),
+    (0.03073108963506036,  -0.00038190060537423),
+    (-0.02332434692533051, -0.00040347288688932),
+    (0.03792606869061214,  -0.00039673687335892),
+    (-0.02437059376675688, -0.00037221210539535),
+    (0.03416764311979521,  -0.00040314850796953),
+    (-0.01761669868102127, -0.00035989484330131),
+    (0.02538237753523052,  -0.00040149119125394),
+    (-0.00770737340728377, -0.00035462118723555),
+    (0.01580706228482803,  -0.00037563141307594),
+    (0.00055119240005586,  -0.00035409299268971),
+    (0.00818552143438768,  -0.00036507661042180),
+    (0.00661842703548304,  -0.00034550528559056),
+    (0.00362447476272098,  -0.00035553012761240),
+    (0.00957098027225745,  -0.00034091691045338),
+    (0.00193621774016660,  -0.00034554529131668),
+    (0.01005433027357935,  -0.00033878223153845),
+    (0.00221712428802004,  -0.00033481410137711),
+    (0.00911255639207995,  -0.00033263425232666),
+    (0.00339667169034909,  -0.00032634428038430),
+    (0.00774096948249924,  -0.00032599868802996),
+    (0.00463907626773794,  -0.00032131993173361),
+    (0.00658222997260378,  -0.00032014977430211),
+    (0.00550347079924993,  -0.00031557153256653),
+    (0.00588754981375325,  -0.00032041307242303),
+    (0.00590293898419892,  -0.00030457857428714),
+    (0.00558952010441800,  -0.00030448053548086),
+    (0.00598183557634295,  -0.00030715064323181),
+    (0.00555223929714115,  -0.00030319367948553),
+];
+
+#[allow(clippy::excessive_precision)]
+pub const API_TAPS: [(f32, f32); 33] = [
+    (0.09299870608542582,  -0.00009582362368873),
+    (-0.11947847710741009, 0.00004500891602770),
+    (0.09071606264761795,  0.00005639498984741),
+    (-0.08561982770836980, 0.00004964855606916),
+    (0.06440549220820363,  0.00002428052139507),
+    (-0.05987991812840746, -0.00000101867082290),
+    (0.03980233135839382,  0.00003312430049041),
+    (-0.03648402630896925, 0.00002116186381142),
+    (0.01826860869525248,  0.00003115110025396),
+    (-0.01723968622495364, 0.00002450634121718),
+    (0.00187588812316724,  0.00002838206198968),
+    (-0.00381796423957237, 0.00003155815499462),
+    (-0.00852092214496733, 0.00001702651162392),
+    (0.00315560292270588,  0.00002547861676047),
+    (-0.01258630914496868, 0.00004555319243213),
+    (0.00536435648963575,  0.00001812393657101),
+    (-0.01272975658159178, 0.00004103775306121),
+    (0.00403818975172755,  0.00003764615492871),
+    (-0.01042617366897483, 0.00003605210426041),
+    (0.00126599583390057,  0.00004305458668852),
+    (-0.00747876207688339, 0.00003731207018977),
+    (-0.00149873689175324, 0.00005086601800791),
+    (-0.00503221309488033, 0.00003636086782783),
+    (-0.00342998224655821, 0.00004103091180506),
+    (-0.00355585977903117, 0.00003698982145400),
+    (-0.00437201792934817, 0.00002720235666939),
+    (-0.00299217874451556, 0.00004446954727956),
+    (-0.00457924652487249, 0.00003859065778860),
+    (-0.00298182934892027, 0.00002064710931733),
+    (-0.00438838441540584, 0.00005223008424866),
+    (-0.00323984218794705, 0.00003397987535887),
+    (-0.00407693981307314, 0.00003935772436894),
+    (-0.00350435348467321, 0.00005525463935338),
+];
+
+#[allow(clippy::excessive_precision)]
+pub const PRECISION_TAPS: [(f32, f32); 33] = [
+    (0.59188440274551890,  -0.00008361469668405),
+    (-0.24439750948076133, -0.00002651678396848),
+    (0.14109876103205621,  -0.00000840487181372),
+    (-0.10053507128157971, -0.00001768100964598),
+    (0.05859287880626238,  -0.00000361398065989),
+    (-0.04337406889823660, -0.00000735941182117),
+    (0.01589900680531097,  0.00000207347387987),
+    (-0.01087234854973281, -0.00000732123412029),
+    (-0.00845782429679176, 0.00000133058605071),
+    (0.00662278586618295,  -0.00000424594730611),
+    (-0.02000592193760155, -0.00000632896879068),
+    (0.01321157777167565,  -0.00001421171592570),
+    (-0.02249955362988238, -0.00000163937127317),
+    (0.01196492077581504,  -0.00000535385220676),
+    (-0.01905917427000097, -0.00000121672882030),
+    (0.00761909482108073,  -0.00000326242895115),
+    (-0.01362744780256239, -0.00000359274216003),
+    (0.00200183122683721,  -0.00000089207452791),
+    (-0.00833042637239315, -0.00000946767677294),
+    (-0.00258481175207224, 0.00000087429351464),
+    (-0.00459744479712244, 0.00000049519758701),
+    (-0.00534277030993820, -0.00000397547847155),
+    (-0.00272332919605675, -0.00000040077229097),
+    (-0.00637243782359372, 0.00000139419072176),
+    (-0.00233001590327504, -0.00000420129915747),
+    (-0.00623296727793041, -0.00000019010664856),
+    (-0.00276177096376805, -0.00000580301901385),
+    (-0.00559184754866264, -0.00000080597287792),
+    (-0.00343180144395919, 0.00000243701142085),
+    (-0.00493325428861701, -0.00000300985740900),
+    (-0.00396140827680823, 0.00000051459681789),
+    (-0.00448497879902493, -0.00000744412841743),
+    (-0.00425146888772076, 0.00000082346016542),
+];
+
+// Base coefficients of a console model, bypass has none
+pub fn base_taps(console_type: ConsoleMode) -> &'static [(f32, f32)] {
+    match console_type {
+        ConsoleMode::BYPASS => &[],
+        ConsoleMode::NEVE => &NEVE_TAPS,
+        ConsoleMode::API => &API_TAPS,
+        ConsoleMode::PRECISION => &PRECISION_TAPS,
+        ConsoleMode::LEAF => &LEAF_TAPS,
+        ConsoleMode::VINE => &VINE_TAPS,
+        ConsoleMode::DURO => &DURO_TAPS,
+        // Built from the seed at runtime
+        ConsoleMode::GENERATED => &[],
+        // Convolved with the loaded impulse responses instead
+        ConsoleMode::CONVOLUTION => &[],
+        ConsoleMode::DYNAMIC => &[],
+    }
+}
+
+// Shift the history along and put the new driven sample at the front
+pub fn push_history(history: &mut [f32], driven_sample: f32) {
+    history.copy_within(0..history.len() - 1, 1);
+    history[0] = driven_sample;
+}
+
+// Sum every tap against the history. The nonlinear part only grows with the level past the threshold,
+// a threshold of 0 is the console as it always was
+pub fn sum_taps(history: &[f32], taps: &[(f32, f32)], sample: f32, threshold: f32) -> f32 {
+    let mut temp_sample = sample;
+    for (tap, (linear, nonlinear)) in history[1..].iter().zip(taps.iter()) {
+        temp_sample += tap * (linear + nonlinear * (tap.abs() - threshold).max(0.0));
+    }
+    temp_sample
+}
+
+// Small splitmix64 generator so a channel number always gives the same voicing on every machine
+pub struct ToleranceRng {
+    state: u64,
+}
+
+impl ToleranceRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform between -1 and 1
+    pub fn bipolar(&mut self) -> f32 {
+        ((self.next_u64() >> 40) as f32 / (1u64 << 24) as f32) * 2.0 - 1.0
+    }
+}
+
+// Seed for one channel of one console model on a given channel number
+pub fn variance_seed(console_type: ConsoleMode, channel_number: i32, channel: usize) -> u64 {
+    ((channel_number as u64) << 32) ^ ((channel as u64) << 8) ^ console_type.to_index() as u64
+}
+
+// Copy the base taps into taps, each coefficient scaled by its own random tolerance
+pub fn vary_taps(taps: &mut [(f32, f32)], base: &[(f32, f32)], tolerance: f32, rng: &mut ToleranceRng) {
+    for (tap, (linear, nonlinear)) in taps.iter_mut().zip(base.iter()) {
+        *tap = (
+            linear * (1.0 + tolerance * rng.bipolar()),
+            nonlinear * (1.0 + tolerance * rng.bipolar()),
+        );
+    }
+}
+
+// Build a console the way Vine and Duro were made: random taps that fade out over the line, then
+// nudged so the lows and highs sit at the wanted tilt and no frequency gets louder than +6dB
+pub fn generate_taps(seed: i32, tilt: f32) -> [(f32, f32); GENERATED_TAPS] {
+    let mut rng = ToleranceRng::new(seed as u32 as u64);
+
+    // Random part of each tap with an exponential fade, and its nonlinear term in the range of the other consoles
+    let fade = 4.0 + 8.0 * (rng.bipolar() * 0.5 + 0.5);
+    let mut envelope = [0.0; GENERATED_TAPS];
+    let mut random = [0.0; GENERATED_TAPS];
+    let mut nonlinear = [0.0; GENERATED_TAPS];
+    for i in 0..GENERATED_TAPS {
+        envelope[i] = (-(i as f32) / fade).exp();
+        random[i] = 0.6 * envelope[i] * rng.bipolar();
+        nonlinear[i] = 0.001 * envelope[i] * rng.bipolar();
+    }
+
+    // The gain at DC is 1 + even + odd and at Nyquist 1 + even - odd, where even and odd sum the taps at history[2], history[4]...
+    // and history[1], history[3]... Spread the gain the tilt wants evenly in dB around unity
+    let tilt_db = tilt.clamp(-1.0, 1.0) * GENERATED_MAX_TILT_DB;
+    let low_gain = 10.0_f32.powf(-tilt_db / 40.0);
+    let high_gain = 10.0_f32.powf(tilt_db / 40.0);
+    let target_even = (low_gain + high_gain) / 2.0 - 1.0;
+    let target_odd = (low_gain - high_gain) / 2.0;
+    let even_weight: f32 = envelope.iter().skip(1).step_by(2).sum();
+    let odd_weight: f32 = envelope.iter().step_by(2).sum();
+
+    let mut taps = [(0.0, 0.0); GENERATED_TAPS];
+    let mut scale = 1.0;
+    for _ in 0..GENERATED_ATTEMPTS {
+        let mut linear = [0.0; GENERATED_TAPS];
+        let (mut even, mut odd) = (0.0, 0.0);
+        for i in 0..GENERATED_TAPS {
+            linear[i] = random[i] * scale;
+            // taps[i] reads history[i + 1]
+            if i % 2 == 1 { even += linear[i] } else { odd += linear[i] }
+        }
+        for i in 0..GENERATED_TAPS {
+            linear[i] += if i % 2 == 1 {
+                (target_even - even) * envelope[i] / even_weight
+            } else {
+                (target_odd - odd) * envelope[i] / odd_weight
+            };
+            taps[i] = (linear[i], nonlinear[i]);
+        }
+
+        if peak_gain(&linear) <= GENERATED_MAX_GAIN {
+            return taps;
+        }
+        scale *= 0.8;
+    }
+
+    // Only the tilt is left, that alone stays well under the limit
+    for (i, tap) in taps.iter_mut().enumerate() {
+        let tilt_part = if i % 2 == 1 { target_even * envelope[i] / even_weight } else { target_odd * envelope[i] / odd_weight };
+        *tap = (tilt_part, nonlinear[i]);
+    }
+    taps
+}
+
+// Loudest point of the linear response 1 + sum(linear[i] * z^-(i + 1)) between DC and Nyquist
+fn peak_gain(linear: &[f32]) -> f32 {
+    let mut peak: f32 = 0.0;
+    for point in 0..=GENERATED_RESPONSE_POINTS {
+        let omega = PI * point as f32 / GENERATED_RESPONSE_POINTS as f32;
+        // Step a phasor along the taps rather than calling cos and sin for every one
+        let (step_real, step_imaginary) = (omega.cos(), -omega.sin());
+        let (mut rotation_real, mut rotation_imaginary) = (step_real, step_imaginary);
+        let (mut real, mut imaginary) = (1.0, 0.0);
+        for coefficient in linear.iter() {
+            real += coefficient * rotation_real;
+            imaginary += coefficient * rotation_imaginary;
+            (rotation_real, rotation_imaginary) = (
+                rotation_real * step_real - rotation_imaginary * step_imaginary,
+                rotation_real * step_imaginary + rotation_imaginary * step_real,
+            );
+        }
+        peak = peak.max((real * real + imaginary * imaginary).sqrt());
+    }
+    peak
+}
+
+// Generated taps shared from the background thread to the audio thread without locks, same versioning
+// as the custom curve table. generate_taps is far too slow for the audio thread so it is only ever run here
+pub struct SharedTaps {
+    version: AtomicU32,
+    taps: [(AtomicF32, AtomicF32); GENERATED_TAPS],
+}
+
+impl SharedTaps {
+    pub fn new(taps: &[(f32, f32); GENERATED_TAPS]) -> Self {
+        Self {
+            version: AtomicU32::new(2),
+            taps: core::array::from_fn(|i| (AtomicF32::new(taps[i].0), AtomicF32::new(taps[i].1))),
+        }
+    }
+
+    // Only call this from one thread at a time, the plugin holds a lock around it
+    pub fn write(&self, taps: &[(f32, f32); GENERATED_TAPS]) {
+        let version = self.version.load(Ordering::Relaxed);
+        self.version.store(version.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        for ((linear, nonlinear), (new_linear, new_nonlinear)) in self.taps.iter().zip(taps.iter()) {
+            linear.store(*new_linear, Ordering::Relaxed);
+            nonlinear.store(*new_nonlinear, Ordering::Relaxed);
+        }
+        self.version.store(version.wrapping_add(2), Ordering::Release);
+    }
+
+    // Copy the taps into the audio thread's own copy if they changed, returns true when they did.
+    // A torn read is retried next time
+    pub fn read_into(&self, taps: &mut [(f32, f32); GENERATED_TAPS], version: &mut u32) -> bool {
+        let start = self.version.load(Ordering::Acquire);
+        if start == *version || start % 2 == 1 {
+            return false;
+        }
+        let mut copy = [(0.0, 0.0); GENERATED_TAPS];
+        for (tap, (linear, nonlinear)) in copy.iter_mut().zip(self.taps.iter()) {
+            *tap = (linear.load(Ordering::Relaxed), nonlinear.load(Ordering::Relaxed));
+        }
+        fence(Ordering::Acquire);
+        if self.version.load(Ordering::Relaxed) != start {
+            return false;
+        }
+        *taps = copy;
+        *version = start;
+        true
+    }
+}
@@ -0,0 +1,92 @@
+// crossfade.rs - Ardura 2023
+// Equal power crossfade between the old and new value of a mode parameter so switching doesn't click
+
+use core::f32::consts::PI;
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+
+// How long a switch takes
+pub const CROSSFADE_MS: f32 = 20.0;
+
+pub struct Crossfade<T: Copy + PartialEq> {
+    current: T,
+    previous: T,
+    position: usize,
+    length: usize,
+}
+
+impl<T: Copy + PartialEq> Crossfade<T> {
+    pub fn new(initial: T, sample_rate: f32) -> Self {
+        let length = Self::length_for(sample_rate);
+        Self {
+            current: initial,
+            previous: initial,
+            position: length,
+            length,
+        }
+    }
+
+    fn length_for(sample_rate: f32) -> usize {
+        ((CROSSFADE_MS * 0.001 * sample_rate) as usize).max(1)
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.length = Self::length_for(sample_rate);
+        self.position = self.position.min(self.length);
+    }
+
+    // Start fading when the target moved, returns true when current changed. Restarting a running fade
+    // would drop whatever the previous value still adds, so a new target waits for the fade to land
+    // (update is called with it again every sample). Going back to the previous value turns the fade
+    // around where it is instead, the gains swap so the mix carries on from the same point
+    pub fn update(&mut self, target: T) -> bool {
+        if target == self.current {
+            return false;
+        }
+        if self.is_fading() {
+            if target != self.previous {
+                return false;
+            }
+            self.previous = self.current;
+            self.current = target;
+            self.position = self.length - self.position;
+            return true;
+        }
+        self.previous = self.current;
+        self.current = target;
+        self.position = 0;
+        true
+    }
+
+    // Skip to the end of any fade in progress
+    pub fn finish(&mut self) {
+        self.previous = self.current;
+        self.position = self.length;
+    }
+
+    // Move one sample frame along the fade
+    pub fn advance(&mut self) {
+        if self.position < self.length {
+            self.position += 1;
+        }
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.position < self.length
+    }
+
+    pub fn previous(&self) -> T {
+        self.previous
+    }
+
+    // What is being faded to, which lags the target while an earlier fade finishes
+    pub fn current(&self) -> T {
+        self.current
+    }
+
+    // Gains for the previous and current value, their squares always sum to 1
+    pub fn gains(&self) -> (f32, f32) {
+        let angle = self.position as f32 / self.length as f32 * PI / 2.0;
+        (angle.cos(), angle.sin())
+    }
+}
@@ -0,0 +1,145 @@
+// custom_curve.rs - Ardura 2023
+// User drawn transfer curve: a spline through the user's points, baked into a lookup table the audio thread reads
+
+use core::sync::atomic::{fence, AtomicU32, Ordering};
+use atomic_float::AtomicF32;
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+// Table entries across the -1 to 1 input range, odd so 0 lands on an entry
+pub const CURVE_TABLE_SIZE: usize = 1025;
+pub const MAX_CURVE_POINTS: usize = 16;
+
+pub type CurveTable = [f32; CURVE_TABLE_SIZE];
+
+// A gentle S curve to start from, points are (input, output) and sorted by input
+pub const DEFAULT_POINTS: [(f32, f32); 5] = [(-1.0, -0.9), (-0.5, -0.6), (0.0, 0.0), (0.5, 0.6), (1.0, 0.9)];
+
+#[cfg(feature = "alloc")]
+pub fn default_points() -> Vec<(f32, f32)> {
+    DEFAULT_POINTS.to_vec()
+}
+
+// Keep the points usable whatever was loaded: sorted, in range, the ends pinned to -1 and 1
+#[cfg(feature = "alloc")]
+pub fn sanitize_points(points: &mut Vec<(f32, f32)>) {
+    points.retain(|(x, y)| x.is_finite() && y.is_finite());
+    for point in points.iter_mut() {
+        point.0 = point.0.clamp(-1.0, 1.0);
+        point.1 = point.1.clamp(-1.0, 1.0);
+    }
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    points.dedup_by(|a, b| a.0 == b.0);
+    points.truncate(MAX_CURVE_POINTS);
+    if points.len() < 2 {
+        *points = default_points();
+        return;
+    }
+    points.first_mut().unwrap().0 = -1.0;
+    points.last_mut().unwrap().0 = 1.0;
+}
+
+// Monotone cubic (Fritsch-Carlson) spline so the curve never overshoots between points
+pub fn build_table(points: &[(f32, f32)]) -> CurveTable {
+    let count = points.len();
+    let mut slopes = [0.0; MAX_CURVE_POINTS];
+    let mut tangents = [0.0; MAX_CURVE_POINTS];
+    for i in 0..count - 1 {
+        slopes[i] = (points[i + 1].1 - points[i].1) / (points[i + 1].0 - points[i].0);
+    }
+    tangents[0] = slopes[0];
+    tangents[count - 1] = slopes[count - 2];
+    for i in 1..count - 1 {
+        tangents[i] = if slopes[i - 1] * slopes[i] <= 0.0 { 0.0 } else { (slopes[i - 1] + slopes[i]) / 2.0 };
+    }
+    for i in 0..count - 1 {
+        if slopes[i] == 0.0 {
+            tangents[i] = 0.0;
+            tangents[i + 1] = 0.0;
+            continue;
+        }
+        let alpha = tangents[i] / slopes[i];
+        let beta = tangents[i + 1] / slopes[i];
+        let length = alpha * alpha + beta * beta;
+        if length > 9.0 {
+            let scale = 3.0 / length.sqrt();
+            tangents[i] = scale * alpha * slopes[i];
+            tangents[i + 1] = scale * beta * slopes[i];
+        }
+    }
+
+    let mut table = [0.0; CURVE_TABLE_SIZE];
+    let mut segment = 0;
+    for (i, entry) in table.iter_mut().enumerate() {
+        let x = i as f32 / (CURVE_TABLE_SIZE - 1) as f32 * 2.0 - 1.0;
+        while segment < count - 2 && x > points[segment + 1].0 {
+            segment += 1;
+        }
+        let (x0, y0) = points[segment];
+        let (x1, y1) = points[segment + 1];
+        let width = x1 - x0;
+        let t = ((x - x0) / width).clamp(0.0, 1.0);
+        let t2 = t * t;
+        let t3 = t2 * t;
+        *entry = (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * width * tangents[segment]
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * width * tangents[segment + 1];
+    }
+    table
+}
+
+// Read the table with linear interpolation, inputs past -1 and 1 hold the end values
+pub fn lookup(table: &CurveTable, sample: f32) -> f32 {
+    let position = (sample.clamp(-1.0, 1.0) + 1.0) * 0.5 * (CURVE_TABLE_SIZE - 1) as f32;
+    let index = (position as usize).min(CURVE_TABLE_SIZE - 2);
+    let fraction = position - index as f32;
+    table[index] + (table[index + 1] - table[index]) * fraction
+}
+
+// Table shared from the GUI to the audio thread without locks. The version is odd while a write is
+// in progress, so the reader only takes a copy that was read between two equal even versions
+pub struct SharedCurve {
+    version: AtomicU32,
+    table: [AtomicF32; CURVE_TABLE_SIZE],
+}
+
+impl SharedCurve {
+    pub fn new(points: &[(f32, f32)]) -> Self {
+        let table = build_table(points);
+        Self {
+            version: AtomicU32::new(2),
+            table: core::array::from_fn(|i| AtomicF32::new(table[i])),
+        }
+    }
+
+    // Only call this while holding the points' write lock so there is a single writer
+    pub fn write(&self, table: &CurveTable) {
+        let version = self.version.load(Ordering::Relaxed);
+        self.version.store(version.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        for (shared, value) in self.table.iter().zip(table.iter()) {
+            shared.store(*value, Ordering::Relaxed);
+        }
+        self.version.store(version.wrapping_add(2), Ordering::Release);
+    }
+
+    // Copy the table into the audio thread's own copy if it changed, a torn read is retried next time
+    pub fn read_into(&self, table: &mut CurveTable, version: &mut u32) {
+        let start = self.version.load(Ordering::Acquire);
+        if start == *version || start % 2 == 1 {
+            return;
+        }
+        let mut copy = [0.0; CURVE_TABLE_SIZE];
+        for (value, shared) in copy.iter_mut().zip(self.table.iter()) {
+            *value = shared.load(Ordering::Relaxed);
+        }
+        fence(Ordering::Acquire);
+        if self.version.load(Ordering::Relaxed) == start {
+            *table = copy;
+            *version = start;
+        }
+    }
+}
@@ -0,0 +1,784 @@
+use core::f32::consts::PI;
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+use crate::util;
+use crate::bus_compressor::{BusCompressor, DetectorMode};
+use crate::lofi::{self, AntiAliasMode, Decimator, Dither};
+use crate::console_models::{self, SharedTaps, ToleranceRng, GENERATED_TAPS, MAX_TAPS};
+use crate::crossfade::Crossfade;
+use crate::custom_curve::{self, CurveTable, SharedCurve};
+use crate::formula::{Program, SharedFormula};
+use crate::fast_math;
+use crate::response::{Response, Silence};
+use crate::harmonics::{Cycle, HarmonicGenerator, HarmonicMask, NUM_HARMONICS};
+
+// The plugin runs in mono or stereo, stateful saturation keeps this many channels of history
+pub const MAX_CHANNELS: usize = 2;
+// Corner of the filter that removes the DC the bias makes the saturation stage add
+const DC_BLOCK_HZ: f32 = 10.0;
+// Full scale in volts used to turn a slew rate into a step per sample. Far hotter than a real console so
+// that even fast op-amps run out of slew in the audio band: a full scale sine starts to limit around
+// 800Hz at a 741's 0.5 V/us and around 20kHz at an NE5534's 13 V/us
+const SLEW_FULL_SCALE_VOLTS: f32 = 100.0;
+// Largest amount past the threshold the transformer curve works with, in multiples of the headroom
+const TRANSFORMER_MAX_REDUCTION: f32 = 1.0e6;
+
+modes! {
+    pub enum ConsoleMode {
+        #[name = "Bypass"]
+        BYPASS,
+        #[name = "Neve Inspired"]
+        NEVE,
+        #[name = "API Inspired"]
+        API,
+        #[name = "Precision Inspired"]
+        PRECISION,
+        #[name = "Leaf Console"]
+        LEAF,
+        #[name = "Vine Console"]
+        VINE,
+        #[name = "Duro Console"]
+        DURO,
+        #[name = "Generated Console"]
+        GENERATED,
+        #[name = "Impulse Response"]
+        CONVOLUTION,
+        #[name = "Dynamic Response"]
+        DYNAMIC,
+    }
+}
+
+modes! {
+    pub enum SaturationModeEnum {
+        #[name = "No Saturation"]
+        NONESAT,
+        #[name = "Tape Saturation"]
+        TAPESAT,
+        #[name = "Candle"]
+        CANDLE,
+        #[name = "Chebyshev"]
+        CHEBYSHEV,
+        #[name = "\"Leaf\""]
+        LEAF,
+        #[name = "Digital Clip"]
+        DIGITAL,
+        #[name = "Golden Cubic"]
+        GOLDENCUBIC,
+        #[name = "Transformer"]
+        TRANSFORMER,
+        #[name = "Odd Harmonics"]
+        ODDHARMONICS,
+        #[name = "Fourth Harmonics"]
+        FORTHHARM,
+        #[name = "Slew Limit"]
+        SLEW,
+        #[name = "Bitcrush"]
+        BITCRUSH,
+        #[name = "Sample Rate Reduce"]
+        DECIMATE,
+        #[name = "Mu-Law"]
+        MULAW,
+        #[name = "A-Law"]
+        ALAW,
+        #[name = "Harmonic Generator"]
+        HARMONICS,
+        #[name = "Custom Curve"]
+        CUSTOMCURVE,
+        #[name = "Formula"]
+        FORMULA,
+    }
+}
+
+/**************************************************
+ * EQ Filter Algorithm
+ **************************************************/
+
+
+// Storing how I created the array of coefficients - not used in program
+#[allow(dead_code)]
+#[cfg(feature = "alloc")]
+pub fn gen_coefficients(boost_db: f32, sr: f32, bands: alloc::vec::Vec<f32>) -> alloc::vec::Vec<(f32, f32, f32, f32, f32)> {
+    // How many bands to split the signal into
+    //let bands: Vec<f32> = vec![280.0,800.0];
+    let mut eq_coefficients: alloc::vec::Vec<(f32, f32, f32, f32, f32)> = alloc::vec::Vec::new();
+
+    for band in bands {
+        let center_freq = band;
+        let omega = 2.0 * PI * center_freq / sr;
+        let alpha = 0.0;
+        let cos_omega = omega.cos();
+        let a0 = 1.0 + alpha / boost_db;
+        let a1 = -2.0 * cos_omega;
+        let a2 = 1.0 - alpha / boost_db;
+        let b0 = (1.0 + cos_omega) / 2.0 / boost_db;
+        let b1 = -(1.0 + cos_omega) / boost_db;
+        let b2 = (1.0 + cos_omega) / 2.0 / boost_db;
+        eq_coefficients.push((b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0));
+    }
+    eq_coefficients
+    //println!("NEW COEFFICIENTS");
+    //println!("{:?}", eq_coefficients);
+}
+
+/**************************************************
+ * Saturation Algorithms
+ **************************************************/
+
+// tape saturation using transfer function
+// Output stays within -12dB of full scale
+pub fn tape_saturation(input_signal: f32, drive: f32, threshold: f32, fast: bool) -> f32 {
+    let tanh = if fast { fast_math::tanh } else { f32::tanh };
+    let idrive = if drive == 0.0 {0.0001} else {drive};
+    // Never divide by 0 even with a threshold of 0, the soft clip below takes the huge gain that leaves
+    let normalizer = tanh(threshold * idrive).max(f32::MIN_POSITIVE);
+    // Define the transfer curve for the tape saturation effect
+    let transfer = |x: f32| -> f32 {
+        tanh(x * idrive) / normalizer
+    };
+    // Apply the transfer curve to the input sample
+    let output_sample = transfer(input_signal);
+    // soft clip the output
+    let mut normalized_output_sample = output_sample / (1.0 + output_sample.abs());
+    // Lower this signal because it is LOUDER than the original
+    normalized_output_sample *= util::db_to_gain(-12.0);
+    normalized_output_sample
+}
+
+// Output stays within the input level plus twice the threshold
+pub fn digital_saturation(sample: f32, threshold: f32, drive: f32) -> f32 
+{
+    let clipped = if sample.abs() > threshold {
+        sample.signum() * threshold // Clip the signal if it exceeds the threshold
+    } else {
+        sample
+    };
+    sample * (1.0 - drive) + clipped * drive // Mix original signal with clipped signal
+}
+
+// Chebyshev polynomial saturation (Thanks to AI help)
+// Output stays within full scale
+pub fn chebyshev_tape(sample: f32, threshold: f32, drive: f32) -> f32 {
+    // saturation limit value
+    let k = if sample.abs() > threshold {
+        threshold / sample.abs()
+    } else {
+        1.0
+    };
+    // normalized input
+    let x = sample * k / (1.0 + drive);
+    // Calculate the Chebyshev values
+    let x2 = x * x;
+    let x3 = x * x2;
+    let x5 = x3 * x2;
+    let x6 = x3 * x3;
+    let y = x
+        - 0.166667 * x3
+        + 0.00833333 * x5
+        - 0.000198413 * x6;
+    y / (1.0 + y.abs()) // Soft clip output
+}
+
+// Golden ratio based saturation with cubic curve
+// Output grows with the cube of the input above the threshold, then is lowered 3dB
+pub fn golden_cubic(sample: f32, threshold: f32, drive: f32, fast: bool) -> f32 
+{
+    let exp = if fast { fast_math::exp } else { f32::exp };
+    let golden_ratio = 1.618034;
+    let abs_input = sample.abs();
+    // If we are above the threshold, multiply by the golden and cube the excess sample
+    let output = if abs_input > threshold {
+        let sign = sample.signum();
+        let excess = abs_input - threshold;
+        let shaped_excess = threshold * golden_ratio * excess.powi(3); // apply cubic function multiplied by golden ratio
+        sign * (threshold + shaped_excess)
+    } else {
+        sample
+    };
+    // Apply soft clip to the output
+    
+    let sc_threshold = 1.0 - drive - 0.0001;
+    let sign = output.signum();
+    // sc_threshold / (1 - e^-sc_threshold) goes to 1 as the drive reaches 99.99%, where dividing first
+    // would be 0 / 0
+    let sc_scale = if sc_threshold.abs() < 1e-4 {
+        1.0 + sc_threshold / 2.0
+    } else {
+        sc_threshold / (1.0 - exp(-sc_threshold))
+    };
+    let clipped = 1.0 - exp(-output.abs());
+    let mut temp = output + sign * clipped * sc_scale;
+
+    // Lower a pinch because it is louder than the original
+    temp *= util::db_to_gain(-3.0);
+    temp
+}
+
+// Add soft compressed candle saturation idea to signal
+// Output stays within the input level plus full scale
+pub fn candle_saturation(signal: f32, drive: f32, threshold: f32) -> f32 {
+    let saturation_amount = (signal - threshold).max(0.0) * drive;
+    let compressed_saturation = saturation_amount / (1.0 + saturation_amount.abs());
+    signal + compressed_saturation
+}
+
+// "Leaf" Saturation designed by Ardura
+// Output stays within the threshold until the input is tens of dB past full scale
+pub fn leaf_saturation(input_signal: f32, threshold: f32, drive: f32, fast: bool) -> f32 {
+    let atan = if fast { fast_math::atan } else { f32::atan };
+    let db_to_gain = if fast { fast_math::db_to_gain } else { util::db_to_gain };
+    let range = 6.0;
+    let min_value = 1.0;
+    let drive_db = min_value + drive * range;
+    let signal_holder = input_signal * db_to_gain(drive_db);
+    
+    let curve = if fast { (signal_holder / 999.0).powi(2) } else { (signal_holder / 999.0).powf(2.0) };
+
+    let mut y = signal_holder / threshold;
+    y = (2.0 / PI) * atan(y);
+    (threshold + (1.0 - threshold) * curve) * y
+}
+
+// Slew rate limiting like a slow op-amp - the output can only move max_step per sample
+pub fn slew_saturation(sample: f32, previous: f32, max_step: f32) -> f32 {
+    let step = (sample - previous).clamp(-max_step, max_step);
+    previous + step
+}
+
+// Transformer saturation, output stays within full scale
+pub fn transformer_saturation(sample: f32, threshold: f32, drive: f32, fast: bool) -> f32 {
+    let tanh = if fast { fast_math::tanh } else { f32::tanh };
+    // Shape reaches 0 at full drive, where the gain below is limited by the drive anyway
+    let shape = (2.0 - (drive * 2.0).clamp(0.0, 2.0)).max(f32::EPSILON);
+
+    let input_level = sample.abs();
+    let output_gain = if input_level < threshold {
+        1.0
+    } else {
+        // A 0dB threshold (or above it with channel variance) leaves no headroom to divide by. tanh is flat
+        // long before the cap, which keeps the 5th power finite so a drive of exactly 1 can't make 0 * inf
+        let gain_reduction = ((input_level - threshold) / (1.0 - threshold).max(f32::EPSILON)).min(TRANSFORMER_MAX_REDUCTION);
+        let input_gain = 1.0 + (drive - 1.0) * if fast { gain_reduction.powi(5) } else { gain_reduction.powf(5.0) };
+        let shaped_gain = (tanh(input_gain) / shape).max(0.0).min(drive);
+
+        // Adjust the gain based on the input level
+        shaped_gain * (1.0 - input_level)
+    };
+
+    // Apply the gain to the input signal and saturate it
+    let output = sample * output_gain;
+    if output.abs() > 1.0 {
+        output.signum()
+    } else {
+        output
+    }
+}
+
+
+
+/****************************************************************************************
+ *  Feedback Delay Network Processor
+ ****************************************************************************************/
+    /*
+    difference equation
+    y[n] = x[n] - 0.00010904511 * y[n-50] - 0.04816142 * y[n-1] - 0.00320949 * y[n-2] - 0.0028770843 * y[n-3] - 0.0025967543 * y[n-4] - 0.0023367526 * y[n-5] - 0.0021145213 * y[n-6] - 0.0019009487 * y[n-7] - 0.0017166135 * y[n-8] - 0.0015247492 * y[n-9] - 0.00133025 * y[n-10] - 0.0011769092 * y[n-11] - 0.0010452771 * y[n-12] - 0.000897834 * y[n-13] - 0.0007409797 * y[n-14] - 0.00058073737 * y[n-15] - 0.00044383493 * y[n-16] - 0.0003281392 * y[n-17] - 0.0002153296 * y[n-18] - 6.399655e-5 * y[n-19] + 4.9314993e-5 * y[n-20] + 0.00013502018 * y[n-21] + 0.00020215382 * y[n-22] + 0.00023804209 * y[n-23] + 0.00026853455 * y[n-24] + 0.0003083128 * y[n-25] + 0.0003445775 * y[n-26] + 0.00036402745 * y[n-27] + 0.00039966477 * y[n-28] + 0.0004403214 * y[n-29] + 0.0004541246 * y[n-30] + 0.00046441422 * y[n-31] + 0.00048072706 * y[n-32] + 0.0004873777 * y[n-33] + 0.00046981 * y[n-34] + 0.00047457838 * y[n-35] + 0.0004539991 * y[n-36] + 0.00042601628 * y[n-37] + 0.00039803347 * y[n-38] + 0.00038385383 * y[n-39] + 0.0003287666 * y[n-40] + 0.000287859 * y[n-41] + 0.00022950921 * y[n-42] + 0.00019349548 * y[n-43] + 0.00017015554 * y[n-44] + 0.00015823462 * y[n-45] + 0.00013853372 * y[n-46] + 0.00012184442 * y[n-47] + 9.2983224e-5 * y[n-48] + 9.461451e-5 * y[n-49] + y[n-50]
+    
+    output = input
+        - fdn[0] * outputs[i-50] 
+        - fdn[1] * outputs[i-1] 
+        - fdn[2] * outputs[i-2] 
+        - fdn[3] * outputs[i-3] 
+        - fdn[4] * outputs[i-4] 
+        - fdn[5] * outputs[i-5]
+        ... 
+        + outputs[n-50]
+     */
+
+
+/**************************************************
+ * Duro Console
+ **************************************************/
+
+// The Impulse Response console runs the driven signal through C and the Dynamic Response console through D
+pub struct Console<C: Response = Silence, D: Response = Silence> {
+    threshold: f32,
+    drive: f32,
+    console_drive: f32,
+    console_threshold: f32,
+    bias: f32,
+    dc_coeff: f32,
+    dc_offset: [f32; MAX_CHANNELS],
+    // The DC blocker only runs with a bias, so curves without one sound as they always have
+    dc_fade: Crossfade<bool>,
+    sample_rate: f32,
+    duro_array: [[f32; 12]; MAX_CHANNELS],
+    leaf_array: [[f32; 20]; MAX_CHANNELS],
+    vine_array: [[f32; 21]; MAX_CHANNELS],
+    neve_array: [[f32; 34]; MAX_CHANNELS],
+    prec_array: [[f32; 34]; MAX_CHANNELS],
+    api_array: [[f32; 34]; MAX_CHANNELS],
+    generated_array: [[f32; GENERATED_TAPS + 1]; MAX_CHANNELS],
+    compressor: BusCompressor,
+    slew_rate: f32,
+    slew_step: f32,
+    slew_state: [f32; MAX_CHANNELS],
+    bit_depth: f32,
+    dither: bool,
+    dither_noise: Dither,
+    anti_alias: AntiAliasMode,
+    decimator: Decimator,
+    harmonics: HarmonicGenerator,
+    // Tap coefficients in use for each channel, with any channel variance applied
+    taps: [[(f32, f32); MAX_TAPS]; MAX_CHANNELS],
+    taps_console: ConsoleMode,
+    // Coefficients of the console being faded out
+    previous_taps: [[(f32, f32); MAX_TAPS]; MAX_CHANNELS],
+    console_fade: Crossfade<ConsoleMode>,
+    sat_fade: Crossfade<SaturationModeEnum>,
+    variance: bool,
+    channel_number: i32,
+    tolerance: f32,
+    threshold_variance: [f32; MAX_CHANNELS],
+    // Audio thread copy of the Generated Console's taps
+    generated_taps: [(f32, f32); GENERATED_TAPS],
+    generated_version: u32,
+    // Audio thread copy of the Custom Curve table
+    curve_table: CurveTable,
+    curve_version: u32,
+    // Audio thread copy of the compiled Formula
+    formula: Program,
+    formula_version: u32,
+    // Saturation curves use the fast_math approximations
+    fast_math: bool,
+    // User impulse response console and its latest output per channel
+    convolver: C,
+    convolved: [f32; MAX_CHANNELS],
+    // Level dependent console from kernels captured at several levels and its latest output per channel
+    dynamic: D,
+    dynamic_convolved: [f32; MAX_CHANNELS],
+}
+
+impl<C: Response + Default, D: Response + Default> Console<C, D> {
+    pub fn new(
+        threshold: f32,
+        ratio: f32,
+        console_type: crate::duro_process::ConsoleMode,
+        sample_rate: f32,
+    ) -> Self {
+        let mut console = Self {
+            threshold,
+            drive: 0.0,
+            console_drive: 0.0,
+            console_threshold: 0.0,
+            bias: 0.0,
+            dc_coeff: (-2.0 * PI * DC_BLOCK_HZ / sample_rate).exp(),
+            dc_offset: [0.0; MAX_CHANNELS],
+            dc_fade: Crossfade::new(false, sample_rate),
+            sample_rate,
+            duro_array: [[0.0; 12]; MAX_CHANNELS],
+            leaf_array: [[0.0; 20]; MAX_CHANNELS],
+            vine_array: [[0.0; 21]; MAX_CHANNELS],
+            neve_array: [[0.0; 34]; MAX_CHANNELS],
+            prec_array: [[0.0; 34]; MAX_CHANNELS],
+            api_array: [[0.0; 34]; MAX_CHANNELS],
+            generated_array: [[0.0; GENERATED_TAPS + 1]; MAX_CHANNELS],
+            compressor: BusCompressor::new(ratio, sample_rate),
+            slew_rate: 0.5,
+            slew_step: 0.0,
+            slew_state: [0.0; MAX_CHANNELS],
+            bit_depth: 8.0,
+            dither: false,
+            dither_noise: Dither::new(),
+            anti_alias: AntiAliasMode::PRE,
+            decimator: Decimator::new(),
+            harmonics: HarmonicGenerator::new(sample_rate),
+            taps: [[(0.0, 0.0); MAX_TAPS]; MAX_CHANNELS],
+            taps_console: ConsoleMode::BYPASS,
+            previous_taps: [[(0.0, 0.0); MAX_TAPS]; MAX_CHANNELS],
+            console_fade: Crossfade::new(console_type, sample_rate),
+            sat_fade: Crossfade::new(SaturationModeEnum::NONESAT, sample_rate),
+            variance: false,
+            channel_number: 1,
+            tolerance: 0.0,
+            threshold_variance: [1.0; MAX_CHANNELS],
+            generated_taps: console_models::generate_taps(0, 0.0),
+            generated_version: 0,
+            curve_table: custom_curve::build_table(&custom_curve::DEFAULT_POINTS),
+            curve_version: 0,
+            formula: Program::default_formula(),
+            formula_version: 0,
+            fast_math: false,
+            convolver: C::default(),
+            convolved: [0.0; MAX_CHANNELS],
+            dynamic: D::default(),
+            dynamic_convolved: [0.0; MAX_CHANNELS],
+        };
+        // Start on the console asked for, its taps ready for the first sample
+        console.build_taps(console_type);
+        console
+    }
+
+    // Start in these modes without fading in from the ones before, for a render that begins in them
+    pub fn jump_to_modes(&mut self, console_type: ConsoleMode, sat_type: SaturationModeEnum) {
+        self.console_fade = Crossfade::new(console_type, self.sample_rate);
+        self.sat_fade = Crossfade::new(sat_type, self.sample_rate);
+        self.dc_fade = Crossfade::new(self.bias != 0.0, self.sample_rate);
+        self.build_taps(console_type);
+    }
+
+    // Clear every delay line, filter and envelope as if nothing had played yet. Settings and the loaded
+    // curve, formula and responses stay, and any mode fade in progress lands on its new mode
+    pub fn reset(&mut self) {
+        self.dc_offset = [0.0; MAX_CHANNELS];
+        self.duro_array = [[0.0; 12]; MAX_CHANNELS];
+        self.leaf_array = [[0.0; 20]; MAX_CHANNELS];
+        self.vine_array = [[0.0; 21]; MAX_CHANNELS];
+        self.neve_array = [[0.0; 34]; MAX_CHANNELS];
+        self.prec_array = [[0.0; 34]; MAX_CHANNELS];
+        self.api_array = [[0.0; 34]; MAX_CHANNELS];
+        self.generated_array = [[0.0; GENERATED_TAPS + 1]; MAX_CHANNELS];
+        self.compressor.reset();
+        self.slew_state = [0.0; MAX_CHANNELS];
+        self.dither_noise.reset();
+        self.decimator.reset();
+        self.harmonics.reset();
+        self.console_fade.finish();
+        self.sat_fade.finish();
+        self.dc_fade.finish();
+        self.convolver.reset();
+        self.convolved = [0.0; MAX_CHANNELS];
+        self.dynamic.reset();
+        self.dynamic_convolved = [0.0; MAX_CHANNELS];
+    }
+
+    // Threshold and drive are for the saturation stage, console drive feeds the console delay lines and
+    // the console threshold is where their nonlinear part starts
+    pub fn update_vals(&mut self, threshold: f32, drive: f32, console_threshold: f32, console_drive: f32, sample_rate: f32) {
+        self.threshold = threshold;
+        self.drive = drive;
+        self.console_threshold = console_threshold;
+        self.console_drive = console_drive;
+        if sample_rate != self.sample_rate {
+            self.harmonics.set_sample_rate(sample_rate);
+            self.console_fade.set_sample_rate(sample_rate);
+            self.sat_fade.set_sample_rate(sample_rate);
+            self.dc_fade.set_sample_rate(sample_rate);
+            self.dc_coeff = (-2.0 * PI * DC_BLOCK_HZ / sample_rate).exp();
+        }
+        self.sample_rate = sample_rate;
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_compressor(&mut self, detector: DetectorMode, threshold_db: f32, ratio: f32, attack_ms: f32, release_ms: f32, auto_release: bool, knee_db: f32, makeup_db: f32, channels: usize) {
+        self.compressor.update_vals(detector, threshold_db, ratio, attack_ms, release_ms, auto_release, knee_db, makeup_db, self.sample_rate, channels);
+    }
+
+    // Slew rate in V/us, scaled to a step per sample at the current sample rate
+    pub fn set_slew_rate(&mut self, slew_rate: f32) {
+        self.slew_rate = slew_rate;
+        self.slew_step = self.slew_rate * 1_000_000.0 / (self.sample_rate * SLEW_FULL_SCALE_VOLTS);
+    }
+
+    // Settings shared by the digital lo-fi modes
+    pub fn set_lofi(&mut self, bit_depth: f32, dither: bool, downsample: f32, anti_alias: AntiAliasMode) {
+        self.bit_depth = bit_depth;
+        self.dither = dither;
+        self.anti_alias = anti_alias;
+        self.decimator.update_vals(downsample, self.sample_rate);
+    }
+
+    // Harmonic spectrum as gains relative to the fundamental and phases in degrees
+    pub fn set_harmonics(&mut self, levels: &[f32; NUM_HARMONICS], phases: &[f32; NUM_HARMONICS]) {
+        self.harmonics.update_vals(levels, phases);
+    }
+
+    // Channel variance, tolerance is a fraction so 0.01 lets each coefficient drift by up to 1%
+    pub fn set_variance(&mut self, variance: bool, channel_number: i32, tolerance: f32) {
+        if variance != self.variance || channel_number != self.channel_number || tolerance != self.tolerance {
+            self.variance = variance;
+            self.channel_number = channel_number;
+            self.tolerance = tolerance;
+            self.build_taps(self.taps_console);
+        }
+    }
+
+    // Use these taps for the Generated Console, built by console_models::generate_taps somewhere other
+    // than the audio thread
+    pub fn set_generated_taps(&mut self, taps: &[(f32, f32); GENERATED_TAPS]) {
+        self.generated_taps = *taps;
+        if self.taps_console == ConsoleMode::GENERATED {
+            self.build_taps(ConsoleMode::GENERATED);
+        }
+    }
+
+    // Pick up newly generated console taps from the background thread if there are some
+    pub fn sync_generated(&mut self, shared: &SharedTaps) {
+        if shared.read_into(&mut self.generated_taps, &mut self.generated_version) && self.taps_console == ConsoleMode::GENERATED {
+            self.build_taps(ConsoleMode::GENERATED);
+        }
+    }
+
+    // Copy the console model's coefficients for every channel, perturbing them when variance is on
+    fn build_taps(&mut self, console_type: ConsoleMode) {
+        self.taps_console = console_type;
+        let generated = self.generated_taps;
+        let base: &[(f32, f32)] = match console_type {
+            ConsoleMode::GENERATED => &generated,
+            _ => console_models::base_taps(console_type),
+        };
+        for channel in 0..MAX_CHANNELS {
+            if self.variance {
+                let mut rng = ToleranceRng::new(console_models::variance_seed(console_type, self.channel_number, channel));
+                console_models::vary_taps(&mut self.taps[channel], base, self.tolerance, &mut rng);
+                self.threshold_variance[channel] = 1.0 + self.tolerance * rng.bipolar();
+            } else {
+                self.taps[channel][..base.len()].copy_from_slice(base);
+                self.threshold_variance[channel] = 1.0;
+            }
+        }
+    }
+
+    // Pick up a new Custom Curve table from the editor if there is one
+    pub fn sync_custom_curve(&mut self, shared: &SharedCurve) {
+        shared.read_into(&mut self.curve_table, &mut self.curve_version);
+    }
+
+    // Pick up a newly compiled Formula from the editor if there is one
+    pub fn sync_formula(&mut self, shared: &SharedFormula) {
+        shared.read_into(&mut self.formula, &mut self.formula_version);
+    }
+
+    // Swap the saturation curves' tanh, atan and exp for the cheaper approximations
+    pub fn set_fast_math(&mut self, fast_math: bool) {
+        self.fast_math = fast_math;
+    }
+
+    // Put a newly loaded impulse response in place, the old one is handed back so it isn't freed here
+    pub fn swap_convolver(&mut self, convolver: C) -> C {
+        self.convolved = [0.0; MAX_CHANNELS];
+        core::mem::replace(&mut self.convolver, convolver)
+    }
+
+    // Same for a newly loaded set of level dependent kernels
+    pub fn swap_dynamic(&mut self, dynamic: D) -> D {
+        self.dynamic_convolved = [0.0; MAX_CHANNELS];
+        core::mem::replace(&mut self.dynamic, dynamic)
+    }
+
+    // Offset added before every saturation curve so symmetric curves make even harmonics too
+    pub fn set_bias(&mut self, bias: f32) {
+        self.bias = bias;
+    }
+
+    // Dither noise in LSBs when dither is on
+    fn dither_lsb(&mut self) -> f32 {
+        if self.dither { self.dither_noise.tpdf() } else { 0.0 }
+    }
+
+    // Gain reduction of the bus compressor in dB for the meter
+    pub fn gain_reduction_db(&self) -> f32 {
+        self.compressor.gain_reduction_db()
+    }
+
+    // A channel's delay line of a console model, bypass has none
+    fn history(&self, console_type: ConsoleMode, channel: usize) -> &[f32] {
+        match console_type {
+            ConsoleMode::BYPASS => &[],
+            ConsoleMode::NEVE => &self.neve_array[channel],
+            ConsoleMode::API => &self.api_array[channel],
+            ConsoleMode::PRECISION => &self.prec_array[channel],
+            ConsoleMode::LEAF => &self.leaf_array[channel],
+            ConsoleMode::VINE => &self.vine_array[channel],
+            ConsoleMode::DURO => &self.duro_array[channel],
+            ConsoleMode::GENERATED => &self.generated_array[channel],
+            ConsoleMode::CONVOLUTION => &[],
+            ConsoleMode::DYNAMIC => &[],
+        }
+    }
+
+    // Whether a console is playing, as the current one or the one being faded away from
+    fn is_heard(&self, console_type: ConsoleMode) -> bool {
+        self.console_fade.current() == console_type || (self.console_fade.is_fading() && self.console_fade.previous() == console_type)
+    }
+
+    // Whether a saturation mode is playing, as the current one or the one being faded away from
+    fn sat_is_heard(&self, sat_type: SaturationModeEnum) -> bool {
+        self.sat_fade.current() == sat_type || (self.sat_fade.is_fading() && self.sat_fade.previous() == sat_type)
+    }
+
+    // Run a console model's taps over its delay line
+    fn console_sample(&self, console_type: ConsoleMode, taps: &[(f32, f32)], sample: f32, channel: usize) -> f32 {
+        // Same as the taps, the dry sample plus the driven signal through what the console adds
+        if console_type == ConsoleMode::CONVOLUTION {
+            return sample + self.convolved[channel];
+        }
+        if console_type == ConsoleMode::DYNAMIC {
+            return sample + self.dynamic_convolved[channel];
+        }
+        let history = self.history(console_type, channel);
+        if history.is_empty() {
+            // Do nothing
+            sample
+        } else {
+            // Each channel's parts are off from the nominal threshold like the saturation stage's
+            console_models::sum_taps(history, &taps[..history.len() - 1], sample, self.console_threshold * self.threshold_variance[channel])
+        }
+    }
+
+    pub fn duro_process(&mut self, sample: f32, channel: usize, sat_type: crate::duro_process::SaturationModeEnum, console_type: crate::duro_process::ConsoleMode) -> f32 
+    {
+        self.drive = if self.drive == 0.0 {0.000001} else {self.drive};
+        self.console_drive = if self.console_drive == 0.0 {0.000001} else {self.console_drive};
+
+        // Both fades move once per sample frame
+        if channel == 0 {
+            self.console_fade.advance();
+            self.sat_fade.advance();
+            self.dc_fade.advance();
+        }
+
+        // Keep the outgoing console's coefficients around while we fade away from it
+        let was_convolving = self.is_heard(ConsoleMode::CONVOLUTION);
+        let was_dynamic = self.is_heard(ConsoleMode::DYNAMIC);
+        if self.console_fade.update(console_type) {
+            self.previous_taps = self.taps;
+            self.build_taps(self.console_fade.current());
+            // The responses sat out since they were last heard, start them from silence instead of from that
+            if !was_convolving && self.is_heard(ConsoleMode::CONVOLUTION) {
+                self.convolver.reset();
+                self.convolved = [0.0; MAX_CHANNELS];
+            }
+            if !was_dynamic && self.is_heard(ConsoleMode::DYNAMIC) {
+                self.dynamic.reset();
+                self.dynamic_convolved = [0.0; MAX_CHANNELS];
+            }
+        }
+        self.sat_fade.update(sat_type);
+        self.dc_fade.update(self.bias != 0.0);
+        // A switch that comes in mid-fade waits for that fade to land, so play the modes the fades are on
+        let console_type = self.console_fade.current();
+        let sat_type = self.sat_fade.current();
+
+        // Initialize Feedback Delay Network Processors - every console's delay line is fed so none are stale when switched to.
+        // Each channel has its own, so its taps only ever read its own past samples
+        let driven_sample = sample * self.console_drive;
+        for history in [
+            &mut self.duro_array[channel][..],
+            &mut self.leaf_array[channel][..],
+            &mut self.vine_array[channel][..],
+            &mut self.neve_array[channel][..],
+            &mut self.prec_array[channel][..],
+            &mut self.api_array[channel][..],
+            &mut self.generated_array[channel][..],
+        ] {
+            console_models::push_history(history, driven_sample);
+        }
+        // Far heavier than the delay lines, so only run them while they can be heard
+        if self.is_heard(ConsoleMode::CONVOLUTION) {
+            self.convolved[channel] = self.convolver.process(driven_sample, channel);
+        }
+        if self.is_heard(ConsoleMode::DYNAMIC) {
+            self.dynamic_convolved[channel] = self.dynamic.process(driven_sample, channel);
+        }
+
+        let mut consoled_sample = self.console_sample(console_type, &self.taps[channel], sample, channel);
+        if self.console_fade.is_fading() {
+            let (previous_gain, current_gain) = self.console_fade.gains();
+            let previous_sample = self.console_sample(self.console_fade.previous(), &self.previous_taps[channel], sample, channel);
+            consoled_sample = previous_gain * previous_sample + current_gain * consoled_sample;
+        }
+
+        // Glue the console output together before it hits the saturation
+        consoled_sample = self.compressor.process(consoled_sample);
+
+        // Push the signal off center into the curve
+        let biased_sample = consoled_sample + self.bias;
+        // The harmonic modes share one generator, so it follows the sample once even when fading between two of them
+        let cycle = if [SaturationModeEnum::HARMONICS, SaturationModeEnum::ODDHARMONICS, SaturationModeEnum::FORTHHARM].into_iter().any(|mode| self.sat_is_heard(mode)) {
+            self.harmonics.follow(biased_sample, channel)
+        } else {
+            Cycle::default()
+        };
+        let saturated_sample = if self.sat_fade.is_fading() {
+            let (previous_gain, current_gain) = self.sat_fade.gains();
+            let previous_sample = self.saturate(self.sat_fade.previous(), biased_sample, channel, cycle);
+            let current_sample = self.saturate(sat_type, biased_sample, channel, cycle);
+            previous_gain * previous_sample + current_gain * current_sample
+        } else {
+            self.saturate(sat_type, biased_sample, channel, cycle)
+        };
+
+        // Take away the DC the bias made the curve add, leaving the signal's own low end alone. Fades in and
+        // out with the bias, then stops until the bias comes back
+        let dc_gain = match (self.dc_fade.is_fading(), self.dc_fade.current()) {
+            (false, blocking) => if blocking { 1.0 } else { 0.0 },
+            (true, true) => self.dc_fade.gains().1,
+            (true, false) => self.dc_fade.gains().0,
+        };
+        if dc_gain == 0.0 {
+            self.dc_offset[channel] = 0.0;
+            return saturated_sample;
+        }
+        self.dc_offset[channel] = fast_math::flush_denormal(self.dc_coeff * self.dc_offset[channel] + (1.0 - self.dc_coeff) * (saturated_sample - consoled_sample));
+        saturated_sample - dc_gain * self.dc_offset[channel]
+    }
+
+    fn saturate(&mut self, sat_type: SaturationModeEnum, consoled_sample: f32, channel: usize, cycle: Cycle) -> f32 {
+        // Each channel's parts are a little off from the nominal threshold when variance is on
+        let threshold = self.threshold * self.threshold_variance[channel];
+
+        #[allow(unreachable_patterns)]
+        match sat_type {
+            // No saturation
+            SaturationModeEnum::NONESAT => consoled_sample,
+            // adding even and odd harmonics
+            SaturationModeEnum::TAPESAT => tape_saturation(consoled_sample, self.drive, threshold, self.fast_math),
+            // Only the odd harmonics of the harmonic spectrum
+            SaturationModeEnum::ODDHARMONICS => self.harmonics.shape(consoled_sample, cycle, HarmonicMask::ODD, self.drive, threshold),
+            // Only the 4th, 8th, 12th and 16th harmonics of the harmonic spectrum
+            SaturationModeEnum::FORTHHARM => self.harmonics.shape(consoled_sample, cycle, HarmonicMask::FOURTH, self.drive, threshold),
+            // Candle Saturation through soft compressor added to signal
+            SaturationModeEnum::CANDLE => candle_saturation(consoled_sample, self.drive, threshold),
+            // Hardclipped mix with original
+            SaturationModeEnum::DIGITAL => digital_saturation(consoled_sample, threshold, self.drive),
+            // Chebyshev polynomial saturation based off the symmetrical saturation research - pretending to be tape
+            SaturationModeEnum::CHEBYSHEV => chebyshev_tape(consoled_sample, threshold, self.drive),
+            // Golden Cubic designed by Ardura
+            SaturationModeEnum::GOLDENCUBIC => golden_cubic(consoled_sample, threshold, self.drive, self.fast_math),
+            // "Leaf" saturation designed by Ardura - wildly inefficient
+            SaturationModeEnum::LEAF => leaf_saturation(consoled_sample, threshold, self.drive, self.fast_math),
+            // Transformer Model
+            SaturationModeEnum::TRANSFORMER => transformer_saturation(consoled_sample, threshold, self.drive, self.fast_math),
+            // Slew limiting with drive pushing the signal into the op-amp harder
+            SaturationModeEnum::SLEW => {
+                let slewed = slew_saturation(consoled_sample * (1.0 + self.drive), self.slew_state[channel], self.slew_step);
+                self.slew_state[channel] = slewed;
+                slewed / (1.0 + self.drive)
+            },
+            // Bit depth reduction, the bit depth knob is the amount
+            SaturationModeEnum::BITCRUSH => {
+                let dither = self.dither_lsb();
+                lofi::bitcrush(consoled_sample, self.bit_depth, dither)
+            },
+            // Sample and hold down to a lower rate with optional anti-alias filtering
+            SaturationModeEnum::DECIMATE => self.decimator.process(consoled_sample, channel, self.anti_alias),
+            // Telephone style companding, drive pushes the signal into the compander harder
+            SaturationModeEnum::MULAW => {
+                let dither = self.dither_lsb();
+                lofi::mu_law(consoled_sample * (1.0 + self.drive), self.bit_depth, dither) / (1.0 + self.drive)
+            },
+            SaturationModeEnum::ALAW => {
+                let dither = self.dither_lsb();
+                lofi::a_law(consoled_sample * (1.0 + self.drive), self.bit_depth, dither) / (1.0 + self.drive)
+            },
+            // Every harmonic of the harmonic spectrum
+            SaturationModeEnum::HARMONICS => self.harmonics.shape(consoled_sample, cycle, HarmonicMask::ALL, self.drive, threshold),
+            // User drawn transfer curve, drive pushes more signal into it
+            SaturationModeEnum::CUSTOMCURVE => custom_curve::lookup(&self.curve_table, consoled_sample * (1.0 + self.drive)),
+            // User typed formula of x, drive and threshold
+            SaturationModeEnum::FORMULA => self.formula.evaluate(consoled_sample, self.drive, threshold),
+            // Default to no saturation
+            _ => consoled_sample,
+        }
+
+    }
+}
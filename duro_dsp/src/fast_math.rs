@@ -0,0 +1,93 @@
+// fast_math.rs - Ardura 2023
+// Cheaper approximations of the transcendental functions the saturation curves lean on
+//  Max errors below are what tests/fast_math.rs measures against the std versions across each function's working range
+
+use core::f32::consts::{FRAC_PI_2, LN_10, LOG2_E};
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+
+// ln 2 split into a part with a short mantissa, so k * LN2_HI is exact, and the remainder
+const LN2_HI: f32 = 0.693_145_75;
+const LN2_LO: f32 = 1.428_606_8e-6;
+// Outside these e^x is 0 or infinity as far as f32 (without subnormals) goes
+const MIN_EXP: f32 = -87.336_55;
+const MAX_EXP: f32 = 88.722_84;
+// Below this tanh uses its Taylor series
+const TANH_SERIES_LIMIT: f32 = 0.25;
+// Filter states below this (-300dB) are flushed to 0, low enough that the gains and filters after them
+// can't turn what is left into a subnormal either
+const DENORMAL_FLOOR: f32 = 1e-15;
+
+// Which math the saturation curves use
+modes! {
+    pub enum MathMode {
+        // Fast while playing back in realtime, exact when the host renders offline
+        #[name = "Auto"]
+        AUTO,
+        #[name = "Exact"]
+        EXACT,
+        #[name = "Fast"]
+        FAST,
+    }
+}
+
+// tanh(x) = 1 - 2 / (e^2x + 1) on the fast exp, with the series near 0 where that form would cancel
+// Max absolute error 2.4e-7 (about -132dB) and relative error 6e-7 everywhere
+pub fn tanh(x: f32) -> f32 {
+    if x.abs() < TANH_SERIES_LIMIT {
+        let x2 = x * x;
+        return x * (1.0 + x2 * (-1.0 / 3.0 + x2 * (2.0 / 15.0 + x2 * (-17.0 / 315.0))));
+    }
+    1.0 - 2.0 / (exp(2.0 * x) + 1.0)
+}
+
+// Odd polynomial on -1 to 1 folded out to the rest of the line with atan(x) = pi/2 - atan(1/x)
+// Max absolute error 1.2e-5 radians (about -98dB) everywhere
+pub fn atan(x: f32) -> f32 {
+    let inverted = x.abs() > 1.0;
+    let z = if inverted { 1.0 / x } else { x };
+    let z2 = z * z;
+    let y = z * (0.999_866 + z2 * (-0.330_299_5 + z2 * (0.180_141 + z2 * (-0.085_133 + z2 * 0.020_835_1))));
+    if inverted {
+        FRAC_PI_2.copysign(x) - y
+    } else {
+        y
+    }
+}
+
+// e^x = 2^k * e^r with r = x - k*ln2 split so the reduction stays exact, then a series for e^r
+// Max relative error 2.6e-7 over -87 to 88, 0 and infinity past that like std
+pub fn exp(x: f32) -> f32 {
+    if x.is_nan() {
+        return x;
+    }
+    if x < MIN_EXP {
+        return 0.0;
+    }
+    if x > MAX_EXP {
+        return f32::INFINITY;
+    }
+    let k = (x * LOG2_E).round();
+    let r = x - k * LN2_HI - k * LN2_LO;
+    let series = 1.0 + r * (1.0 + r * (0.5 + r * (1.0 / 6.0 + r * (1.0 / 24.0 + r * (1.0 / 120.0 + r * (1.0 / 720.0))))));
+    // Built in two halves so k = 128 doesn't overflow the exponent bits
+    let k = k as i32;
+    let half = f32::from_bits(((k / 2 + 127) as u32) << 23);
+    let rest = f32::from_bits(((k - k / 2 + 127) as u32) << 23);
+    series * half * rest
+}
+
+// Same as util::db_to_gain using the fast exp
+// Max relative error 1.5e-6 from -100 to +60dB, rounding dbs * ln10 / 20 costs more than the exp itself
+pub fn db_to_gain(dbs: f32) -> f32 {
+    exp(dbs * LN_10 / 20.0)
+}
+
+// Zero out values that are about to decay into subnormals, which are very slow on most CPUs
+pub fn flush_denormal(x: f32) -> f32 {
+    if x.abs() < DENORMAL_FLOOR {
+        0.0
+    } else {
+        x
+    }
+}
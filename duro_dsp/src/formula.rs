@@ -0,0 +1,509 @@
+// formula.rs - Ardura 2023
+// User typed saturation formulas like tanh(x*drive)/tanh(threshold*drive)
+//  Parsed on the GUI thread into fixed size bytecode so the audio thread evaluates it without allocating
+
+use core::sync::atomic::{fence, AtomicU32, AtomicU64, Ordering};
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+#[cfg(feature = "alloc")]
+use core::fmt;
+#[cfg(feature = "alloc")]
+use alloc::{format, string::{String, ToString}, vec::Vec};
+
+pub const DEFAULT_FORMULA: &str = "tanh(x * (1 + drive))";
+// Longest program and deepest evaluation stack a formula may compile to
+const MAX_OPS: usize = 128;
+const MAX_STACK: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Op {
+    Const(f32),
+    X,
+    Drive,
+    Threshold,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Neg,
+    Sin,
+    Cos,
+    Tan,
+    Tanh,
+    Atan,
+    Exp,
+    Ln,
+    Sqrt,
+    Abs,
+    Sign,
+    Min,
+    Max,
+    Clamp,
+}
+
+// Functions the formula can call and how many arguments they take
+#[cfg(feature = "alloc")]
+const FUNCTIONS: [(&str, Op, usize); 13] = [
+    ("sin", Op::Sin, 1),
+    ("cos", Op::Cos, 1),
+    ("tan", Op::Tan, 1),
+    ("tanh", Op::Tanh, 1),
+    ("atan", Op::Atan, 1),
+    ("exp", Op::Exp, 1),
+    ("ln", Op::Ln, 1),
+    ("sqrt", Op::Sqrt, 1),
+    ("abs", Op::Abs, 1),
+    ("sign", Op::Sign, 1),
+    ("min", Op::Min, 2),
+    ("max", Op::Max, 2),
+    ("clamp", Op::Clamp, 3),
+];
+
+impl Op {
+    // Pack into a word so a program can live in atomics, the tag goes in the top half and any constant in the bottom
+    fn encode(self) -> u64 {
+        let (tag, value): (u64, f32) = match self {
+            Op::Const(value) => (0, value),
+            Op::X => (1, 0.0),
+            Op::Drive => (2, 0.0),
+            Op::Threshold => (3, 0.0),
+            Op::Add => (4, 0.0),
+            Op::Sub => (5, 0.0),
+            Op::Mul => (6, 0.0),
+            Op::Div => (7, 0.0),
+            Op::Pow => (8, 0.0),
+            Op::Neg => (9, 0.0),
+            Op::Sin => (10, 0.0),
+            Op::Cos => (11, 0.0),
+            Op::Tan => (12, 0.0),
+            Op::Tanh => (13, 0.0),
+            Op::Atan => (14, 0.0),
+            Op::Exp => (15, 0.0),
+            Op::Ln => (16, 0.0),
+            Op::Sqrt => (17, 0.0),
+            Op::Abs => (18, 0.0),
+            Op::Sign => (19, 0.0),
+            Op::Min => (20, 0.0),
+            Op::Max => (21, 0.0),
+            Op::Clamp => (22, 0.0),
+        };
+        (tag << 32) | value.to_bits() as u64
+    }
+
+    fn decode(word: u64) -> Op {
+        match word >> 32 {
+            0 => Op::Const(f32::from_bits(word as u32)),
+            1 => Op::X,
+            2 => Op::Drive,
+            3 => Op::Threshold,
+            4 => Op::Add,
+            5 => Op::Sub,
+            6 => Op::Mul,
+            7 => Op::Div,
+            8 => Op::Pow,
+            9 => Op::Neg,
+            10 => Op::Sin,
+            11 => Op::Cos,
+            12 => Op::Tan,
+            13 => Op::Tanh,
+            14 => Op::Atan,
+            15 => Op::Exp,
+            16 => Op::Ln,
+            17 => Op::Sqrt,
+            18 => Op::Abs,
+            19 => Op::Sign,
+            20 => Op::Min,
+            21 => Op::Max,
+            22 => Op::Clamp,
+            _ => Op::X,
+        }
+    }
+
+    // Change in stack depth when this op runs
+    #[cfg(feature = "alloc")]
+    fn stack_effect(self) -> isize {
+        match self {
+            Op::Const(_) | Op::X | Op::Drive | Op::Threshold => 1,
+            Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow | Op::Min | Op::Max => -1,
+            Op::Clamp => -2,
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormulaError {
+    pub message: String,
+    // Character offset the error was found at
+    pub position: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.position + 1)
+    }
+}
+
+// Compiled formula in postfix order, fixed size so it can be copied around without allocating
+#[derive(Clone, Copy)]
+pub struct Program {
+    ops: [Op; MAX_OPS],
+    len: usize,
+}
+
+impl Program {
+    // Just passes x through
+    pub fn passthrough() -> Self {
+        Self { ops: [Op::X; MAX_OPS], len: 1 }
+    }
+
+    // DEFAULT_FORMULA as compile gives it, for when there's no allocator to compile with
+    pub fn default_formula() -> Self {
+        let mut program = Self::passthrough();
+        let ops = [Op::X, Op::Const(1.0), Op::Drive, Op::Add, Op::Mul, Op::Tanh];
+        program.ops[..ops.len()].copy_from_slice(&ops);
+        program.len = ops.len();
+        program
+    }
+
+    pub fn evaluate(&self, x: f32, drive: f32, threshold: f32) -> f32 {
+        let mut stack = [0.0_f32; MAX_STACK];
+        let mut top = 0;
+        for op in self.ops[..self.len].iter() {
+            match *op {
+                Op::Const(value) => { stack[top] = value; top += 1; }
+                Op::X => { stack[top] = x; top += 1; }
+                Op::Drive => { stack[top] = drive; top += 1; }
+                Op::Threshold => { stack[top] = threshold; top += 1; }
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow | Op::Min | Op::Max => {
+                    top -= 1;
+                    let (a, b) = (stack[top - 1], stack[top]);
+                    stack[top - 1] = match *op {
+                        Op::Add => a + b,
+                        Op::Sub => a - b,
+                        Op::Mul => a * b,
+                        Op::Div => a / b,
+                        Op::Pow => a.powf(b),
+                        Op::Min => a.min(b),
+                        _ => a.max(b),
+                    };
+                }
+                Op::Clamp => {
+                    top -= 2;
+                    let (value, low, high) = (stack[top - 1], stack[top], stack[top + 1]);
+                    stack[top - 1] = value.max(low).min(high);
+                }
+                unary => {
+                    let a = stack[top - 1];
+                    stack[top - 1] = match unary {
+                        Op::Neg => -a,
+                        Op::Sin => a.sin(),
+                        Op::Cos => a.cos(),
+                        Op::Tan => a.tan(),
+                        Op::Tanh => a.tanh(),
+                        Op::Atan => a.atan(),
+                        Op::Exp => a.exp(),
+                        Op::Ln => a.ln(),
+                        Op::Sqrt => a.sqrt(),
+                        Op::Abs => a.abs(),
+                        _ => if a == 0.0 { 0.0 } else { a.signum() },
+                    };
+                }
+            }
+        }
+        // Anything that blows up (like dividing by a drive of 0) passes the input through instead
+        let result = stack[0];
+        if result.is_finite() { result } else { x }
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Token {
+    Number(f32),
+    Ident(usize, usize),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Caret,
+    Comma,
+    Open,
+    Close,
+    End,
+}
+
+#[cfg(feature = "alloc")]
+struct Parser<'a> {
+    text: &'a str,
+    chars: Vec<(usize, char)>,
+    index: usize,
+    token: Token,
+    token_position: usize,
+    program: Program,
+    depth: isize,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> Parser<'a> {
+    fn error<T>(&self, message: &str) -> Result<T, FormulaError> {
+        Err(FormulaError { message: message.to_string(), position: self.token_position })
+    }
+
+    fn next_token(&mut self) -> Result<(), FormulaError> {
+        while self.index < self.chars.len() && self.chars[self.index].1.is_whitespace() {
+            self.index += 1;
+        }
+        self.token_position = self.chars.get(self.index).map(|c| c.0).unwrap_or(self.text.len());
+        let Some(&(start, c)) = self.chars.get(self.index) else {
+            self.token = Token::End;
+            return Ok(());
+        };
+        self.index += 1;
+        self.token = match c {
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '^' => Token::Caret,
+            ',' => Token::Comma,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            c if c.is_ascii_digit() || c == '.' => {
+                while self.index < self.chars.len() {
+                    let (_, next) = self.chars[self.index];
+                    let previous = self.chars[self.index - 1].1;
+                    let exponent_sign = (next == '+' || next == '-') && (previous == 'e' || previous == 'E');
+                    if next.is_ascii_digit() || next == '.' || next == 'e' || next == 'E' || exponent_sign {
+                        self.index += 1;
+                    } else {
+                        break;
+                    }
+                }
+                let end = self.chars.get(self.index).map(|c| c.0).unwrap_or(self.text.len());
+                match self.text[start..end].parse::<f32>() {
+                    Ok(value) => Token::Number(value),
+                    Err(_) => return self.error("Bad number"),
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while self.index < self.chars.len() && (self.chars[self.index].1.is_alphanumeric() || self.chars[self.index].1 == '_') {
+                    self.index += 1;
+                }
+                let end = self.chars.get(self.index).map(|c| c.0).unwrap_or(self.text.len());
+                Token::Ident(start, end)
+            }
+            _ => return self.error(&format!("Unexpected '{c}'")),
+        };
+        Ok(())
+    }
+
+    fn emit(&mut self, op: Op) -> Result<(), FormulaError> {
+        if self.program.len == MAX_OPS {
+            return self.error("Formula is too long");
+        }
+        self.program.ops[self.program.len] = op;
+        self.program.len += 1;
+        self.depth += op.stack_effect();
+        if self.depth > MAX_STACK as isize {
+            return self.error("Formula is nested too deeply");
+        }
+        Ok(())
+    }
+
+    fn expect(&mut self, token: Token, message: &str) -> Result<(), FormulaError> {
+        if self.token != token {
+            return self.error(message);
+        }
+        self.next_token()
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<(), FormulaError> {
+        self.term()?;
+        loop {
+            let op = match self.token {
+                Token::Plus => Op::Add,
+                Token::Minus => Op::Sub,
+                _ => return Ok(()),
+            };
+            self.next_token()?;
+            self.term()?;
+            self.emit(op)?;
+        }
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<(), FormulaError> {
+        self.unary()?;
+        loop {
+            let op = match self.token {
+                Token::Star => Op::Mul,
+                Token::Slash => Op::Div,
+                _ => return Ok(()),
+            };
+            self.next_token()?;
+            self.unary()?;
+            self.emit(op)?;
+        }
+    }
+
+    // unary := ('-' | '+') unary | power
+    fn unary(&mut self) -> Result<(), FormulaError> {
+        match self.token {
+            Token::Minus => {
+                self.next_token()?;
+                self.unary()?;
+                self.emit(Op::Neg)
+            }
+            Token::Plus => {
+                self.next_token()?;
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    // power := primary ('^' unary)?, so -x^2 is -(x^2) and 2^3^2 is 2^(3^2)
+    fn power(&mut self) -> Result<(), FormulaError> {
+        self.primary()?;
+        if self.token == Token::Caret {
+            self.next_token()?;
+            self.unary()?;
+            self.emit(Op::Pow)?;
+        }
+        Ok(())
+    }
+
+    // primary := number | variable | constant | function '(' arguments ')' | '(' expression ')'
+    fn primary(&mut self) -> Result<(), FormulaError> {
+        match self.token {
+            Token::Number(value) => {
+                self.next_token()?;
+                self.emit(Op::Const(value))
+            }
+            Token::Open => {
+                self.next_token()?;
+                self.expression()?;
+                self.expect(Token::Close, "Missing ')'")
+            }
+            Token::Ident(start, end) => {
+                let name = &self.text[start..end];
+                let position = self.token_position;
+                let variable = match name {
+                    "x" => Some(Op::X),
+                    "drive" => Some(Op::Drive),
+                    "threshold" => Some(Op::Threshold),
+                    "pi" => Some(Op::Const(core::f32::consts::PI)),
+                    "e" => Some(Op::Const(core::f32::consts::E)),
+                    _ => None,
+                };
+                if let Some(op) = variable {
+                    self.next_token()?;
+                    return self.emit(op);
+                }
+
+                let Some(&(_, op, arguments)) = FUNCTIONS.iter().find(|(function, _, _)| *function == name) else {
+                    return self.error(&format!("Unknown name '{name}'"));
+                };
+                let arity = format!("{name} takes {arguments} argument{}", if arguments == 1 { "" } else { "s" });
+                self.next_token()?;
+                self.expect(Token::Open, &format!("Expected '(' after {name}"))?;
+                for argument in 0..arguments {
+                    if argument > 0 && self.token != Token::Comma {
+                        self.token_position = position;
+                        return self.error(&arity);
+                    }
+                    if argument > 0 {
+                        self.next_token()?;
+                    }
+                    self.expression()?;
+                }
+                match self.token {
+                    Token::Close => {}
+                    Token::End => return self.error("Missing ')'"),
+                    _ => {
+                        self.token_position = position;
+                        return self.error(&arity);
+                    }
+                }
+                self.next_token()?;
+                self.emit(op)
+            }
+            Token::End => self.error("Formula ended early"),
+            _ => self.error("Expected a number, variable or '('"),
+        }
+    }
+}
+
+// Parse a formula using the variables x, drive and threshold
+#[cfg(feature = "alloc")]
+pub fn compile(text: &str) -> Result<Program, FormulaError> {
+    let mut parser = Parser {
+        text,
+        chars: text.char_indices().collect(),
+        index: 0,
+        token: Token::End,
+        token_position: 0,
+        program: Program { ops: [Op::X; MAX_OPS], len: 0 },
+        depth: 0,
+    };
+    parser.next_token()?;
+    if parser.token == Token::End {
+        return parser.error("Formula is empty");
+    }
+    parser.expression()?;
+    if parser.token != Token::End {
+        return parser.error("Unexpected text after the formula");
+    }
+    Ok(parser.program)
+}
+
+// Program shared from the GUI to the audio thread without locks, same versioning as the custom curve table
+pub struct SharedFormula {
+    version: AtomicU32,
+    len: AtomicU32,
+    ops: [AtomicU64; MAX_OPS],
+}
+
+impl SharedFormula {
+    pub fn new(program: &Program) -> Self {
+        Self {
+            version: AtomicU32::new(2),
+            len: AtomicU32::new(program.len as u32),
+            ops: core::array::from_fn(|i| AtomicU64::new(program.ops[i].encode())),
+        }
+    }
+
+    // Only call this while holding the formula text's write lock so there is a single writer
+    pub fn write(&self, program: &Program) {
+        let version = self.version.load(Ordering::Relaxed);
+        self.version.store(version.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.len.store(program.len as u32, Ordering::Relaxed);
+        for (shared, op) in self.ops.iter().zip(program.ops.iter()) {
+            shared.store(op.encode(), Ordering::Relaxed);
+        }
+        self.version.store(version.wrapping_add(2), Ordering::Release);
+    }
+
+    // Copy the program into the audio thread's own copy if it changed, a torn read is retried next time
+    pub fn read_into(&self, program: &mut Program, version: &mut u32) {
+        let start = self.version.load(Ordering::Acquire);
+        if start == *version || start % 2 == 1 {
+            return;
+        }
+        let mut copy = Program { ops: [Op::X; MAX_OPS], len: (self.len.load(Ordering::Relaxed) as usize).min(MAX_OPS) };
+        for (op, shared) in copy.ops.iter_mut().zip(self.ops.iter()) {
+            *op = Op::decode(shared.load(Ordering::Relaxed));
+        }
+        fence(Ordering::Acquire);
+        if self.version.load(Ordering::Relaxed) == start {
+            *program = copy;
+            *version = start;
+        }
+    }
+}
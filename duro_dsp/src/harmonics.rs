@@ -0,0 +1,156 @@
+// harmonics.rs - Ardura 2023
+// Chebyshev polynomial harmonic generator with a user set level and phase for harmonics 2 to 16
+
+use core::f32::consts::PI;
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+use crate::duro_process::MAX_CHANNELS;
+use crate::fast_math::flush_denormal;
+
+pub const FIRST_HARMONIC: usize = 2;
+pub const NUM_HARMONICS: usize = 15;
+// Release of the envelope the input is normalized by, long enough not to droop inside a 20Hz cycle
+const ENVELOPE_RELEASE_MS: f32 = 500.0;
+// Level the harmonics of the old Odd Harmonics and Fourth Harmonics modes start at, all of them alike
+// as they were before the spectrum could be set
+const OLD_MODE_LEVEL: f32 = 0.25;
+
+// Starting level of a harmonic: 3, 5, 7 and 9 for Odd Harmonics and 4, 8, 12 and 16 for Fourth
+// Harmonics, so sessions saved in those modes still add what they did
+pub fn default_level(harmonic: usize) -> f32 {
+    match harmonic {
+        3 | 5 | 7 | 9 | 4 | 8 | 12 | 16 => OLD_MODE_LEVEL,
+        _ => 0.0,
+    }
+}
+
+// Which harmonics a saturation mode lets through
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum HarmonicMask {
+    ALL,
+    ODD,
+    FOURTH,
+}
+
+impl HarmonicMask {
+    fn allows(&self, harmonic: usize) -> bool {
+        match self {
+            HarmonicMask::ALL => true,
+            HarmonicMask::ODD => harmonic % 2 == 1,
+            HarmonicMask::FOURTH => harmonic.is_multiple_of(4),
+        }
+    }
+}
+
+// Where a channel's input is in its cycle, read as amplitude * cos(theta)
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Cycle {
+    amplitude: f32,
+    cos: f32,
+    sin: f32,
+}
+
+pub struct HarmonicGenerator {
+    levels: [f32; NUM_HARMONICS],
+    phases: [f32; NUM_HARMONICS],
+    cos_phase: [f32; NUM_HARMONICS],
+    sin_phase: [f32; NUM_HARMONICS],
+    release_coeff: f32,
+    envelope: [f32; MAX_CHANNELS],
+    previous: [f32; MAX_CHANNELS],
+}
+
+impl HarmonicGenerator {
+    pub fn new(sample_rate: f32) -> Self {
+        let mut generator = Self {
+            levels: core::array::from_fn(|i| default_level(i + FIRST_HARMONIC)),
+            phases: [0.0; NUM_HARMONICS],
+            cos_phase: [1.0; NUM_HARMONICS],
+            sin_phase: [0.0; NUM_HARMONICS],
+            release_coeff: 0.0,
+            envelope: [0.0; MAX_CHANNELS],
+            previous: [0.0; MAX_CHANNELS],
+        };
+        generator.set_sample_rate(sample_rate);
+        generator
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.release_coeff = (-1.0 / (ENVELOPE_RELEASE_MS * 0.001 * sample_rate)).exp();
+    }
+
+    // Levels are gains relative to the fundamental, phases are in degrees
+    pub fn update_vals(&mut self, levels: &[f32; NUM_HARMONICS], phases: &[f32; NUM_HARMONICS]) {
+        self.levels = *levels;
+        for (i, phase) in phases.iter().enumerate() {
+            if *phase != self.phases[i] {
+                self.phases[i] = *phase;
+                let radians = phase * PI / 180.0;
+                self.cos_phase[i] = radians.cos();
+                self.sin_phase[i] = radians.sin();
+            }
+        }
+    }
+
+    // Forget the envelope and last sample of every channel
+    pub fn reset(&mut self) {
+        self.envelope = [0.0; MAX_CHANNELS];
+        self.previous = [0.0; MAX_CHANNELS];
+    }
+
+    // Move a channel's envelope and last sample on by one sample. Once per sample however many modes
+    // shape it, a second call on the same sample would see no movement and flip the sign of sin(theta)
+    pub fn follow(&mut self, sample: f32, channel: usize) -> Cycle {
+        // Follow the peak level so the input can be treated as a*cos(theta)
+        let magnitude = sample.abs();
+        self.envelope[channel] = if magnitude > self.envelope[channel] {
+            magnitude
+        } else {
+            flush_denormal(self.release_coeff * self.envelope[channel])
+        };
+        let amplitude = self.envelope[channel];
+        let previous = self.previous[channel];
+        self.previous[channel] = sample;
+        if amplitude <= f32::EPSILON {
+            return Cycle::default();
+        }
+
+        // cos(theta) and sin(theta), with the sign of sin(theta) taken from which way the signal is moving
+        let cos = (sample / amplitude).clamp(-1.0, 1.0);
+        let sin = if sample < previous { 1.0 } else { -1.0 } * (1.0 - cos * cos).sqrt();
+        Cycle { amplitude, cos, sin }
+    }
+
+    // Add the harmonics the mask lets through to a sample at the point of its cycle follow found. Like the
+    // other curves only the level above the threshold is worked, each harmonic comes out at its level
+    // times drive times how far the input peaks past the threshold
+    pub fn shape(&self, sample: f32, cycle: Cycle, mask: HarmonicMask, drive: f32, threshold: f32) -> f32 {
+        if cycle.amplitude <= f32::EPSILON {
+            return sample;
+        }
+        let c = cycle.cos;
+        let s = cycle.sin;
+
+        // T_n(c) = cos(n*theta) and U_{n-1}(c) * sin(theta) = sin(n*theta)
+        let mut t_prev = 1.0;
+        let mut t = c;
+        let mut u_prev = 1.0;
+        let mut u = 2.0 * c;
+        let mut harmonics = 0.0;
+        for i in 0..NUM_HARMONICS {
+            let harmonic = i + FIRST_HARMONIC;
+            let t_next = 2.0 * c * t - t_prev;
+            t_prev = t;
+            t = t_next;
+            // u currently holds U_{harmonic-1}
+            if mask.allows(harmonic) && self.levels[i] > 0.0 {
+                harmonics += self.levels[i] * (t * self.cos_phase[i] - u * s * self.sin_phase[i]);
+            }
+            let u_next = 2.0 * c * u - u_prev;
+            u_prev = u;
+            u = u_next;
+        }
+
+        sample + (cycle.amplitude - threshold).max(0.0) * drive * harmonics
+    }
+}
@@ -0,0 +1,28 @@
+// lib.rs - Ardura 2023
+// Duro Console's saturation curves, console models and the Console that runs them, free of any plugin framework
+//  Nothing here allocates while processing. Without the std feature it is no_std with libm's float math,
+//  alloc adds formula compiling and the Custom Curve point helpers, names the display names of the modes
+
+#![cfg_attr(not(feature = "std"), no_std)]
+// The test harness links std, whose float methods leave the num-traits imports unused
+#![cfg_attr(all(test, not(feature = "std")), allow(unused_imports))]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[macro_use]
+pub mod modes;
+pub mod util;
+pub mod bus_compressor;
+pub mod lofi;
+pub mod harmonics;
+pub mod console_models;
+pub mod crossfade;
+pub mod custom_curve;
+pub mod formula;
+pub mod fast_math;
+pub mod response;
+pub mod duro_process;
+
+pub use modes::Mode;
+pub use response::{Response, Silence};
@@ -0,0 +1,218 @@
+// lofi.rs - Ardura 2023
+// Digital degradation for the lo-fi saturation modes: bit depth, sample rate and companding
+
+use core::f32::consts::PI;
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+use crate::duro_process::MAX_CHANNELS;
+use crate::fast_math::flush_denormal;
+
+modes! {
+    pub enum AntiAliasMode {
+        #[name = "No Filter"]
+        OFF,
+        #[name = "Pre Filter"]
+        PRE,
+        #[name = "Pre + Post Filter"]
+        PREPOST,
+    }
+}
+
+// Standard companding constants from G.711
+const MU: f32 = 255.0;
+const A: f32 = 87.6;
+// Keep the anti-alias filters a little under the new Nyquist
+const ANTI_ALIAS_CUTOFF: f32 = 0.9;
+// Q of the two sections of a 4th order Butterworth lowpass
+const BUTTERWORTH_Q: [f32; 2] = [0.541_196_1, 1.306_563];
+
+// Round to the nearest step of a signed quantizer with the given bits, dither is in LSBs
+pub fn bitcrush(sample: f32, bits: f32, dither: f32) -> f32 {
+    let levels = 2.0_f32.powf(bits - 1.0);
+    ((sample.clamp(-1.0, 1.0) * levels + dither).round() / levels).clamp(-1.0, 1.0)
+}
+
+// Compress with mu-law, quantize in the companded domain, then expand back
+pub fn mu_law(sample: f32, bits: f32, dither: f32) -> f32 {
+    let x = sample.clamp(-1.0, 1.0);
+    let compressed = x.signum() * (1.0 + MU * x.abs()).ln() / (1.0 + MU).ln();
+    let quantized = bitcrush(compressed, bits, dither);
+    quantized.signum() * ((1.0 + MU).powf(quantized.abs()) - 1.0) / MU
+}
+
+// Compress with A-law, quantize in the companded domain, then expand back
+pub fn a_law(sample: f32, bits: f32, dither: f32) -> f32 {
+    let x = sample.clamp(-1.0, 1.0);
+    let magnitude = x.abs();
+    let denominator = 1.0 + A.ln();
+    let compressed = if magnitude < 1.0 / A {
+        A * magnitude / denominator
+    } else {
+        (1.0 + (A * magnitude).ln()) / denominator
+    };
+    let quantized = bitcrush(x.signum() * compressed, bits, dither);
+    let y = quantized.abs();
+    let expanded = if y < 1.0 / denominator {
+        y * denominator / A
+    } else {
+        (y * denominator - 1.0).exp() / A
+    };
+    quantized.signum() * expanded
+}
+
+// Triangular dither noise from a small xorshift generator so nothing allocates on the audio thread
+pub struct Dither {
+    state: u32,
+}
+
+impl Dither {
+    pub fn new() -> Self {
+        Self { state: 0x1234_5678 }
+    }
+
+    fn uniform(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state as f32 / u32::MAX as f32 - 0.5
+    }
+
+    // Noise between -1 and 1 LSB with a triangular distribution
+    pub fn tpdf(&mut self) -> f32 {
+        self.uniform() + self.uniform()
+    }
+
+    // Back to the start of the sequence so a render repeats exactly
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    fn new() -> Self {
+        Self { b0: 1.0, b1: 0.0, b2: 0.0, a1: 0.0, a2: 0.0, z1: 0.0, z2: 0.0 }
+    }
+
+    // RBJ cookbook lowpass, keeps the filter state so the cutoff can move while running
+    fn set_lowpass(&mut self, cutoff: f32, q: f32, sample_rate: f32) {
+        let omega = 2.0 * PI * (cutoff / sample_rate).min(0.49);
+        let alpha = omega.sin() / (2.0 * q);
+        let cos_omega = omega.cos();
+        let a0 = 1.0 + alpha;
+        self.b0 = (1.0 - cos_omega) / 2.0 / a0;
+        self.b1 = (1.0 - cos_omega) / a0;
+        self.b2 = self.b0;
+        self.a1 = -2.0 * cos_omega / a0;
+        self.a2 = (1.0 - alpha) / a0;
+    }
+
+    fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+
+    // Transposed direct form II. The output is flushed too, with a low cutoff b0 is tiny and a few
+    // sections in a row would shrink a small enough output into a subnormal
+    fn process(&mut self, sample: f32) -> f32 {
+        let output = flush_denormal(self.b0 * sample + self.z1);
+        self.z1 = flush_denormal(self.b1 * sample - self.a1 * output + self.z2);
+        self.z2 = flush_denormal(self.b2 * sample - self.a2 * output);
+        output
+    }
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Sample and hold decimation with optional lowpass filters around it
+pub struct Decimator {
+    factor: f32,
+    sample_rate: f32,
+    phase: [f32; MAX_CHANNELS],
+    held: [f32; MAX_CHANNELS],
+    pre_filter: [[Biquad; 2]; MAX_CHANNELS],
+    post_filter: [[Biquad; 2]; MAX_CHANNELS],
+}
+
+impl Decimator {
+    pub fn new() -> Self {
+        Self {
+            factor: 0.0,
+            sample_rate: 0.0,
+            phase: [0.0; MAX_CHANNELS],
+            held: [0.0; MAX_CHANNELS],
+            pre_filter: [[Biquad::new(); 2]; MAX_CHANNELS],
+            post_filter: [[Biquad::new(); 2]; MAX_CHANNELS],
+        }
+    }
+
+    // Factor is how many samples each held value lasts, fractional factors are allowed
+    pub fn update_vals(&mut self, factor: f32, sample_rate: f32) {
+        let factor = factor.max(1.0);
+        if factor == self.factor && sample_rate == self.sample_rate {
+            return;
+        }
+        self.factor = factor;
+        self.sample_rate = sample_rate;
+
+        let cutoff = ANTI_ALIAS_CUTOFF * sample_rate / (2.0 * factor);
+        for channel in 0..MAX_CHANNELS {
+            for (section, q) in BUTTERWORTH_Q.iter().enumerate() {
+                self.pre_filter[channel][section].set_lowpass(cutoff, *q, sample_rate);
+                self.post_filter[channel][section].set_lowpass(cutoff, *q, sample_rate);
+            }
+        }
+    }
+
+    // Clear the held samples and filter states, the factor and cutoff stay
+    pub fn reset(&mut self) {
+        self.phase = [0.0; MAX_CHANNELS];
+        self.held = [0.0; MAX_CHANNELS];
+        for section in self.pre_filter.iter_mut().chain(self.post_filter.iter_mut()).flatten() {
+            section.reset();
+        }
+    }
+
+    pub fn process(&mut self, sample: f32, channel: usize, anti_alias: AntiAliasMode) -> f32 {
+        let mut input = sample;
+        if anti_alias != AntiAliasMode::OFF {
+            for section in self.pre_filter[channel].iter_mut() {
+                input = section.process(input);
+            }
+        }
+
+        self.phase[channel] += 1.0;
+        if self.phase[channel] >= self.factor {
+            self.phase[channel] -= self.factor;
+            self.held[channel] = input;
+        }
+
+        let mut output = self.held[channel];
+        if anti_alias == AntiAliasMode::PREPOST {
+            for section in self.post_filter[channel].iter_mut() {
+                output = section.process(output);
+            }
+        }
+        output
+    }
+}
+
+impl Default for Decimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,53 @@
+// modes.rs - Ardura 2023
+// Index and display name of the mode enums, what nih-plug's Enum derive gives a plugin without depending on it
+
+// A choice between a fixed set of modes, numbered from 0 in the order they are declared
+pub trait Mode: Copy + PartialEq + 'static {
+    const COUNT: usize;
+
+    fn to_index(self) -> usize;
+
+    // Indices past the last mode give the last mode
+    fn from_index(index: usize) -> Self;
+
+    // Display name of every mode by index
+    #[cfg(feature = "names")]
+    fn variants() -> &'static [&'static str];
+}
+
+// Declares a mode enum the same way the plugin declares its nih-plug enums, with a #[name] on every variant
+macro_rules! modes {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $enum:ident {
+            $(
+                #[name = $name:literal]
+                $variant:ident,
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+        $vis enum $enum {
+            $($variant,)*
+        }
+
+        impl $crate::modes::Mode for $enum {
+            const COUNT: usize = [$($enum::$variant),*].len();
+
+            fn to_index(self) -> usize {
+                self as usize
+            }
+
+            fn from_index(index: usize) -> Self {
+                const ALL: &[$enum] = &[$($enum::$variant),*];
+                ALL[index.min(ALL.len() - 1)]
+            }
+
+            #[cfg(feature = "names")]
+            fn variants() -> &'static [&'static str] {
+                &[$($name),*]
+            }
+        }
+    };
+}
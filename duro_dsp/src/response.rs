@@ -0,0 +1,35 @@
+// response.rs - Ardura 2023
+// What the Impulse Response and Dynamic Response consoles run the driven signal through
+//  The plugin loads convolvers here, without one both consoles pass the dry signal alone
+
+pub trait Response {
+    // Next output of one channel, channels are fed one after the other every sample frame
+    fn process(&mut self, sample: f32, channel: usize) -> f32;
+
+    // Clear all history as if nothing had played yet
+    fn reset(&mut self);
+}
+
+// No response at all
+#[derive(Default, Clone, Copy)]
+pub struct Silence;
+
+impl Response for Silence {
+    fn process(&mut self, _sample: f32, _channel: usize) -> f32 {
+        0.0
+    }
+
+    fn reset(&mut self) {}
+}
+
+// Loaded on another thread and swapped in, so the swap only moves a pointer
+#[cfg(feature = "alloc")]
+impl<T: Response + ?Sized> Response for alloc::boxed::Box<T> {
+    fn process(&mut self, sample: f32, channel: usize) -> f32 {
+        (**self).process(sample, channel)
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+}
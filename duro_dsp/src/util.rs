@@ -0,0 +1,22 @@
+// util.rs - Ardura 2023
+// Decibel conversions, the same ones nih-plug's util has
+
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+
+pub const MINUS_INFINITY_DB: f32 = -100.0;
+pub const MINUS_INFINITY_GAIN: f32 = 1e-5;
+
+// Anything at or below MINUS_INFINITY_DB is silence
+pub fn db_to_gain(dbs: f32) -> f32 {
+    if dbs > MINUS_INFINITY_DB {
+        10.0f32.powf(dbs * 0.05)
+    } else {
+        0.0
+    }
+}
+
+// Silence comes out as MINUS_INFINITY_DB
+pub fn gain_to_db(gain: f32) -> f32 {
+    gain.max(MINUS_INFINITY_GAIN).log10() * 20.0
+}
@@ -0,0 +1,321 @@
+// console.rs - Ardura 2023
+// The Console on its own, with no plugin around it: every saturation type into every console type
+//  An allocator that counts on the test's own thread checks that neither building nor running a
+//  Console allocates, the promise that lets it run without an allocator at all
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    f32::consts::PI,
+};
+
+use duro_dsp::{
+    crossfade::{Crossfade, CROSSFADE_MS},
+    duro_process::{candle_saturation, Console, ConsoleMode, SaturationModeEnum},
+    formula::{self, Program},
+    harmonics::NUM_HARMONICS,
+    Mode, Response,
+};
+
+const SAMPLE_RATE: f32 = 48000.0;
+const FRAMES: usize = 2048;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // try_with so allocations while the thread is shutting down still go through
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|count| count.get())
+}
+
+// A one sample echo standing in for a loaded impulse response
+#[derive(Default)]
+struct Echo {
+    previous: [f32; 2],
+}
+
+impl Response for Echo {
+    fn process(&mut self, sample: f32, channel: usize) -> f32 {
+        std::mem::replace(&mut self.previous[channel], sample) * 0.5
+    }
+
+    fn reset(&mut self) {
+        self.previous = [0.0; 2];
+    }
+}
+
+// Stereo noise through one combination of modes, the way the plugin feeds a console frame by frame
+fn play<C: Response + Default, D: Response + Default>(console: &mut Console<C, D>, sat_type: SaturationModeEnum, console_type: ConsoleMode) -> f32 {
+    console.update_vals(0.5, 0.6, 0.0, 0.4, SAMPLE_RATE);
+    console.set_slew_rate(0.5);
+    console.set_harmonics(&[0.1; duro_dsp::harmonics::NUM_HARMONICS], &[0.0; duro_dsp::harmonics::NUM_HARMONICS]);
+    let mut state = 1u32;
+    let mut peak = 0.0f32;
+    for _ in 0..FRAMES {
+        for channel in 0..2 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let sample = state as f32 / u32::MAX as f32 - 0.5;
+            let output = console.duro_process(sample, channel, sat_type, console_type);
+            assert!(output.is_finite(), "{sat_type:?} into {console_type:?} is not finite");
+            peak = peak.max(output.abs());
+        }
+    }
+    peak
+}
+
+#[test]
+fn no_mode_allocates() {
+    let before = allocations();
+    let mut console: Console<Echo, Echo> = Console::new(0.5, 2.0, ConsoleMode::BYPASS, SAMPLE_RATE);
+    for sat in 0..SaturationModeEnum::COUNT {
+        for console_type in 0..ConsoleMode::COUNT {
+            play(&mut console, SaturationModeEnum::from_index(sat), ConsoleMode::from_index(console_type));
+        }
+    }
+    console.reset();
+    assert_eq!(allocations(), before, "the console allocated");
+}
+
+#[test]
+fn response_consoles_are_dry_without_a_response() {
+    // With nothing loaded both response consoles pass the signal through like bypass
+    let peak = |console_type| {
+        let mut console: Console = Console::new(0.5, 1.0, ConsoleMode::BYPASS, SAMPLE_RATE);
+        console.jump_to_modes(console_type, SaturationModeEnum::NONESAT);
+        play(&mut console, SaturationModeEnum::NONESAT, console_type)
+    };
+    let bypass = peak(ConsoleMode::BYPASS);
+    assert_eq!(peak(ConsoleMode::CONVOLUTION), bypass);
+    assert_eq!(peak(ConsoleMode::DYNAMIC), bypass);
+}
+
+#[test]
+fn channels_only_hear_their_own_delay_lines() {
+    // Noise on the left and silence on the right, nothing of the left may reach the right's taps
+    for console_type in [ConsoleMode::NEVE, ConsoleMode::API, ConsoleMode::PRECISION, ConsoleMode::LEAF, ConsoleMode::VINE, ConsoleMode::DURO, ConsoleMode::GENERATED] {
+        let mut console: Console = Console::new(0.5, 1.0, ConsoleMode::BYPASS, SAMPLE_RATE);
+        console.jump_to_modes(console_type, SaturationModeEnum::NONESAT);
+        console.update_vals(0.5, 1.0, 0.0, 1.0, SAMPLE_RATE);
+        for n in 0..FRAMES {
+            console.duro_process((n as f32 * 0.7).sin() * 0.5, 0, SaturationModeEnum::NONESAT, console_type);
+            let right = console.duro_process(0.0, 1, SaturationModeEnum::NONESAT, console_type);
+            assert_eq!(right, 0.0, "{console_type:?} leaked the left channel into the right at sample {n}");
+        }
+    }
+}
+
+#[test]
+fn default_formula_matches_compiling_it() {
+    let compiled = formula::compile(formula::DEFAULT_FORMULA).unwrap();
+    let built = Program::default_formula();
+    for step in -40..=40 {
+        let x = step as f32 / 10.0;
+        for drive in [0.0, 0.5, 1.0, 4.0] {
+            assert_eq!(built.evaluate(x, drive, 0.5).to_bits(), compiled.evaluate(x, drive, 0.5).to_bits());
+        }
+    }
+}
+
+#[test]
+fn every_mode_has_a_name_and_its_own_index() {
+    fn check<T: Mode + std::fmt::Debug>() {
+        assert_eq!(T::variants().len(), T::COUNT);
+        for index in 0..T::COUNT {
+            assert_eq!(T::from_index(index).to_index(), index);
+        }
+        assert_eq!(T::from_index(T::COUNT), T::from_index(T::COUNT - 1));
+    }
+    check::<SaturationModeEnum>();
+    check::<ConsoleMode>();
+    check::<duro_dsp::bus_compressor::DetectorMode>();
+    check::<duro_dsp::lofi::AntiAliasMode>();
+    check::<duro_dsp::fast_math::MathMode>();
+}
+
+#[test]
+fn switching_again_mid_fade_never_jumps() {
+    // A steady input settles every mode to a steady output, so any step between samples comes from the
+    // fades. Each gain of the equal power fade moves at most (pi / 2) / length per sample
+    let level = 0.5;
+    let length = CROSSFADE_MS * 0.001 * SAMPLE_RATE;
+    let mut console: Console = Console::new(0.5, 1.0, ConsoleMode::BYPASS, SAMPLE_RATE);
+    console.update_vals(0.5, 0.6, 0.0, 1.0, SAMPLE_RATE);
+    console.jump_to_modes(ConsoleMode::DURO, SaturationModeEnum::TAPESAT);
+    let mut previous = 0.0;
+    let mut biggest_step = 0.0f32;
+    let mut loudest = 0.0f32;
+    // Settle, then switch to new modes, again to others inside that fade and then back to the first ones
+    // before it lands, and once more to new modes while turning around
+    let switches = [
+        (0, ConsoleMode::DURO, SaturationModeEnum::TAPESAT),
+        (FRAMES, ConsoleMode::NEVE, SaturationModeEnum::DIGITAL),
+        (FRAMES + length as usize / 4, ConsoleMode::API, SaturationModeEnum::CANDLE),
+        (FRAMES + length as usize / 2, ConsoleMode::DURO, SaturationModeEnum::TAPESAT),
+        (FRAMES + 3 * length as usize / 4, ConsoleMode::LEAF, SaturationModeEnum::GOLDENCUBIC),
+    ];
+    for frame in 0..FRAMES * 2 {
+        let (_, console_type, sat_type) = *switches.iter().rev().find(|(at, _, _)| frame >= *at).unwrap();
+        let output = console.duro_process(level, 0, sat_type, console_type);
+        if frame > FRAMES / 2 {
+            loudest = loudest.max(output.abs());
+            if frame > FRAMES / 2 + 1 {
+                biggest_step = biggest_step.max((output - previous).abs());
+            }
+        }
+        previous = output;
+    }
+    let limit = 2.0 * loudest * PI / 2.0 / length;
+    assert!(biggest_step <= limit, "the output stepped by {biggest_step}, more than the fades allow ({limit})");
+}
+
+#[test]
+fn fading_between_harmonic_modes_follows_the_input_once() {
+    // With only odd harmonics set the Harmonic Generator and Odd Harmonics sound the same, so a fade between
+    // them is the steady mode at the fade's gains. The phase makes a wrong sign of sin(theta) heard
+    let mut levels = [0.0; NUM_HARMONICS];
+    let mut phases = [0.0; NUM_HARMONICS];
+    levels[1] = 0.5;
+    phases[1] = 90.0;
+    let console = || {
+        let mut console: Console = Console::new(0.5, 1.0, ConsoleMode::BYPASS, SAMPLE_RATE);
+        console.update_vals(0.0, 1.0, 0.0, 1.0, SAMPLE_RATE);
+        console.set_harmonics(&levels, &phases);
+        console.jump_to_modes(ConsoleMode::BYPASS, SaturationModeEnum::HARMONICS);
+        console
+    };
+    let mut steady = console();
+    let mut fading = console();
+    let mut fade = Crossfade::new(SaturationModeEnum::HARMONICS, SAMPLE_RATE);
+    for n in 0..FRAMES {
+        let sat_type = if n < FRAMES / 2 { SaturationModeEnum::HARMONICS } else { SaturationModeEnum::ODDHARMONICS };
+        fade.advance();
+        fade.update(sat_type);
+        let (previous_gain, current_gain) = fade.gains();
+        let gain = if fade.is_fading() { previous_gain + current_gain } else { 1.0 };
+
+        let sample = 0.5 * (2.0 * PI * 1000.0 * n as f32 / SAMPLE_RATE).sin();
+        let expected = gain * steady.duro_process(sample, 0, SaturationModeEnum::HARMONICS, ConsoleMode::BYPASS);
+        let output = fading.duro_process(sample, 0, sat_type, ConsoleMode::BYPASS);
+        assert!((output - expected).abs() < 0.01, "sample {n} is {output} in the fade instead of {expected}");
+    }
+}
+
+// Biggest difference a harmonic mode makes to a half scale sine, with the harmonic levels it starts with
+fn harmonics_added(sat_type: SaturationModeEnum, threshold: f32, drive: f32) -> f32 {
+    let mut console: Console = Console::new(0.5, 1.0, ConsoleMode::BYPASS, SAMPLE_RATE);
+    console.jump_to_modes(ConsoleMode::BYPASS, sat_type);
+    console.update_vals(threshold, drive, 0.0, 1.0, SAMPLE_RATE);
+    let mut added = 0.0f32;
+    for n in 0..FRAMES {
+        let sample = 0.5 * (2.0 * PI * 200.0 * n as f32 / SAMPLE_RATE).sin();
+        added = added.max((console.duro_process(sample, 0, sat_type, ConsoleMode::BYPASS) - sample).abs());
+    }
+    added
+}
+
+#[test]
+fn old_harmonic_modes_add_harmonics_past_the_threshold() {
+    // Sessions from before the harmonic spectrum load with its defaults, which still give these modes theirs
+    for sat_type in [SaturationModeEnum::ODDHARMONICS, SaturationModeEnum::FORTHHARM] {
+        assert!(harmonics_added(sat_type, 0.1, 1.0) > 0.1, "{sat_type:?} adds nothing by default");
+        // Half the drive, half the harmonics
+        let ratio = harmonics_added(sat_type, 0.1, 0.5) / harmonics_added(sat_type, 0.1, 1.0);
+        assert!((ratio - 0.5).abs() < 0.01, "{sat_type:?} doesn't follow the drive");
+        // Nothing past the threshold, nothing added
+        assert!(harmonics_added(sat_type, 0.6, 1.0) < 1e-6, "{sat_type:?} works a signal below the threshold");
+    }
+}
+
+#[test]
+fn the_dc_blocker_only_runs_with_a_bias() {
+    let sine = |n: usize| 0.5 * (2.0 * PI * 200.0 * n as f32 / SAMPLE_RATE).sin();
+    let mut console: Console = Console::new(0.5, 1.0, ConsoleMode::BYPASS, SAMPLE_RATE);
+    console.update_vals(0.3, 1.0, 0.0, 1.0, SAMPLE_RATE);
+    console.jump_to_modes(ConsoleMode::BYPASS, SaturationModeEnum::CANDLE);
+    let play = |console: &mut Console, frames: std::ops::Range<usize>| -> Vec<(f32, f32)> {
+        frames.map(|n| (console.duro_process(sine(n), 0, SaturationModeEnum::CANDLE, ConsoleMode::BYPASS), candle_saturation(sine(n), 1.0, 0.3))).collect()
+    };
+
+    // Without a bias Candle keeps the offset it always had
+    for (output, curve) in play(&mut console, 0..FRAMES) {
+        assert_eq!(output, curve);
+    }
+    // With one the offset is taken away once the 10Hz blocker has settled
+    console.set_bias(0.2);
+    let biased = play(&mut console, FRAMES..24 * FRAMES);
+    let settled = &biased[biased.len() - 2400..];
+    let mean = settled.iter().map(|(output, _)| output).sum::<f32>() / settled.len() as f32;
+    assert!(mean.abs() < 0.01, "{mean} of DC is left with a bias");
+    // And back without a bias the blocker fades out and lets the curve through as it is again
+    console.set_bias(0.0);
+    let length = (CROSSFADE_MS * 0.001 * SAMPLE_RATE) as usize;
+    let unbiased = play(&mut console, 24 * FRAMES..25 * FRAMES);
+    for (output, curve) in &unbiased[length + 1..] {
+        assert_eq!(output, curve);
+    }
+}
+
+// How far a console is from linear: the largest difference between twice the output of a quiet sine and
+// the output of one twice as loud
+fn console_nonlinearity(console_threshold: f32) -> f32 {
+    let output = |level: f32| -> Vec<f32> {
+        let mut console: Console = Console::new(0.5, 1.0, ConsoleMode::BYPASS, SAMPLE_RATE);
+        console.jump_to_modes(ConsoleMode::NEVE, SaturationModeEnum::NONESAT);
+        console.update_vals(0.5, 1.0, console_threshold, 1.0, SAMPLE_RATE);
+        (0..FRAMES).map(|n| console.duro_process(level * (2.0 * PI * 200.0 * n as f32 / SAMPLE_RATE).sin(), 0, SaturationModeEnum::NONESAT, ConsoleMode::NEVE)).collect()
+    };
+    output(0.1).iter().zip(output(0.2)).map(|(quiet, loud)| (2.0 * quiet - loud).abs()).fold(0.0, f32::max)
+}
+
+#[test]
+fn the_console_threshold_keeps_quieter_signals_linear() {
+    assert!(console_nonlinearity(0.0) > 1e-4, "the console taps have no nonlinear part to keep out");
+    assert!(console_nonlinearity(0.5) < 1e-6, "a signal below the console threshold was colored");
+}
+
+// Peak of a full scale sine after the Slew Limit, once it has settled
+fn slew_limited_peak(slew_rate: f32, drive: f32, frequency: f32) -> f32 {
+    let mut console: Console = Console::new(1.0, 1.0, ConsoleMode::BYPASS, SAMPLE_RATE);
+    console.jump_to_modes(ConsoleMode::BYPASS, SaturationModeEnum::SLEW);
+    console.update_vals(1.0, drive, 0.0, 0.0, SAMPLE_RATE);
+    console.set_slew_rate(slew_rate);
+    let mut peak = 0.0f32;
+    for n in 0..FRAMES {
+        let output = console.duro_process((2.0 * PI * frequency * n as f32 / SAMPLE_RATE).sin(), 0, SaturationModeEnum::SLEW, ConsoleMode::BYPASS);
+        if n >= FRAMES / 2 {
+            peak = peak.max(output.abs());
+        }
+    }
+    peak
+}
+
+#[test]
+fn the_op_amps_on_the_slew_knob_are_heard() {
+    // A 741 leaves the lows alone and turns the upper mids into a quieter triangle
+    assert!(slew_limited_peak(0.5, 0.0, 200.0) > 0.98);
+    assert!(slew_limited_peak(0.5, 0.0, 2000.0) < 0.75);
+    // An NE5534 only gives out on the highs, and with drive pushing it
+    assert!(slew_limited_peak(13.0, 0.0, 5000.0) > 0.98);
+    assert!(slew_limited_peak(13.0, 2.0, 8000.0) < 0.9);
+    // The top of the knob stays clean all the way up
+    assert!(slew_limited_peak(20.0, 2.0, 5000.0) > 0.98);
+}
@@ -0,0 +1,84 @@
+// fast_math.rs - Ardura 2023
+// The fast_math approximations against the std functions they stand in for
+//  Each function is swept across its working range and held to the error its doc comment states, then
+//  the curves that use them are compared fast against exact across the drive and threshold knobs
+
+use duro_dsp::{duro_process, fast_math, util};
+
+const POINTS: usize = 1_000_000;
+
+// A saturation curve as (input, threshold, drive, fast)
+type Curve = fn(f32, f32, f32, bool) -> f32;
+
+// Evenly spaced points from low to high, both ends included
+fn sweep(low: f32, high: f32, points: usize) -> impl Iterator<Item = f32> {
+    (0..=points).map(move |i| low + (high - low) * i as f32 / points as f32)
+}
+
+#[test]
+fn tanh_stays_within_its_stated_error() {
+    let (mut absolute, mut relative) = (0.0f32, 0.0f32);
+    for x in sweep(-20.0, 20.0, POINTS).chain(sweep(-0.5, 0.5, POINTS)) {
+        let error = (fast_math::tanh(x) - x.tanh()).abs();
+        absolute = absolute.max(error);
+        if x != 0.0 {
+            relative = relative.max(error / x.tanh().abs());
+        }
+    }
+    assert!(absolute <= 2.4e-7, "tanh is off by {absolute}");
+    assert!(relative <= 6e-7, "tanh is off by {relative} relative");
+}
+
+#[test]
+fn atan_stays_within_its_stated_error() {
+    let mut absolute = 0.0f32;
+    for x in sweep(-1000.0, 1000.0, POINTS).chain(sweep(-2.0, 2.0, POINTS)) {
+        absolute = absolute.max((fast_math::atan(x) - x.atan()).abs());
+    }
+    assert!(absolute <= 1.2e-5, "atan is off by {absolute} radians");
+}
+
+#[test]
+fn exp_stays_within_its_stated_error() {
+    let mut relative = 0.0f32;
+    for x in sweep(-87.0, 88.0, POINTS) {
+        relative = relative.max(((fast_math::exp(x) - x.exp()) / x.exp()).abs());
+    }
+    assert!(relative <= 2.6e-7, "exp is off by {relative} relative");
+    assert_eq!(fast_math::exp(-100.0), 0.0);
+    assert_eq!(fast_math::exp(100.0), f32::INFINITY);
+    assert!(fast_math::exp(f32::NAN).is_nan());
+}
+
+#[test]
+fn db_to_gain_stays_within_its_stated_error() {
+    let mut relative = 0.0f32;
+    for dbs in sweep(-99.0, 60.0, POINTS) {
+        let exact = util::db_to_gain(dbs);
+        relative = relative.max(((fast_math::db_to_gain(dbs) - exact) / exact).abs());
+    }
+    assert!(relative <= 1.5e-6, "db_to_gain is off by {relative} relative");
+}
+
+#[test]
+fn fast_curves_stay_within_minus_100db_of_exact() {
+    // What the README promises for the Fast math mode, Leaf is the worst of the four at about -102dB
+    let curves: [(&str, Curve); 4] = [
+        ("Tape", |x, threshold, drive, fast| duro_process::tape_saturation(x, drive, threshold, fast)),
+        ("Leaf", duro_process::leaf_saturation),
+        ("Golden Cubic", duro_process::golden_cubic),
+        ("Transformer", duro_process::transformer_saturation),
+    ];
+    for (name, curve) in curves {
+        let mut difference = 0.0f32;
+        // Inputs to 12dB past full scale over the whole threshold and drive knobs
+        for x in sweep(-4.0, 4.0, 2000) {
+            for threshold in sweep(util::db_to_gain(-30.0), 1.0, 20) {
+                for drive in sweep(0.0, 2.0, 20) {
+                    difference = difference.max((curve(x, threshold, drive, true) - curve(x, threshold, drive, false)).abs());
+                }
+            }
+        }
+        assert!(difference <= 1e-5, "{name} fast is {}dB off exact", util::gain_to_db(difference));
+    }
+}
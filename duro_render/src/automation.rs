@@ -0,0 +1,138 @@
+// automation.rs - Ardura 2023
+// Parameter automation for duro-render from a breakpoint file, evaluated like a host's automation lanes
+//  CSV rows are time,id,value with the time in seconds. JSON is an object of lanes, each a list of
+//  {"time": seconds, "value": value} points. Values are typed the same way as the command line flags
+
+use std::path::Path;
+
+use Duro_Console::{chain::ChainSettings, GainParams};
+
+// The one parameter that changes the plugin's latency
+const LATENCY_PARAM: &str = "ceiling_mode";
+
+struct Point {
+    time: f64,
+    // Automated values are kept as the 0 to 1 value a host automates
+    value: f32,
+}
+
+struct Lane {
+    id: String,
+    stepped: bool,
+    points: Vec<Point>,
+}
+
+impl Lane {
+    // Holds the first point's value before it and the last one's after. Continuous parameters
+    // ramp evenly across the knob's travel between points, stepped ones change at each point
+    fn value_at(&self, time: f64) -> f32 {
+        let next = self.points.partition_point(|point| point.time <= time);
+        if next == 0 {
+            return self.points[0].value;
+        }
+        let previous = &self.points[next - 1];
+        match self.points.get(next) {
+            Some(point) if !self.stepped => {
+                let position = (time - previous.time) / (point.time - previous.time);
+                previous.value + (point.value - previous.value) * position as f32
+            }
+            _ => previous.value,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Automation {
+    lanes: Vec<Lane>,
+}
+
+impl Automation {
+    pub fn load(path: &Path, params: &GainParams) -> Result<Self, String> {
+        let name = path.display();
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Could not read {name}: {e}"))?;
+        let is_json = path.extension().map(|ext| ext.eq_ignore_ascii_case("json")).unwrap_or(false);
+        let points = if is_json { parse_json(&text) } else { parse_csv(&text) }.map_err(|e| format!("{name}: {e}"))?;
+
+        let mut automation = Automation::default();
+        for (time, id, value) in points {
+            let normalized = params.parse_normalized(&id, &value).map_err(|e| format!("{name}: {e}"))?;
+            if !time.is_finite() || time < 0.0 {
+                return Err(format!("{name}: {id} has a point at {time} s, times start at 0"));
+            }
+            let point = Point { time, value: normalized };
+            match automation.lanes.iter_mut().find(|lane| lane.id == id) {
+                Some(lane) => lane.points.push(point),
+                None => automation.lanes.push(Lane {
+                    stepped: params.is_stepped(&id)?,
+                    id,
+                    points: vec![point],
+                }),
+            }
+        }
+        // Points at the same time stay in file order so two of them make a jump
+        for lane in automation.lanes.iter_mut() {
+            lane.points.sort_by(|a, b| a.time.total_cmp(&b.time));
+        }
+        // The output is lined up by taking the latency out once, so it can't change partway through
+        if let Some(lane) = automation.lanes.iter().find(|lane| lane.id == LATENCY_PARAM) {
+            if lane.points.iter().any(|point| point.value != lane.points[0].value) {
+                return Err(format!("{name}: {LATENCY_PARAM} changes the latency, give it one value with --{LATENCY_PARAM} instead"));
+            }
+        }
+        Ok(automation)
+    }
+
+    // The base settings with every automated parameter at its value for this time
+    pub fn settings_at(&self, params: &GainParams, base: &ChainSettings, time: f64) -> ChainSettings {
+        let mut settings = *base;
+        for lane in self.lanes.iter() {
+            // The ids were all checked when the file loaded
+            let _ = params.set_normalized(&mut settings, &lane.id, lane.value_at(time));
+        }
+        settings
+    }
+}
+
+fn parse_csv(text: &str) -> Result<Vec<(f64, String, String)>, String> {
+    let mut points = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut columns = line.splitn(3, ',').map(|column| column.trim().trim_matches('"'));
+        let (Some(time), Some(id), Some(value)) = (columns.next(), columns.next(), columns.next()) else {
+            return Err(format!("line {}: expected time,id,value", number + 1));
+        };
+        match time.parse::<f64>() {
+            Ok(time) => points.push((time, id.to_string(), value.to_string())),
+            // A header row
+            Err(_) if points.is_empty() && time.eq_ignore_ascii_case("time") => {}
+            Err(_) => return Err(format!("line {}: '{time}' is not a time in seconds", number + 1)),
+        }
+    }
+    Ok(points)
+}
+
+fn parse_json(text: &str) -> Result<Vec<(f64, String, String)>, String> {
+    let json: serde_json::Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let lanes = json.as_object().ok_or("expected an object of parameter lanes")?;
+    let mut points = Vec::new();
+    for (id, lane) in lanes {
+        let lane = lane.as_array().ok_or_else(|| format!("{id} should be a list of points"))?;
+        for point in lane {
+            let time = point.get("time").and_then(|time| time.as_f64());
+            let value = point.get("value").and_then(|value| match value {
+                serde_json::Value::String(text) => Some(text.clone()),
+                serde_json::Value::Number(number) => Some(number.to_string()),
+                serde_json::Value::Bool(on) => Some(on.to_string()),
+                _ => None,
+            });
+            let (Some(time), Some(value)) = (time, value) else {
+                return Err(format!("{id} points need a time in seconds and a value"));
+            };
+            points.push((time, id.clone(), value));
+        }
+    }
+    Ok(points)
+}
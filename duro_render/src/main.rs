@@ -0,0 +1,277 @@
+// main.rs - Ardura 2023
+// duro-render: runs WAV files through the Duro Console signal chain offline, no host needed
+//  Every plugin parameter is a flag or a line in a preset file, typed the same way as in a host
+
+mod automation;
+mod wav;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use Duro_Console::{
+    chain::{ChainSettings, DuroChain},
+    convolution::{self, Convolver},
+    custom_curve::{self, SharedCurve},
+    dynamic_convolution::{self, DynamicConvolver},
+    formula::{self, SharedFormula},
+    GainParams,
+};
+
+use automation::Automation;
+
+// Frames between updates of what the plugin only reads once per buffer, like the Generated seed
+const BLOCK_SIZE: usize = 512;
+
+const USAGE: &str = "Usage: duro-render [OPTIONS] <INPUT.wav>...
+
+Options:
+  -o, --output <PATH>      Output file for one input, or a folder for several
+                           (default: <input>_duro.wav next to each input)
+  --preset <FILE>          Read settings from a file of 'id = value' lines, # starts a comment
+  --automation <FILE>      Automate parameters from a CSV (time,id,value) or JSON breakpoint file
+  --format <16|24|32f>     Output sample format (default: the input's)
+  --list                   List every parameter id with its default value
+  --<id> <VALUE>           Set a parameter, like --type Tape or --drive 50
+  --formula <TEXT>         Formula for the Formula saturation
+  --curve <\"X,Y X,Y ...\">  Points of the Custom Curve saturation
+  --ir <FILE>              Impulse response for the Impulse Response console
+  --kernels <FOLDER>       Kernel folder for the Dynamic Response console
+  -h, --help               Show this help
+
+Flags given on the command line override the preset.";
+
+// Everything the plugin keeps in its state besides the parameters
+#[derive(Default)]
+struct ConsoleState {
+    formula: Option<String>,
+    curve: Option<String>,
+    ir: Option<String>,
+    kernels: Option<String>,
+}
+
+struct Options {
+    inputs: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    format: Option<wav::Format>,
+    settings: ChainSettings,
+    state: ConsoleState,
+    automation: Automation,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let params = GainParams::default();
+
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    if args.iter().any(|arg| arg == "--list") {
+        for (id, name, default) in params.describe() {
+            println!("{id:<20} {default:<24} {name}");
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    let result = parse_args(&params, &args).and_then(|options| {
+        let outputs = output_paths(&options)?;
+        for (input, output) in options.inputs.iter().zip(outputs.iter()) {
+            render_file(&params, input, output, &options)?;
+            println!("{} -> {}", input.display(), output.display());
+        }
+        Ok(())
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("duro-render: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(params: &GainParams, args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        inputs: Vec::new(),
+        output: None,
+        format: None,
+        settings: params.settings(),
+        state: ConsoleState::default(),
+        automation: Automation::default(),
+    };
+
+    // Pair up flags with their values first so the preset can be applied before any flag
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    let mut preset = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--").or_else(|| arg.strip_prefix('-').filter(|flag| *flag == "o")) else {
+            options.inputs.push(PathBuf::from(arg));
+            continue;
+        };
+        let (key, value) = match flag.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => {
+                let value = args.next().ok_or_else(|| format!("{arg} needs a value"))?;
+                (flag.to_string(), value.clone())
+            }
+        };
+        match key.as_str() {
+            "o" | "output" => options.output = Some(PathBuf::from(value)),
+            "format" => options.format = Some(wav::Format::parse(&value)?),
+            "preset" => preset = Some(value),
+            "automation" => options.automation = Automation::load(Path::new(&value), params)?,
+            _ => pairs.push((key, value)),
+        }
+    }
+    if options.inputs.is_empty() {
+        return Err("No input files, see --help".to_string());
+    }
+
+    if let Some(preset) = preset {
+        let text = fs::read_to_string(&preset).map_err(|e| format!("Could not read preset {preset}: {e}"))?;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("{preset} line {}: expected 'id = value'", number + 1))?;
+            apply(params, &mut options, key.trim(), value.trim()).map_err(|e| format!("{preset} line {}: {e}", number + 1))?;
+        }
+    }
+    for (key, value) in pairs {
+        apply(params, &mut options, &key, &value)?;
+    }
+    Ok(options)
+}
+
+fn apply(params: &GainParams, options: &mut Options, key: &str, value: &str) -> Result<(), String> {
+    let state = &mut options.state;
+    match key {
+        "formula" => state.formula = Some(value.to_string()),
+        "curve" => state.curve = Some(value.to_string()),
+        "ir" => state.ir = Some(value.to_string()),
+        "kernels" => state.kernels = Some(value.to_string()),
+        _ => params.set_from_string(&mut options.settings, key, value)?,
+    }
+    Ok(())
+}
+
+// Where each input is written to
+fn output_paths(options: &Options) -> Result<Vec<PathBuf>, String> {
+    let default_name = |input: &Path| {
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        PathBuf::from(format!("{stem}_duro.wav"))
+    };
+    let into_folder = match &options.output {
+        None => None,
+        // A trailing slash asks for a folder that doesn't exist yet
+        Some(output) if options.inputs.len() > 1 || output.is_dir() || output.to_string_lossy().ends_with(['/', '\\']) => {
+            fs::create_dir_all(output).map_err(|e| format!("Could not create {}: {e}", output.display()))?;
+            Some(output.clone())
+        }
+        Some(output) => return Ok(vec![output.clone()]),
+    };
+    Ok(options
+        .inputs
+        .iter()
+        .map(|input| {
+            let folder = into_folder.clone().unwrap_or_else(|| input.parent().unwrap_or(Path::new("")).to_path_buf());
+            folder.join(default_name(input))
+        })
+        .collect())
+}
+
+fn render_file(params: &GainParams, input: &Path, output: &Path, options: &Options) -> Result<(), String> {
+    let audio = wav::read(input)?;
+    let sample_rate = audio.sample_rate as f32;
+    let settings_at = |frame: usize| options.automation.settings_at(params, &options.settings, frame as f64 / sample_rate as f64);
+
+    // Start settled at the first frame's settings, then automation moves through the plugin's smoothers
+    let mut target = settings_at(0);
+    params.reset_smoothers(&target);
+    let mut chain = prepare_chain(&target, &options.state, sample_rate)?;
+
+    // The ceiling delays the signal, run that much silence through after the file and drop the
+    // same amount from the start so the output lines up with the input
+    let latency = chain.latency_samples() as usize;
+    let channels = audio.channels;
+    let mut samples = Vec::with_capacity(audio.samples.len());
+    let mut frame = vec![0.0; channels];
+    for index in 0..audio.frames() + latency {
+        let previous = target;
+        target = settings_at(index);
+        let settings = params.next_settings_towards(&previous, &target, sample_rate);
+        if index % BLOCK_SIZE == 0 {
+            // Rendering is never realtime, so Auto math is exact, and the generated console can be built right here
+            chain.generate_console(&settings);
+            chain.start_block(&settings, false);
+        }
+
+        match audio.samples.get(index * channels..(index + 1) * channels) {
+            Some(input) => frame.copy_from_slice(input),
+            None => frame.iter_mut().for_each(|sample| *sample = 0.0),
+        }
+        // Automation can't move the ceiling mode, so the latency taken out above holds for the whole file
+        let latency_changed = chain.process_frame(&mut frame, &settings);
+        debug_assert!(!latency_changed, "the latency changed partway through the render");
+        if index >= latency {
+            samples.extend_from_slice(&frame);
+        }
+    }
+
+    wav::write(
+        output,
+        &wav::Audio {
+            samples,
+            channels,
+            sample_rate: audio.sample_rate,
+            format: options.format.unwrap_or(audio.format),
+        },
+    )
+}
+
+// A chain at the file's rate with the formula, curve and responses loaded
+fn prepare_chain(settings: &ChainSettings, state: &ConsoleState, sample_rate: f32) -> Result<DuroChain, String> {
+    let mut chain = DuroChain::new(sample_rate);
+    chain.update_ceiling(settings.ceiling_mode, settings.ceiling);
+    chain.jump_to(settings);
+
+    let console = chain.console_mut();
+    if let Some(text) = &state.formula {
+        let program = formula::compile(text).map_err(|e| format!("Formula: {e}"))?;
+        console.sync_formula(&SharedFormula::new(&program));
+    }
+    if let Some(text) = &state.curve {
+        let mut points = parse_curve(text)?;
+        custom_curve::sanitize_points(&mut points);
+        console.sync_custom_curve(&SharedCurve::new(&points));
+    }
+    if let Some(path) = &state.ir {
+        let impulse = convolution::load_impulse(path, sample_rate)?;
+        console.swap_convolver(Box::new(Convolver::new(&impulse)));
+    }
+    if let Some(folder) = &state.kernels {
+        let kernels = dynamic_convolution::load_kernels(folder, sample_rate)?;
+        console.swap_dynamic(Box::new(DynamicConvolver::new(&kernels, sample_rate)));
+    }
+    Ok(chain)
+}
+
+// Curve points written as "x,y x,y ..."
+fn parse_curve(text: &str) -> Result<Vec<(f32, f32)>, String> {
+    text.split_whitespace()
+        .map(|point| {
+            let (x, y) = point.split_once(',').ok_or_else(|| format!("Curve point '{point}' should be x,y"))?;
+            match (x.parse::<f32>(), y.parse::<f32>()) {
+                (Ok(x), Ok(y)) => Ok((x, y)),
+                _ => Err(format!("Curve point '{point}' should be two numbers")),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,100 @@
+// wav.rs - Ardura 2023
+// Reading and writing the WAV files duro-render works on, samples are interleaved f32 at full scale 1.0
+
+use std::path::Path;
+
+// Sample formats duro-render writes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl Format {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.trim() {
+            "16" => Ok(Format::Int16),
+            "24" => Ok(Format::Int24),
+            "32f" | "32" | "float" => Ok(Format::Float32),
+            _ => Err(format!("Unknown format '{text}', use 16, 24 or 32f")),
+        }
+    }
+
+    // Closest format we write to what a file was read as
+    fn closest(spec: &hound::WavSpec) -> Self {
+        match (spec.sample_format, spec.bits_per_sample) {
+            (hound::SampleFormat::Float, _) => Format::Float32,
+            (hound::SampleFormat::Int, bits) if bits <= 16 => Format::Int16,
+            _ => Format::Int24,
+        }
+    }
+}
+
+pub struct Audio {
+    pub samples: Vec<f32>,
+    pub channels: usize,
+    pub sample_rate: u32,
+    pub format: Format,
+}
+
+impl Audio {
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+}
+
+pub fn read(path: &Path) -> Result<Audio, String> {
+    let name = path.display();
+    let mut reader = hound::WavReader::open(path).map_err(|e| format!("Could not open {name}: {e}"))?;
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+    if channels == 0 || channels > Duro_Console::duro_process::MAX_CHANNELS {
+        return Err(format!("{name} has {channels} channels, only mono and stereo are supported"));
+    }
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<f32>, _>>(),
+        hound::SampleFormat::Int => {
+            let full_scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader.samples::<i32>().map(|s| s.map(|s| s as f32 / full_scale)).collect::<Result<Vec<f32>, _>>()
+        }
+    }
+    .map_err(|e| format!("Could not read {name}: {e}"))?;
+
+    Ok(Audio {
+        samples,
+        channels,
+        sample_rate: spec.sample_rate,
+        format: Format::closest(&spec),
+    })
+}
+
+pub fn write(path: &Path, audio: &Audio) -> Result<(), String> {
+    let name = path.display();
+    let (bits_per_sample, sample_format) = match audio.format {
+        Format::Int16 => (16, hound::SampleFormat::Int),
+        Format::Int24 => (24, hound::SampleFormat::Int),
+        Format::Float32 => (32, hound::SampleFormat::Float),
+    };
+    let spec = hound::WavSpec {
+        channels: audio.channels as u16,
+        sample_rate: audio.sample_rate,
+        bits_per_sample,
+        sample_format,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).map_err(|e| format!("Could not create {name}: {e}"))?;
+    let result = match audio.format {
+        Format::Float32 => audio.samples.iter().try_for_each(|sample| writer.write_sample(*sample)),
+        Format::Int16 | Format::Int24 => {
+            // Anything past full scale clips here, the same as it would in a fixed point host
+            let full_scale = (1_i64 << (bits_per_sample - 1)) as f32;
+            audio
+                .samples
+                .iter()
+                .try_for_each(|sample| writer.write_sample((sample * full_scale).round().clamp(-full_scale, full_scale - 1.0) as i32))
+        }
+    };
+    result
+        .and_then(|_| writer.finalize())
+        .map_err(|e| format!("Could not write {name}: {e}"))
+}
@@ -0,0 +1,153 @@
+// console.rs - Ardura 2023
+// Fuzz target for the signal chain: arbitrary audio with parameter, mode and formula changes in between
+//  Fails on a panic, a sample that isn't finite, or a chain that sounds different after reset() than a
+//  new one in the same settings would. Run with cargo +nightly fuzz run console
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use Duro_Console::{
+    chain::{ChainSettings, DuroChain},
+    convolution::Convolver,
+    dynamic_convolution::DynamicConvolver,
+    formula::{self, Program, SharedFormula},
+    GainParams,
+};
+
+const SAMPLE_RATES: [f32; 5] = [22050.0, 44100.0, 48000.0, 96000.0, 192000.0];
+// Samples come in as i16 and go up to +-4, past full scale like a hot input gain
+const SAMPLE_SCALE: f32 = 4.0 / i16::MAX as f32;
+// Longest impulse response tried, long enough for the convolver's frequency domain partitions
+const MAX_IMPULSE: usize = 512;
+// Noise played through the reset and the new chain, several convolver blocks long
+const PROBE_FRAMES: usize = 512;
+
+#[derive(Arbitrary, Debug)]
+enum Step {
+    // Interleaved when stereo
+    Play(Vec<i16>),
+    // Index into the parameter list and a normalized value
+    Set(u8, u16),
+    // Anything typed into the Formula box, only ones that compile reach the chain
+    Formula(String),
+    // Start of a host buffer, where the generated console, math mode and formula are picked up
+    Block { realtime: bool },
+    Reset,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    stereo: bool,
+    sample_rate: u8,
+    // Loaded into the Impulse Response console, and at two levels into the Dynamic Response console
+    impulse: Vec<i16>,
+    steps: Vec<Step>,
+}
+
+struct Session {
+    params: GainParams,
+    ids: Vec<String>,
+    sample_rate: f32,
+    channels: usize,
+    impulse: Vec<f32>,
+    formula: SharedFormula,
+    realtime: bool,
+}
+
+impl Session {
+    // A chain as the plugin would have it after loading this session
+    fn new_chain(&self, settings: &ChainSettings) -> DuroChain {
+        let mut chain = DuroChain::new(self.sample_rate);
+        if !self.impulse.is_empty() {
+            let quiet: Vec<f32> = self.impulse.iter().map(|x| x * 0.5).collect();
+            let console = chain.console_mut();
+            console.swap_convolver(Box::new(Convolver::new(&self.impulse)));
+            console.swap_dynamic(Box::new(DynamicConvolver::new(&[(-24.0, quiet), (-6.0, self.impulse.clone())], self.sample_rate)));
+        }
+        chain.update_ceiling(settings.ceiling_mode, settings.ceiling);
+        self.start(&mut chain, settings);
+        chain
+    }
+
+    fn start(&self, chain: &mut DuroChain, settings: &ChainSettings) {
+        chain.jump_to(settings);
+        chain.generate_console(settings);
+        chain.start_block(settings, self.realtime);
+        chain.console_mut().sync_formula(&self.formula);
+    }
+
+    fn play(&self, chain: &mut DuroChain, settings: &ChainSettings, samples: &[f32]) -> Vec<f32> {
+        let mut output = samples.to_vec();
+        for frame in output.chunks_exact_mut(self.channels) {
+            chain.process_frame(frame, settings);
+            assert!(frame.iter().all(|x| x.is_finite()), "{frame:?} is not finite with {settings:?}");
+        }
+        output
+    }
+}
+
+fn probe(frames: usize) -> Vec<f32> {
+    let mut state = 12463u32;
+    (0..frames)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32 * 2.0 - 1.0
+        })
+        .collect()
+}
+
+fuzz_target!(|input: Input| {
+    let params = GainParams::default();
+    let ids = params.describe().into_iter().map(|(id, _, _)| id).collect();
+    let mut settings = params.settings();
+    let mut session = Session {
+        params,
+        ids,
+        sample_rate: SAMPLE_RATES[input.sample_rate as usize % SAMPLE_RATES.len()],
+        channels: if input.stereo { 2 } else { 1 },
+        impulse: input.impulse.iter().take(MAX_IMPULSE).map(|x| *x as f32 * SAMPLE_SCALE).collect(),
+        formula: SharedFormula::new(&formula::compile(formula::DEFAULT_FORMULA).unwrap_or_else(|_| Program::passthrough())),
+        realtime: false,
+    };
+    let mut chain = session.new_chain(&settings);
+
+    for step in input.steps {
+        match step {
+            Step::Play(samples) => {
+                let samples: Vec<f32> = samples.iter().map(|x| *x as f32 * SAMPLE_SCALE).collect();
+                session.play(&mut chain, &settings, &samples);
+            }
+            Step::Set(index, value) => {
+                let id = &session.ids[index as usize % session.ids.len()];
+                session.params.set_normalized(&mut settings, id, value as f32 / u16::MAX as f32).unwrap();
+            }
+            Step::Formula(text) => {
+                if let Ok(program) = formula::compile(&text) {
+                    session.formula.write(&program);
+                }
+            }
+            Step::Block { realtime } => {
+                session.realtime = realtime;
+                chain.generate_console(&settings);
+                chain.start_block(&settings, realtime);
+                chain.console_mut().sync_formula(&session.formula);
+            }
+            Step::Reset => {
+                // Nothing that played before a reset may leak into what plays after it
+                chain.reset();
+                chain.update_ceiling(settings.ceiling_mode, settings.ceiling);
+                session.start(&mut chain, &settings);
+                let mut fresh = session.new_chain(&settings);
+                let signal = probe(PROBE_FRAMES * session.channels);
+                let after_reset = session.play(&mut chain, &settings, &signal);
+                let from_new = session.play(&mut fresh, &settings, &signal);
+                if let Some(n) = (0..signal.len()).find(|n| after_reset[*n].to_bits() != from_new[*n].to_bits()) {
+                    panic!("sample {n} is {} after a reset and {} from a new chain with {settings:?}", after_reset[n], from_new[n]);
+                }
+            }
+        }
+    }
+});
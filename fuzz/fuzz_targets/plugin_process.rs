@@ -0,0 +1,137 @@
+// plugin_process.rs - Ardura 2023
+// Fuzz target for the plugin's process loop: the engine Gain::process runs, with arbitrary audio, sample
+// accurate parameter changes through the plugin's smoothers, resets, restarts and editor handoffs
+//  Fails on a panic or a sample that isn't finite. No host needed, the parameters move the way
+//  duro-render's automation moves them. Run with cargo +nightly fuzz run plugin_process
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use Duro_Console::{
+    chain::ChainSettings,
+    console_models,
+    convolution::{self, Convolver},
+    custom_curve,
+    dynamic_convolution::DynamicConvolver,
+    engine::{Engine, Handoff},
+    formula, GainParams,
+};
+
+const SAMPLE_RATES: [f32; 5] = [22050.0, 44100.0, 48000.0, 96000.0, 192000.0];
+const MAX_BLOCK: usize = 4096;
+// Samples come in as i16 and go up to +-4, past full scale like a hot input gain
+const SAMPLE_SCALE: f32 = 4.0 / i16::MAX as f32;
+// Longest impulse response tried, long enough for the convolver's frequency domain partitions
+const MAX_IMPULSE: usize = 512;
+
+#[derive(Arbitrary, Debug)]
+enum Step {
+    // Frames in the block, audio repeated to fill it and (sample, parameter index, normalized value) changes
+    Process { frames: u16, samples: Vec<i16>, changes: Vec<(u16, u8, u16)>, metering: bool },
+    // What a host does on a transport jump
+    Reset,
+    // Deactivate and activate again, like after a latency change
+    Restart,
+    // Points dragged in the curve editor
+    Curve(Vec<(i16, i16)>),
+    // Anything typed into the Formula box, only ones that compile get handed over
+    Formula(String),
+    // A loaded impulse response, and at two levels the Dynamic Response kernels
+    Impulse(Vec<i16>),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    stereo: bool,
+    sample_rate: u8,
+    max_frames: u16,
+    offline: bool,
+    steps: Vec<Step>,
+}
+
+// What Gain::initialize and Gain::reset do with the engine
+fn activate(engine: &mut Engine, params: &GainParams, settings: &ChainSettings, sample_rate: f32) {
+    params.reset_smoothers(settings);
+    engine.meters.set_sample_rate(sample_rate);
+    engine.chain.set_sample_rate(sample_rate);
+    engine.chain.update_ceiling(settings.ceiling_mode, settings.ceiling);
+    reset(engine, settings);
+}
+
+fn reset(engine: &mut Engine, settings: &ChainSettings) {
+    engine.chain.reset();
+    engine.chain.jump_to(settings);
+    engine.meters.clear();
+}
+
+fuzz_target!(|input: Input| {
+    let params = GainParams::default();
+    let ids: Vec<String> = params.describe().into_iter().map(|(id, _, _)| id).collect();
+    let sample_rate = SAMPLE_RATES[input.sample_rate as usize % SAMPLE_RATES.len()];
+    let channels = if input.stereo { 2 } else { 1 };
+    let max_frames = 1 + input.max_frames as usize % MAX_BLOCK;
+
+    let mut target = params.settings();
+    let handoff = Handoff::new(target.console_seed, target.console_tilt);
+    let mut generated_from = (target.console_seed, target.console_tilt);
+    let mut engine = Engine::new(sample_rate, handoff.clone());
+    activate(&mut engine, &params, &target, sample_rate);
+
+    for step in input.steps {
+        match step {
+            Step::Process { frames, samples, mut changes, metering } => {
+                let frames = 1 + frames as usize % max_frames;
+                changes.sort_by_key(|(time, _, _)| *time as usize % frames);
+                let mut changes = changes.into_iter().peekable();
+
+                // The background thread builds a new seed or tilt, here it's ready right away
+                if (target.console_seed, target.console_tilt) != generated_from {
+                    generated_from = (target.console_seed, target.console_tilt);
+                    handoff.generated.write(&console_models::generate_taps(generated_from.0, generated_from.1));
+                }
+                engine.start_block(&target, !input.offline);
+
+                for n in 0..frames {
+                    let previous = target;
+                    while let Some((_, index, value)) = changes.next_if(|(time, _, _)| *time as usize % frames == n) {
+                        let id = &ids[index as usize % ids.len()];
+                        params.set_normalized(&mut target, id, value as f32 / u16::MAX as f32).unwrap();
+                    }
+                    let settings = params.next_settings_towards(&previous, &target, sample_rate);
+
+                    let mut frame = [0.0; 2];
+                    for (channel, sample) in frame[..channels].iter_mut().enumerate() {
+                        let index = (n * channels + channel) % samples.len().max(1);
+                        *sample = samples.get(index).map_or(0.0, |x| *x as f32 * SAMPLE_SCALE);
+                    }
+                    engine.process_frame(&mut frame[..channels], &settings, metering);
+                    if let Some(channel) = frame[..channels].iter().position(|x| !x.is_finite()) {
+                        panic!("channel {channel} sample {n} is {} with {settings:?}", frame[channel]);
+                    }
+                }
+            }
+            Step::Reset => reset(&mut engine, &target),
+            Step::Restart => activate(&mut engine, &params, &target, sample_rate),
+            Step::Curve(points) => {
+                let mut points: Vec<(f32, f32)> = points.iter().map(|(x, y)| (*x as f32 / i16::MAX as f32, *y as f32 / i16::MAX as f32)).collect();
+                custom_curve::sanitize_points(&mut points);
+                handoff.custom_curve.write(&custom_curve::build_table(&points));
+            }
+            Step::Formula(text) => {
+                if let Ok(program) = formula::compile(&text) {
+                    handoff.formula.write(&program);
+                }
+            }
+            Step::Impulse(samples) => {
+                let impulse: Vec<f32> = samples.iter().take(MAX_IMPULSE).map(|x| *x as f32 * SAMPLE_SCALE).collect();
+                // Same as a file the loaders read: trimmed to the peak and normalized, nothing loads without one
+                let Some((peak_index, peak)) = convolution::find_peak(&impulse) else { continue };
+                let impulse = convolution::prepare_impulse(&impulse, peak_index, peak, sample_rate, sample_rate);
+                let quiet: Vec<f32> = impulse.iter().map(|x| x * 0.5).collect();
+                handoff.convolver_slot.lock().unwrap().offer(Box::new(Convolver::new(&impulse)));
+                handoff.dynamic_slot.lock().unwrap().offer(Box::new(DynamicConvolver::new(&[(-24.0, quiet), (-6.0, impulse)], sample_rate)));
+            }
+        }
+    }
+});
@@ -0,0 +1,167 @@
+// chain.rs - Ardura 2023
+// The whole signal path from the input gain to the output ceiling, without any plugin framework types
+//  The plugin fills the settings from its parameters every sample, duro-render fills them from the command line
+
+use crate::bus_compressor::DetectorMode;
+use crate::duro_process::{Console, ConsoleMode, SaturationModeEnum, MAX_CHANNELS};
+use crate::fast_math::MathMode;
+use crate::harmonics::NUM_HARMONICS;
+use crate::lofi::AntiAliasMode;
+use crate::output_ceiling::{CeilingMode, OutputCeiling};
+use duro_dsp::console_models;
+
+// Plain value of every parameter for one sample frame, gains are linear
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChainSettings {
+    pub free_gain: f32,
+    pub threshold: f32,
+    pub drive: f32,
+    pub console_drive: f32,
+    pub console_threshold: f32,
+    pub sat_type: SaturationModeEnum,
+    pub console_type: ConsoleMode,
+    pub console_seed: i32,
+    pub console_tilt: f32,
+    pub variance: bool,
+    pub channel_number: i32,
+    // Tolerance in percent
+    pub variance_amount: f32,
+    pub bias: f32,
+    pub slew_rate: f32,
+    pub bit_depth: f32,
+    pub dither: bool,
+    pub downsample: f32,
+    pub anti_alias: AntiAliasMode,
+    pub math_mode: MathMode,
+    pub harmonic_levels: [f32; NUM_HARMONICS],
+    // In degrees
+    pub harmonic_phases: [f32; NUM_HARMONICS],
+    pub output_gain: f32,
+    pub dry_wet: f32,
+    pub comp_detector: DetectorMode,
+    pub comp_threshold: f32,
+    pub comp_ratio: f32,
+    pub comp_attack: f32,
+    pub comp_release: f32,
+    pub comp_auto_release: bool,
+    pub comp_knee: f32,
+    pub comp_makeup: f32,
+    pub ceiling_mode: CeilingMode,
+    pub ceiling: f32,
+}
+
+pub struct DuroChain {
+    console: Console,
+    output_ceiling: OutputCeiling,
+    sample_rate: f32,
+    // Seed and tilt generate_console last built the Generated Console from
+    generated: Option<(i32, f32)>,
+}
+
+impl DuroChain {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            console: Console::new(0.0, 1.0, ConsoleMode::BYPASS, sample_rate),
+            output_ceiling: OutputCeiling::new(sample_rate),
+            sample_rate,
+            generated: None,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.output_ceiling.set_sample_rate(sample_rate);
+    }
+
+    // Set the ceiling outside of processing, returns true if the latency changed
+    pub fn update_ceiling(&mut self, mode: CeilingMode, ceiling: f32) -> bool {
+        self.output_ceiling.update_vals(mode, ceiling)
+    }
+
+    pub fn latency_samples(&self) -> u32 {
+        self.output_ceiling.latency_samples()
+    }
+
+    // Back to silence everywhere, playback carries on in the same modes and settings
+    pub fn reset(&mut self) {
+        self.console.reset();
+        self.output_ceiling.reset();
+    }
+
+    // For handing the console curves, formulas and impulse responses
+    pub fn console_mut(&mut self) -> &mut Console {
+        &mut self.console
+    }
+
+    pub fn gain_reduction_db(&self) -> f32 {
+        self.console.gain_reduction_db()
+    }
+
+    // Begin in the settings' console and saturation instead of fading in from bypass
+    pub fn jump_to(&mut self, settings: &ChainSettings) {
+        self.console.update_vals(settings.threshold, settings.drive, settings.console_threshold, settings.console_drive, self.sample_rate);
+        self.console.set_bias(settings.bias);
+        self.console.jump_to_modes(settings.console_type, settings.sat_type);
+    }
+
+    // Settings that only change once per block. Auto math is fast in realtime and exact otherwise
+    pub fn start_block(&mut self, settings: &ChainSettings, realtime: bool) {
+        self.console.set_fast_math(match settings.math_mode {
+            MathMode::AUTO => realtime,
+            MathMode::EXACT => false,
+            MathMode::FAST => true,
+        });
+    }
+
+    // Build the Generated Console for the settings' seed and tilt on this thread, only when they moved.
+    // This is for renders outside of a host, the plugin builds it in the background and hands it over
+    // with Console::sync_generated so the audio thread never runs generate_taps
+    pub fn generate_console(&mut self, settings: &ChainSettings) {
+        let wanted = (settings.console_seed, settings.console_tilt);
+        if self.generated != Some(wanted) {
+            self.generated = Some(wanted);
+            self.console.set_generated_taps(&console_models::generate_taps(wanted.0, wanted.1));
+        }
+    }
+
+    // Process one frame of up to MAX_CHANNELS samples in place, returns true if the latency changed
+    pub fn process_frame(&mut self, frame: &mut [f32], settings: &ChainSettings) -> bool {
+        let channels = frame.len().min(MAX_CHANNELS);
+
+        // Switching the ceiling mode changes our latency
+        let latency_changed = self.output_ceiling.update_vals(settings.ceiling_mode, settings.ceiling);
+
+        // Update the console and its bus compressor
+        self.console.update_vals(settings.threshold, settings.drive, settings.console_threshold, settings.console_drive, self.sample_rate);
+        self.console.set_variance(settings.variance, settings.channel_number, settings.variance_amount / 100.0);
+        self.console.set_bias(settings.bias);
+        self.console.set_slew_rate(settings.slew_rate);
+        self.console.set_lofi(settings.bit_depth, settings.dither, settings.downsample, settings.anti_alias);
+        self.console.set_harmonics(&settings.harmonic_levels, &settings.harmonic_phases);
+        self.console.update_compressor(
+            settings.comp_detector,
+            settings.comp_threshold,
+            settings.comp_ratio,
+            settings.comp_attack,
+            settings.comp_release,
+            settings.comp_auto_release,
+            settings.comp_knee,
+            settings.comp_makeup,
+            channels);
+
+        for (channel, sample) in frame[..channels].iter_mut().enumerate() {
+            let input = *sample * settings.free_gain;
+
+            // Perform processing on the sample
+            let processed_sample = self.console.duro_process(input, channel, settings.sat_type, settings.console_type);
+
+            // Calculate dry/wet mix (no compression but saturation possible)
+            let mixed_sample = input * (1.0 - settings.dry_wet) + processed_sample * settings.dry_wet;
+            *sample = mixed_sample * settings.output_gain;
+        }
+
+        // Keep the output under the ceiling, this needs the whole frame to link the channels
+        self.output_ceiling.process_frame(&mut frame[..channels]);
+        latency_changed
+    }
+}
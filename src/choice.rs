@@ -0,0 +1,27 @@
+// choice.rs - Ardura 2023
+// nih-plug's Enum for the mode enums of duro_dsp, which has no plugin framework to derive it from
+
+use duro_dsp::Mode;
+use nih_plug::prelude::Enum;
+
+// A duro_dsp mode as an EnumParam value, the display names come from its names feature
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct Choice<T>(pub T);
+
+impl<T: Mode> Enum for Choice<T> {
+    fn variants() -> &'static [&'static str] {
+        T::variants()
+    }
+
+    fn ids() -> Option<&'static [&'static str]> {
+        None
+    }
+
+    fn to_index(self) -> usize {
+        self.0.to_index()
+    }
+
+    fn from_index(index: usize) -> Self {
+        Choice(T::from_index(index))
+    }
+}
@@ -0,0 +1,330 @@
+// convolution.rs - Ardura 2023
+// Convolution console from a user impulse response of any length
+//  Zero latency uniformly partitioned convolution: the first partition runs directly per sample and
+//  every later partition runs in the frequency domain once per block, where its output is not due yet
+
+use std::{f32::consts::PI, path::Path, sync::Arc};
+
+use duro_dsp::Response;
+use realfft::{num_complex::Complex, ComplexToReal, RealFftPlanner, RealToComplex};
+
+use crate::duro_process::MAX_CHANNELS;
+
+// Samples per partition, also how many samples the direct part convolves each sample
+pub const PARTITION_SIZE: usize = 64;
+const FFT_SIZE: usize = PARTITION_SIZE * 2;
+const BINS: usize = PARTITION_SIZE + 1;
+// Zero crossings of the resampling sinc on either side of each new sample
+const RESAMPLE_ZERO_CROSSINGS: f32 = 16.0;
+
+// Per channel input history and frequency domain delay line
+struct ChannelState {
+    // Last PARTITION_SIZE inputs written twice so the direct part reads them in one slice
+    recent: Vec<f32>,
+    recent_pos: usize,
+    // The previous and the current block, the overlap-save FFT input
+    blocks: Vec<f32>,
+    block_pos: usize,
+    // Spectra of past input blocks, newest at spectra_pos
+    spectra: Vec<Vec<Complex<f32>>>,
+    spectra_pos: usize,
+    // What the later partitions add to the current block
+    tail: Vec<f32>,
+}
+
+pub struct Convolver {
+    // First partition in the time domain, reversed so it lines up with the recent inputs
+    head: Vec<f32>,
+    // Spectra of every later partition, already scaled for the unnormalized inverse FFT
+    partitions: Vec<Vec<Complex<f32>>>,
+    channels: Vec<ChannelState>,
+    fft: Option<Arc<dyn RealToComplex<f32>>>,
+    ifft: Option<Arc<dyn ComplexToReal<f32>>>,
+    fft_buffer: Vec<f32>,
+    accumulator: Vec<Complex<f32>>,
+    forward_scratch: Vec<Complex<f32>>,
+    inverse_scratch: Vec<Complex<f32>>,
+    // Length of the loaded response in samples, 0 when nothing is loaded
+    length: usize,
+}
+
+impl Convolver {
+    // Nothing loaded, processes to silence without allocating
+    pub fn empty() -> Self {
+        Self {
+            head: Vec::new(),
+            partitions: Vec::new(),
+            channels: Vec::new(),
+            fft: None,
+            ifft: None,
+            fft_buffer: Vec::new(),
+            accumulator: Vec::new(),
+            forward_scratch: Vec::new(),
+            inverse_scratch: Vec::new(),
+            length: 0,
+        }
+    }
+
+    // Prepare a response, this allocates so it belongs on a background thread
+    pub fn new(impulse: &[f32]) -> Self {
+        if impulse.is_empty() {
+            return Self::empty();
+        }
+        let mut planner = RealFftPlanner::<f32>::new();
+        let fft = planner.plan_fft_forward(FFT_SIZE);
+        let ifft = planner.plan_fft_inverse(FFT_SIZE);
+
+        let mut head = vec![0.0; PARTITION_SIZE];
+        for (i, value) in impulse.iter().take(PARTITION_SIZE).enumerate() {
+            head[PARTITION_SIZE - 1 - i] = *value;
+        }
+
+        let scale = 1.0 / FFT_SIZE as f32;
+        let mut fft_buffer = vec![0.0; FFT_SIZE];
+        let mut forward_scratch = fft.make_scratch_vec();
+        let partitions: Vec<Vec<Complex<f32>>> = impulse
+            .chunks(PARTITION_SIZE)
+            .skip(1)
+            .map(|chunk| {
+                fft_buffer.iter_mut().for_each(|value| *value = 0.0);
+                for (value, tap) in fft_buffer.iter_mut().zip(chunk.iter()) {
+                    *value = tap * scale;
+                }
+                let mut spectrum = fft.make_output_vec();
+                fft.process_with_scratch(&mut fft_buffer, &mut spectrum, &mut forward_scratch).unwrap();
+                spectrum
+            })
+            .collect();
+
+        let channels = (0..MAX_CHANNELS)
+            .map(|_| ChannelState {
+                recent: vec![0.0; PARTITION_SIZE * 2],
+                recent_pos: 0,
+                blocks: vec![0.0; FFT_SIZE],
+                block_pos: 0,
+                spectra: vec![vec![Complex::new(0.0, 0.0); BINS]; partitions.len()],
+                spectra_pos: 0,
+                tail: vec![0.0; PARTITION_SIZE],
+            })
+            .collect();
+
+        Self {
+            head,
+            channels,
+            fft_buffer,
+            accumulator: fft.make_output_vec(),
+            forward_scratch,
+            inverse_scratch: ifft.make_scratch_vec(),
+            fft: Some(fft),
+            ifft: Some(ifft),
+            partitions,
+            length: impulse.len(),
+        }
+    }
+
+    // Clear every channel's history in place, the response stays loaded and nothing allocates
+    pub fn reset(&mut self) {
+        for state in self.channels.iter_mut() {
+            state.recent.iter_mut().for_each(|value| *value = 0.0);
+            state.recent_pos = 0;
+            state.blocks.iter_mut().for_each(|value| *value = 0.0);
+            state.block_pos = 0;
+            for spectrum in state.spectra.iter_mut() {
+                spectrum.iter_mut().for_each(|bin| *bin = Complex::new(0.0, 0.0));
+            }
+            state.spectra_pos = 0;
+            state.tail.iter_mut().for_each(|value| *value = 0.0);
+        }
+    }
+
+    // Convolve one sample of a channel, returns the convolved output for that same sample
+    pub fn process(&mut self, sample: f32, channel: usize) -> f32 {
+        if self.length == 0 {
+            return 0.0;
+        }
+        let state = &mut self.channels[channel];
+
+        // First partition directly so there is no latency
+        state.recent[state.recent_pos] = sample;
+        state.recent[state.recent_pos + PARTITION_SIZE] = sample;
+        state.recent_pos = (state.recent_pos + 1) % PARTITION_SIZE;
+        let window = &state.recent[state.recent_pos..state.recent_pos + PARTITION_SIZE];
+        let direct: f32 = window.iter().zip(self.head.iter()).map(|(x, h)| x * h).sum();
+        let output = direct + state.tail[state.block_pos];
+
+        state.blocks[PARTITION_SIZE + state.block_pos] = sample;
+        state.block_pos += 1;
+        if state.block_pos == PARTITION_SIZE {
+            state.block_pos = 0;
+            self.next_block(channel);
+        }
+        output
+    }
+
+    // A block of input is complete: add its spectrum to the delay line and work out what the later
+    // partitions contribute to the next block
+    fn next_block(&mut self, channel: usize) {
+        let state = &mut self.channels[channel];
+        if self.partitions.is_empty() {
+            return;
+        }
+        let (Some(fft), Some(ifft)) = (&self.fft, &self.ifft) else {
+            return;
+        };
+
+        self.fft_buffer.copy_from_slice(&state.blocks);
+        state.blocks.copy_within(PARTITION_SIZE.., 0);
+        state.spectra_pos = (state.spectra_pos + 1) % state.spectra.len();
+        fft.process_with_scratch(&mut self.fft_buffer, &mut state.spectra[state.spectra_pos], &mut self.forward_scratch).unwrap();
+
+        // Partition 1 pairs with the newest block, partition 2 with the one before and so on
+        self.accumulator.iter_mut().for_each(|bin| *bin = Complex::new(0.0, 0.0));
+        let count = state.spectra.len();
+        for (p, partition) in self.partitions.iter().enumerate() {
+            let input = &state.spectra[(state.spectra_pos + count - p) % count];
+            for ((bin, h), x) in self.accumulator.iter_mut().zip(partition.iter()).zip(input.iter()) {
+                *bin += h * x;
+            }
+        }
+
+        // The real inverse wants purely real DC and Nyquist bins
+        self.accumulator[0].im = 0.0;
+        self.accumulator[BINS - 1].im = 0.0;
+        ifft.process_with_scratch(&mut self.accumulator, &mut self.fft_buffer, &mut self.inverse_scratch).unwrap();
+        state.tail.copy_from_slice(&self.fft_buffer[PARTITION_SIZE..]);
+    }
+}
+
+impl Default for Convolver {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+// What the Impulse Response console runs through
+impl Response for Convolver {
+    fn process(&mut self, sample: f32, channel: usize) -> f32 {
+        Convolver::process(self, sample, channel)
+    }
+
+    fn reset(&mut self) {
+        Convolver::reset(self)
+    }
+}
+
+// Handoff between the loader and the audio thread. The audio thread swaps in the incoming convolver
+// and leaves its old one in retired, so freeing it happens on the next load instead of in process()
+pub struct ConvolverSlot<T> {
+    incoming: Option<Box<T>>,
+    retired: Option<Box<T>>,
+}
+
+impl<T> Default for ConvolverSlot<T> {
+    fn default() -> Self {
+        Self { incoming: None, retired: None }
+    }
+}
+
+impl<T> ConvolverSlot<T> {
+    // Loader side: drop whatever the audio thread retired and offer the new one
+    pub fn offer(&mut self, convolver: Box<T>) {
+        self.retired = None;
+        self.incoming = Some(convolver);
+    }
+
+    // Audio thread side: swap gets the incoming convolver and gives back the one it replaced
+    pub fn take(&mut self, swap: impl FnOnce(Box<T>) -> Box<T>) {
+        if self.retired.is_none() {
+            if let Some(convolver) = self.incoming.take() {
+                self.retired = Some(swap(convolver));
+            }
+        }
+    }
+}
+
+// Read a WAV file mixed down to mono, with its sample rate
+pub fn read_wav(path: &Path) -> Result<(Vec<f32>, f32), String> {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let mut reader = hound::WavReader::open(path).map_err(|e| format!("Could not open {name}: {e}"))?;
+    let spec = reader.spec();
+    let channels = spec.channels.max(1) as usize;
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>(),
+        hound::SampleFormat::Int => {
+            let full_scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader.samples::<i32>().map(|s| s.map(|s| s as f32 / full_scale)).collect::<Result<_, _>>()
+        }
+    }
+    .map_err(|e| format!("Could not read {name}: {e}"))?;
+
+    let mono = samples.chunks(channels).map(|frame| frame.iter().sum::<f32>() / channels as f32).collect();
+    Ok((mono, spec.sample_rate as f32))
+}
+
+// Index and value of the loudest sample, None if there isn't a usable one
+pub fn find_peak(impulse: &[f32]) -> Option<(usize, f32)> {
+    let (index, peak) = impulse
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))?;
+    if *peak == 0.0 || !peak.is_finite() {
+        return None;
+    }
+    Some((index, *peak))
+}
+
+// Start a response at the given peak, scale the peak to +1 and bring it to the session rate. The +1
+// is the direct signal, it is taken out so like the tap tables the response only holds what the
+// console adds on top of the dry sample
+pub fn prepare_impulse(impulse: &[f32], peak_index: usize, peak: f32, from_rate: f32, to_rate: f32) -> Vec<f32> {
+    let trimmed: Vec<f32> = impulse[peak_index..].iter().map(|s| s / peak).collect();
+    let mut prepared = resample(&trimmed, from_rate, to_rate);
+    prepared[0] -= 1.0;
+    prepared
+}
+
+// Read a WAV file into a console response at the given sample rate. The response starts at its
+// loudest sample so the console adds no latency
+pub fn load_impulse(path: &str, sample_rate: f32) -> Result<Vec<f32>, String> {
+    let (impulse, rate) = read_wav(Path::new(path))?;
+    let (peak_index, peak) = find_peak(&impulse).ok_or_else(|| "IR is empty or silent".to_string())?;
+    Ok(prepare_impulse(&impulse, peak_index, peak, rate, sample_rate))
+}
+
+// Windowed sinc resampling. Going down in rate the sinc is widened so it low passes below the new
+// Nyquist, and every tap is scaled by from_rate / to_rate so the response keeps its gain at any rate.
+// The sinc rings a little before the peak too, that part is added onto the first sample so the
+// response still adds no latency and loses none of its gain
+fn resample(impulse: &[f32], from_rate: f32, to_rate: f32) -> Vec<f32> {
+    if from_rate == to_rate || impulse.len() < 2 {
+        return impulse.to_vec();
+    }
+    // In source samples, positions in f64 so long responses don't drift
+    let step = from_rate as f64 / to_rate as f64;
+    let cutoff = (to_rate / from_rate).min(1.0);
+    let half_width = RESAMPLE_ZERO_CROSSINGS / cutoff;
+    let scale = from_rate / to_rate;
+    let sample_at = |i: i64| -> f32 {
+        let position = i as f64 * step;
+        let first = ((position - half_width as f64).ceil() as i64).max(0);
+        let last = ((position + half_width as f64).floor() as i64).min(impulse.len() as i64 - 1);
+        let sum: f32 = (first..=last)
+            .map(|k| impulse[k as usize] * windowed_sinc((k as f64 - position) as f32, cutoff, half_width))
+            .sum();
+        sum * scale
+    };
+    // Far enough either side for the whole sinc around the first and the last sample
+    let reach = (half_width as f64 / step).ceil() as i64;
+    let length = ((impulse.len() - 1) as f64 / step) as i64 + 1 + reach;
+    let mut resampled: Vec<f32> = (0..length).map(sample_at).collect();
+    resampled[0] += (-reach..0).map(sample_at).sum::<f32>();
+    resampled
+}
+
+// Sinc with its first zero at 1 / cutoff source samples, under a Blackman window half_width either side
+fn windowed_sinc(distance: f32, cutoff: f32, half_width: f32) -> f32 {
+    let x = PI * cutoff * distance;
+    let sinc = if x.abs() < 1e-6 { 1.0 } else { x.sin() / x };
+    let window = 0.42 + 0.5 * (PI * distance / half_width).cos() + 0.08 * (2.0 * PI * distance / half_width).cos();
+    cutoff * sinc * window
+}
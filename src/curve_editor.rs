@@ -0,0 +1,175 @@
+// curve_editor.rs - Ardura 2023
+// Editor for the Custom Curve transfer function
+//  Drag a point to move it, double click to add one, right click a point to remove it
+
+use std::sync::RwLock;
+
+use nih_plug_egui::egui::{Color32, Id, Pos2, Rect, Response, Rounding, Sense, Shape, Stroke, Ui, Vec2, Widget};
+use once_cell::sync::Lazy;
+
+use crate::custom_curve::{self, SharedCurve, CURVE_TABLE_SIZE, MAX_CURVE_POINTS};
+
+static DRAG_POINT_MEMORY_ID: Lazy<Id> = Lazy::new(|| Id::new("drag_point_memory_id"));
+
+// How close the pointer has to be to grab a point
+const GRAB_DISTANCE: f32 = 8.0;
+const POINT_RADIUS: f32 = 4.0;
+// Closest two points can get along the input axis
+const MIN_POINT_SPACING: f32 = 0.01;
+// Table entries skipped between drawn curve segments
+const DRAW_STEP: usize = 8;
+
+pub struct CurveEditor<'a> {
+    points: &'a RwLock<Vec<(f32, f32)>>,
+    shared: &'a SharedCurve,
+    size: Vec2,
+    line_color: Color32,
+    point_color: Color32,
+    background_color: Color32,
+}
+
+#[allow(dead_code)]
+impl<'a> CurveEditor<'a> {
+    pub fn new(points: &'a RwLock<Vec<(f32, f32)>>, shared: &'a SharedCurve, size: Vec2) -> Self {
+        CurveEditor {
+            points,
+            shared,
+            size,
+            line_color: Color32::WHITE,
+            point_color: Color32::WHITE,
+            background_color: Color32::GRAY,
+        }
+    }
+
+    // Specify curve line color
+    pub fn set_line_color(&mut self, new_color: Color32) {
+        self.line_color = new_color;
+    }
+
+    // Specify point color
+    pub fn set_point_color(&mut self, new_color: Color32) {
+        self.point_color = new_color;
+    }
+
+    // Specify background color
+    pub fn set_background_color(&mut self, new_color: Color32) {
+        self.background_color = new_color;
+    }
+
+    fn to_screen(rect: Rect, point: (f32, f32)) -> Pos2 {
+        Pos2::new(
+            rect.left() + (point.0 + 1.0) * 0.5 * rect.width(),
+            rect.bottom() - (point.1 + 1.0) * 0.5 * rect.height(),
+        )
+    }
+
+    fn from_screen(rect: Rect, pos: Pos2) -> (f32, f32) {
+        (
+            ((pos.x - rect.left()) / rect.width() * 2.0 - 1.0).clamp(-1.0, 1.0),
+            ((rect.bottom() - pos.y) / rect.height() * 2.0 - 1.0).clamp(-1.0, 1.0),
+        )
+    }
+
+    fn nearest_point(rect: Rect, points: &[(f32, f32)], pos: Pos2) -> Option<usize> {
+        points
+            .iter()
+            .enumerate()
+            .map(|(i, point)| (i, Self::to_screen(rect, *point).distance(pos)))
+            .filter(|(_, distance)| *distance <= GRAB_DISTANCE)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    // Returns true when the points changed
+    fn handle_response(&self, ui: &Ui, response: &Response, points: &mut Vec<(f32, f32)>) -> bool {
+        let pointer = match response.interact_pointer_pos() {
+            Some(pos) => pos,
+            None => return false,
+        };
+        let rect = response.rect;
+
+        if response.drag_started() {
+            match Self::nearest_point(rect, points, pointer) {
+                Some(index) => ui.memory().data.insert_temp(*DRAG_POINT_MEMORY_ID, index),
+                None => ui.memory().data.remove::<usize>(*DRAG_POINT_MEMORY_ID),
+            }
+        }
+
+        let mut changed = false;
+        if response.dragged() {
+            if let Some(index) = ui.memory().data.get_temp::<usize>(*DRAG_POINT_MEMORY_ID) {
+                let (mut x, y) = Self::from_screen(rect, pointer);
+                // The ends stay at -1 and 1, the rest can't pass their neighbours
+                if index == 0 || index == points.len() - 1 {
+                    x = points[index].0;
+                } else {
+                    x = x.clamp(points[index - 1].0 + MIN_POINT_SPACING, points[index + 1].0 - MIN_POINT_SPACING);
+                }
+                points[index] = (x, y);
+                changed = true;
+            }
+        }
+
+        if response.drag_released() {
+            ui.memory().data.remove::<usize>(*DRAG_POINT_MEMORY_ID);
+        }
+
+        if response.double_clicked() && points.len() < MAX_CURVE_POINTS && Self::nearest_point(rect, points, pointer).is_none() {
+            let point = Self::from_screen(rect, pointer);
+            let index = points.iter().position(|p| p.0 > point.0).unwrap_or(points.len() - 1).max(1);
+            if point.0 - points[index - 1].0 > MIN_POINT_SPACING && points[index].0 - point.0 > MIN_POINT_SPACING {
+                points.insert(index, point);
+                changed = true;
+            }
+        }
+
+        if response.secondary_clicked() {
+            if let Some(index) = Self::nearest_point(rect, points, pointer) {
+                if index != 0 && index != points.len() - 1 {
+                    points.remove(index);
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+}
+
+impl<'a> Widget for CurveEditor<'a> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let response = ui.allocate_response(self.size, Sense::click_and_drag());
+        let rect = response.rect;
+
+        // Edit under the write lock so the table write below has a single writer
+        let points = {
+            let mut points = self.points.write().unwrap();
+            if self.handle_response(ui, &response, &mut points) {
+                self.shared.write(&custom_curve::build_table(&points));
+            }
+            points.clone()
+        };
+
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, Rounding::none(), self.background_color);
+        let guide = Stroke::new(1.0, Color32::from_gray(90));
+        painter.line_segment([Pos2::new(rect.center().x, rect.top()), Pos2::new(rect.center().x, rect.bottom())], guide);
+        painter.line_segment([Pos2::new(rect.left(), rect.center().y), Pos2::new(rect.right(), rect.center().y)], guide);
+
+        let table = custom_curve::build_table(&points);
+        let curve: Vec<Pos2> = (0..CURVE_TABLE_SIZE)
+            .step_by(DRAW_STEP)
+            .map(|i| {
+                let x = i as f32 / (CURVE_TABLE_SIZE - 1) as f32 * 2.0 - 1.0;
+                Self::to_screen(rect, (x, table[i]))
+            })
+            .collect();
+        painter.add(Shape::line(curve, Stroke::new(2.0, self.line_color)));
+
+        for point in points.iter() {
+            painter.circle_filled(Self::to_screen(rect, *point), POINT_RADIUS, self.point_color);
+        }
+        painter.rect_stroke(rect, Rounding::none(), Stroke::new(1.0, Color32::BLACK));
+
+        response
+    }
+}
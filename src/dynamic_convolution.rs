@@ -0,0 +1,174 @@
+// dynamic_convolution.rs - Ardura 2023
+// Level dependent console from impulse responses captured at several input levels
+//  Every input sample is split between the two kernels either side of the signal's level, weighted by
+//  how close it is to each, and each kernel convolves its share. Summing those is the same as convolving
+//  every sample with its own kernel interpolated for the level it was played at
+
+use std::path::Path;
+
+use duro_dsp::{fast_math::flush_denormal, Response};
+use nih_plug::util;
+
+use crate::convolution::{self, Convolver};
+use crate::duro_process::MAX_CHANNELS;
+
+// Most capture levels a kernel set can have, each one is a full convolution per sample
+pub const MAX_KERNELS: usize = 8;
+// The level picking the kernels follows the signal's peaks like a meter. Going by each sample's own level
+// would swap kernels inside every cycle, at the zero crossings a loud signal would get the quiet kernel
+const LEVEL_ATTACK_MS: f32 = 1.0;
+const LEVEL_RELEASE_MS: f32 = 200.0;
+
+pub struct DynamicConvolver {
+    // Capture level of each kernel in dBFS, lowest first
+    levels: Vec<f32>,
+    convolvers: Vec<Convolver>,
+    attack_coeff: f32,
+    release_coeff: f32,
+    // Followed peak level of each channel
+    envelope: [f32; MAX_CHANNELS],
+}
+
+impl DynamicConvolver {
+    // Nothing loaded, processes to silence without allocating
+    pub fn empty() -> Self {
+        Self {
+            levels: Vec::new(),
+            convolvers: Vec::new(),
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            envelope: [0.0; MAX_CHANNELS],
+        }
+    }
+
+    // Prepare a kernel set for the rate it was resampled to, this allocates so it belongs on a background thread
+    pub fn new(kernels: &[(f32, Vec<f32>)], sample_rate: f32) -> Self {
+        let coeff = |ms: f32| -> f32 { (-1.0 / (ms * 0.001 * sample_rate)).exp() };
+        Self {
+            levels: kernels.iter().map(|(level, _)| *level).collect(),
+            convolvers: kernels.iter().map(|(_, kernel)| Convolver::new(kernel)).collect(),
+            attack_coeff: coeff(LEVEL_ATTACK_MS),
+            release_coeff: coeff(LEVEL_RELEASE_MS),
+            envelope: [0.0; MAX_CHANNELS],
+        }
+    }
+
+    // Clear the history of every kernel and the followed levels
+    pub fn reset(&mut self) {
+        for convolver in self.convolvers.iter_mut() {
+            convolver.reset();
+        }
+        self.envelope = [0.0; MAX_CHANNELS];
+    }
+
+    // Convolve one sample of a channel, returns the convolved output for that same sample
+    pub fn process(&mut self, sample: f32, channel: usize) -> f32 {
+        if self.convolvers.is_empty() {
+            return 0.0;
+        }
+
+        let magnitude = sample.abs();
+        let envelope = &mut self.envelope[channel];
+        let coeff = if magnitude > *envelope { self.attack_coeff } else { self.release_coeff };
+        *envelope = flush_denormal(coeff * *envelope + (1.0 - coeff) * magnitude);
+
+        // The pair of kernels around the signal's level, quieter or louder than every capture uses the end one
+        let level = util::gain_to_db(*envelope);
+        let upper = self.levels.iter().position(|capture| *capture >= level).unwrap_or(self.levels.len() - 1);
+        let lower = upper.saturating_sub(1);
+        let upper_weight = if upper == lower || level >= self.levels[upper] {
+            1.0
+        } else {
+            (level - self.levels[lower]) / (self.levels[upper] - self.levels[lower])
+        };
+
+        // Every kernel still runs so its history lines up when the level moves back into its range
+        let mut output = 0.0;
+        for (i, convolver) in self.convolvers.iter_mut().enumerate() {
+            let weight = if i == upper {
+                upper_weight
+            } else if i == lower {
+                1.0 - upper_weight
+            } else {
+                0.0
+            };
+            output += convolver.process(sample * weight, channel);
+        }
+        output
+    }
+}
+
+impl Default for DynamicConvolver {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+// What the Dynamic Response console runs through
+impl Response for DynamicConvolver {
+    fn process(&mut self, sample: f32, channel: usize) -> f32 {
+        DynamicConvolver::process(self, sample, channel)
+    }
+
+    fn reset(&mut self) {
+        DynamicConvolver::reset(self)
+    }
+}
+
+// Capture level from a kernel's file name, like -18.wav or -18dB.wav
+fn capture_level(path: &Path) -> Option<f32> {
+    let stem = path.file_stem()?.to_str()?.trim();
+    let number = stem
+        .trim_end_matches("dBFS")
+        .trim_end_matches("dbfs")
+        .trim_end_matches("dB")
+        .trim_end_matches("db")
+        .trim();
+    number.parse::<f32>().ok().filter(|level| level.is_finite())
+}
+
+// Read every WAV in a folder as a kernel named by its capture level. The kernels are expected to be
+// normalized to their capture level, so a perfectly linear console would give the same kernel at
+// every level. All of them start where the quietest kernel peaks and are scaled by that peak, which
+// keeps the quiet kernel at unity and leaves the louder ones their extra compression or gain
+pub fn load_kernels(folder: &str, sample_rate: f32) -> Result<Vec<(f32, Vec<f32>)>, String> {
+    let entries = std::fs::read_dir(folder).map_err(|e| format!("Could not open kernel folder: {e}"))?;
+    let mut paths: Vec<(f32, std::path::PathBuf)> = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_wav = path.extension().map(|ext| ext.eq_ignore_ascii_case("wav")).unwrap_or(false);
+        if !is_wav {
+            continue;
+        }
+        let level = capture_level(&path).ok_or_else(|| {
+            format!("Name kernels by their level in dBFS, like -18.wav, not {}", path.file_name().unwrap_or_default().to_string_lossy())
+        })?;
+        paths.push((level, path));
+    }
+    if paths.is_empty() {
+        return Err("No WAV kernels in that folder".to_string());
+    }
+    if paths.len() > MAX_KERNELS {
+        return Err(format!("At most {MAX_KERNELS} kernels are supported"));
+    }
+    paths.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if paths.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+        return Err("Two kernels have the same level".to_string());
+    }
+
+    let mut kernels = Vec::with_capacity(paths.len());
+    for (level, path) in paths.iter() {
+        kernels.push((*level, convolution::read_wav(path)?));
+    }
+    let (quietest, rate) = &kernels[0].1;
+    let (peak_index, peak) = convolution::find_peak(quietest).ok_or_else(|| "The quietest kernel is silent".to_string())?;
+    let rate = *rate;
+    if kernels.iter().any(|(_, (kernel, kernel_rate))| kernel.len() <= peak_index || *kernel_rate != rate) {
+        return Err("Kernels need the same sample rate and the same start".to_string());
+    }
+
+    Ok(kernels
+        .into_iter()
+        .map(|(level, (kernel, _))| (level, convolution::prepare_impulse(&kernel, peak_index, peak, rate, sample_rate)))
+        .collect())
+}
@@ -0,0 +1,88 @@
+// engine.rs - Ardura 2023
+// What Gain::process does with a host buffer, without the plugin framework around it
+//  The plugin, the benchmarks and the realtime tests all run this, so what gets measured and checked
+//  is what plays in a host
+
+use std::sync::{Arc, Mutex};
+
+use crate::chain::{ChainSettings, DuroChain};
+use crate::convolution::{Convolver, ConvolverSlot};
+use crate::dynamic_convolution::DynamicConvolver;
+use crate::meters::Meters;
+use duro_dsp::{console_models, custom_curve, formula};
+
+// Everything the editor and the background thread hand to the audio thread
+#[derive(Clone)]
+pub struct Handoff {
+    // Custom Curve lookup table the editor bakes
+    pub custom_curve: Arc<custom_curve::SharedCurve>,
+    // Compiled Formula from the editor
+    pub formula: Arc<formula::SharedFormula>,
+    // Generated Console taps built in the background
+    pub generated: Arc<console_models::SharedTaps>,
+    // Impulse responses loaded in the background
+    pub convolver_slot: Arc<Mutex<ConvolverSlot<Convolver>>>,
+    pub dynamic_slot: Arc<Mutex<ConvolverSlot<DynamicConvolver>>>,
+}
+
+impl Handoff {
+    // The default curve and formula, and the Generated Console of this seed and tilt
+    pub fn new(console_seed: i32, console_tilt: f32) -> Self {
+        Self {
+            custom_curve: Arc::new(custom_curve::SharedCurve::new(&custom_curve::default_points())),
+            formula: Arc::new(formula::SharedFormula::new(
+                &formula::compile(formula::DEFAULT_FORMULA).unwrap_or_else(|_| formula::Program::passthrough()),
+            )),
+            generated: Arc::new(console_models::SharedTaps::new(&console_models::generate_taps(console_seed, console_tilt))),
+            convolver_slot: Arc::new(Mutex::new(ConvolverSlot::default())),
+            dynamic_slot: Arc::new(Mutex::new(ConvolverSlot::default())),
+        }
+    }
+}
+
+pub struct Engine {
+    // Console, bus compressor, saturation and the output ceiling after the output gain
+    pub chain: DuroChain,
+    // The current data for the different meters
+    pub meters: Meters,
+    pub handoff: Handoff,
+}
+
+impl Engine {
+    pub fn new(sample_rate: f32, handoff: Handoff) -> Self {
+        let mut meters = Meters::new();
+        meters.set_sample_rate(sample_rate);
+        Self { chain: DuroChain::new(sample_rate), meters, handoff }
+    }
+
+    // Once per buffer: the buffer's settings, then whatever the editor and the background thread
+    // handed over. Nothing here waits, the shared tables are versioned and the slots are only tried
+    pub fn start_block(&mut self, settings: &ChainSettings, realtime: bool) {
+        self.chain.start_block(settings, realtime);
+
+        let console = self.chain.console_mut();
+        console.sync_custom_curve(&self.handoff.custom_curve);
+        console.sync_formula(&self.handoff.formula);
+        console.sync_generated(&self.handoff.generated);
+
+        // Take newly loaded impulse responses if the loader isn't busy with the slot
+        if let Ok(mut slot) = self.handoff.convolver_slot.try_lock() {
+            slot.take(|convolver| console.swap_convolver(convolver));
+        }
+        if let Ok(mut slot) = self.handoff.dynamic_slot.try_lock() {
+            slot.take(|dynamic| console.swap_dynamic(dynamic));
+        }
+    }
+
+    // One sample frame in place, at most MAX_CHANNELS channels. The meters only follow it while
+    // something shows them. Returns true when switching the ceiling mode changed the latency
+    pub fn process_frame(&mut self, frame: &mut [f32], settings: &ChainSettings, metering: bool) -> bool {
+        let in_amplitude: f32 = frame.iter().map(|sample| sample * settings.free_gain).sum();
+        let latency_changed = self.chain.process_frame(frame, settings);
+        if metering {
+            let out_amplitude: f32 = frame.iter().sum();
+            self.meters.update(in_amplitude, out_amplitude, frame.len(), self.chain.gain_reduction_db());
+        }
+        latency_changed
+    }
+}
@@ -0,0 +1,155 @@
+// harmonic_graph.rs - Ardura 2023
+// Bar graph editor for the harmonic generator spectrum
+//  Drag a bar up and down to set its level, right drag to set its phase, double click to reset it
+
+use std::f32::consts::PI;
+
+use nih_plug::prelude::{Param, ParamSetter};
+use nih_plug_egui::egui::{
+    Align2, Color32, FontId, Id, PointerButton, Pos2, Rect, Response, Rounding, Sense, Stroke, Ui, Vec2, Widget, self,
+};
+use once_cell::sync::Lazy;
+
+use crate::harmonics::{HarmonicParams, FIRST_HARMONIC, NUM_HARMONICS};
+
+static DRAG_HARMONIC_MEMORY_ID: Lazy<Id> = Lazy::new(|| Id::new("drag_harmonic_memory_id"));
+
+// Room under the bars for the harmonic number and phase dial
+const LABEL_HEIGHT: f32 = 24.0;
+const PHASE_DIAL_RADIUS: f32 = 4.0;
+
+pub struct HarmonicGraph<'a> {
+    harmonics: &'a [HarmonicParams; NUM_HARMONICS],
+    param_setter: &'a ParamSetter<'a>,
+    size: Vec2,
+    bar_color: Color32,
+    line_color: Color32,
+    background_color: Color32,
+}
+
+#[allow(dead_code)]
+impl<'a> HarmonicGraph<'a> {
+    pub fn for_params(harmonics: &'a [HarmonicParams; NUM_HARMONICS], param_setter: &'a ParamSetter, size: Vec2) -> Self {
+        HarmonicGraph {
+            harmonics,
+            param_setter,
+            size,
+            bar_color: Color32::WHITE,
+            line_color: Color32::BLACK,
+            background_color: Color32::GRAY,
+        }
+    }
+
+    // Specify bar fill color
+    pub fn set_bar_color(&mut self, new_color: Color32) {
+        self.bar_color = new_color;
+    }
+
+    // Specify text and phase dial color
+    pub fn set_line_color(&mut self, new_color: Color32) {
+        self.line_color = new_color;
+    }
+
+    // Specify background color
+    pub fn set_background_color(&mut self, new_color: Color32) {
+        self.background_color = new_color;
+    }
+
+    // Which bar sits under an x position
+    fn bar_index(&self, rect: Rect, x: f32) -> usize {
+        let bar_width = rect.width() / NUM_HARMONICS as f32;
+        (((x - rect.left()) / bar_width).floor().max(0.0) as usize).min(NUM_HARMONICS - 1)
+    }
+
+    fn handle_response(&self, ui: &Ui, response: &Response, bar_area: Rect) {
+        let pointer = match response.interact_pointer_pos() {
+            Some(pos) => pos,
+            None => return,
+        };
+        // Top of the bar area is full value, bottom is the minimum
+        let normalized = ((bar_area.bottom() - pointer.y) / bar_area.height()).clamp(0.0, 1.0);
+
+        if response.double_clicked() {
+            let harmonic = &self.harmonics[self.bar_index(bar_area, pointer.x)];
+            self.param_setter.set_parameter_normalized(&harmonic.level, harmonic.level.default_normalized_value());
+            self.param_setter.set_parameter_normalized(&harmonic.phase, harmonic.phase.default_normalized_value());
+            return;
+        }
+
+        // Stick to the bar the drag started on until it is released
+        if response.drag_started() {
+            let index = self.bar_index(bar_area, pointer.x);
+            ui.memory().data.insert_temp(*DRAG_HARMONIC_MEMORY_ID, index);
+            let harmonic = &self.harmonics[index];
+            self.param_setter.begin_set_parameter(&harmonic.level);
+            self.param_setter.begin_set_parameter(&harmonic.phase);
+        }
+        let index = ui.memory().data.get_temp::<usize>(*DRAG_HARMONIC_MEMORY_ID);
+
+        if let Some(index) = index {
+            let harmonic = &self.harmonics[index];
+            if response.dragged_by(PointerButton::Primary) {
+                self.param_setter.set_parameter_normalized(&harmonic.level, normalized);
+            } else if response.dragged_by(PointerButton::Secondary) {
+                self.param_setter.set_parameter_normalized(&harmonic.phase, normalized);
+            }
+
+            if response.drag_released() {
+                self.param_setter.end_set_parameter(&harmonic.level);
+                self.param_setter.end_set_parameter(&harmonic.phase);
+                ui.memory().data.remove::<usize>(*DRAG_HARMONIC_MEMORY_ID);
+            }
+        }
+    }
+}
+
+impl<'a> Widget for HarmonicGraph<'a> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let response = ui.allocate_response(self.size, Sense::click_and_drag());
+        let rect = response.rect;
+        let bar_area = Rect::from_min_max(rect.min, Pos2::new(rect.right(), rect.bottom() - LABEL_HEIGHT));
+        self.handle_response(ui, &response, bar_area);
+
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(bar_area, Rounding::none(), self.background_color);
+
+        let bar_width = bar_area.width() / NUM_HARMONICS as f32;
+        let mut hovered = None;
+        for (i, harmonic) in self.harmonics.iter().enumerate() {
+            let left = bar_area.left() + bar_width * i as f32;
+            let column = Rect::from_min_max(Pos2::new(left, bar_area.top()), Pos2::new(left + bar_width, bar_area.bottom()));
+
+            // Bar height follows the level knob's own curve so the graph and the knobs agree
+            let level = harmonic.level.unmodulated_normalized_value();
+            let bar = Rect::from_min_max(
+                Pos2::new(column.left() + 2.0, column.bottom() - column.height() * level),
+                Pos2::new(column.right() - 2.0, column.bottom()),
+            );
+            painter.rect_filled(bar, Rounding::none(), self.bar_color);
+
+            // Harmonic number and a small dial showing the phase
+            let label_center = Pos2::new(column.center().x, bar_area.bottom() + LABEL_HEIGHT / 4.0 + 2.0);
+            painter.text(label_center, Align2::CENTER_CENTER, (i + FIRST_HARMONIC).to_string(), FontId::proportional(10.0), self.line_color);
+            let dial_center = Pos2::new(column.center().x, bar_area.bottom() + LABEL_HEIGHT * 0.75);
+            let angle = harmonic.phase.unmodulated_plain_value() * PI / 180.0;
+            painter.circle_stroke(dial_center, PHASE_DIAL_RADIUS, Stroke::new(1.0, self.line_color));
+            painter.line_segment(
+                [dial_center, dial_center + egui::vec2(angle.sin(), -angle.cos()) * PHASE_DIAL_RADIUS],
+                Stroke::new(1.0, self.line_color),
+            );
+
+            if let Some(hover) = response.hover_pos() {
+                if column.x_range().contains(&hover.x) {
+                    hovered = Some(harmonic);
+                }
+            }
+        }
+
+        painter.rect_stroke(bar_area, Rounding::none(), Stroke::new(1.0, self.line_color));
+
+        match hovered {
+            Some(harmonic) => response.on_hover_text(format!("{}, {}", harmonic.level, harmonic.phase)),
+            None => response,
+        }
+    }
+}
@@ -0,0 +1,50 @@
+// harmonics.rs - Ardura 2023
+// Level and phase parameters for harmonics 2 to 16, the generator itself lives in duro_dsp
+
+use nih_plug::prelude::*;
+pub use duro_dsp::harmonics::*;
+
+// Lowest level the level parameter shows before it reads as off
+const LEVEL_FLOOR_DB: f32 = -60.0;
+
+#[derive(Params)]
+pub struct HarmonicParams {
+    // Level relative to the fundamental
+    #[id = "level"]
+    pub level: FloatParam,
+
+    // Phase relative to the fundamental in degrees
+    #[id = "phase"]
+    pub phase: FloatParam,
+}
+
+impl HarmonicParams {
+    pub fn new(harmonic: usize) -> Self {
+        Self {
+            level: FloatParam::new(
+                format!("H{harmonic} Level"),
+                default_level(harmonic),
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 1.0,
+                    factor: FloatRange::gain_skew_factor(LEVEL_FLOOR_DB, 0.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(1))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+
+            phase: FloatParam::new(
+                format!("H{harmonic} Phase"),
+                0.0,
+                FloatRange::Linear {
+                    min: -180.0,
+                    max: 180.0,
+                },
+            )
+            .with_unit("°")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+        }
+    }
+}
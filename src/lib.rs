@@ -1,11 +1,24 @@
 mod ui_knob;
 mod db_meter;
+pub mod output_ceiling;
+pub mod harmonics;
+mod harmonic_graph;
+mod curve_editor;
+pub mod choice;
+pub mod convolution;
+pub mod dynamic_convolution;
+pub mod chain;
+pub mod meters;
+pub mod engine;
 use atomic_float::AtomicF32;
-use duro_process::{Console};
+use chain::ChainSettings;
+use choice::Choice;
 use nih_plug::{prelude::*};
+use nih_plug::wrapper::state::ParamValue;
 use nih_plug_egui::{create_egui_editor, egui::{self, Color32, Rect, Rounding, RichText, FontId, Pos2}, EguiState};
-use std::{sync::{Arc}, ops::RangeInclusive};
-mod duro_process;
+use std::{sync::{Arc, Mutex, RwLock}, ops::RangeInclusive};
+pub mod duro_process;
+pub use duro_dsp::{bus_compressor, console_models, custom_curve, fast_math, formula, lofi};
 
 /**************************************************
  * Duro Console by Ardura
@@ -23,32 +36,88 @@ const ORANGE: Color32 = Color32::from_rgb(251, 133, 0);
 
 // Plugin sizing
 const WIDTH: u32 = 800;
-const HEIGHT: u32 = 180;
+const HEIGHT: u32 = 760;
 
-/// The time it takes for the peak meter to decay by 12 dB after switching to complete silence.
-const PEAK_METER_DECAY_MS: f64 = 100.0;
+// The gain reduction meter shows this many dB at full scale
+const GR_METER_RANGE_DB: f32 = 24.0;
+
+// Both drives start where the single drive before the split did, so a new instance sounds the same
+const DEFAULT_DRIVE: f32 = 0.0;
 
 pub struct Gain {
     params: Arc<GainParams>,
 
-    // normalize the peak meter's response based on the sample rate with this
-    out_meter_decay_weight: f32,
+    // The signal path, the meters and what the editor and the background thread hand over
+    engine: engine::Engine,
+
+    // Seed and tilt the Generated Console taps were last built from. The lock keeps a single writer
+    generated_from: Arc<Mutex<(i32, f32)>>,
+    // Seed and tilt the audio thread last asked the background thread to build
+    generated_request: (i32, f32),
+
+    // Whether the host is rendering offline, the Auto math mode goes exact then
+    process_mode: ProcessMode,
+
+    // Result of the last impulse response and kernel loads for the editor
+    ir_status: Arc<RwLock<String>>,
+    dynamic_status: Arc<RwLock<String>>,
+    // Rate the impulse response gets resampled to
+    sample_rate: Arc<AtomicF32>,
+}
+
+// Work done off the audio thread
+pub enum DuroTask {
+    // Read and prepare the impulse response at this path
+    LoadImpulse(String),
+    // Read and prepare the level dependent kernels in this folder
+    LoadKernels(String),
+    // Build the Generated Console for the current seed and tilt
+    GenerateConsole,
+}
 
-    // Console class
-    console: Console,
+// Kept by the editor between frames. The formula compiles when its text changes, not every frame
+struct EditorMemory {
+    // Text the formula was last compiled from and what came of it
+    formula_text: String,
+    formula: Result<formula::Program, formula::FormulaError>,
+}
 
-    // The current data for the different meters
-    out_meter: Arc<AtomicF32>,
-    in_meter: Arc<AtomicF32>,
+impl EditorMemory {
+    fn new(formula_text: &str) -> Self {
+        Self {
+            formula_text: formula_text.to_string(),
+            formula: formula::compile(formula_text),
+        }
+    }
 }
 
 #[derive(Params)]
-struct GainParams {
+pub struct GainParams {
     /// The editor state, saved together with the parameter state so the custom scaling can be
     /// restored.
     #[persist = "editor-state"]
     editor_state: Arc<EguiState>,
 
+    /// Generated console seeds the user saved, kept with the session
+    #[persist = "seed-favorites"]
+    seed_favorites: Arc<RwLock<Vec<i32>>>,
+
+    /// Points of the Custom Curve as (input, output) pairs
+    #[persist = "custom-curve"]
+    custom_curve: Arc<RwLock<Vec<(f32, f32)>>>,
+
+    /// Text of the Formula saturation, kept as typed even when it doesn't parse
+    #[persist = "formula"]
+    formula: Arc<RwLock<String>>,
+
+    /// WAV file the Impulse Response console is loaded from
+    #[persist = "ir-path"]
+    ir_path: Arc<RwLock<String>>,
+
+    /// Folder of level named WAV kernels the Dynamic Response console is loaded from
+    #[persist = "kernel-folder"]
+    kernel_folder: Arc<RwLock<String>>,
+
     #[id = "free_gain"]
     pub free_gain: FloatParam,
 
@@ -58,27 +127,107 @@ struct GainParams {
     #[id = "drive"]
     pub drive: FloatParam,
 
+    #[id = "console_drive"]
+    pub console_drive: FloatParam,
+
+    #[id = "console_threshold"]
+    pub console_threshold: FloatParam,
+
     #[id = "type"]
-    pub sat_type: EnumParam<duro_process::SaturationModeEnum>,
+    pub sat_type: EnumParam<Choice<duro_process::SaturationModeEnum>>,
 
     #[id = "console_type"]
-    pub console_type: EnumParam<duro_process::ConsoleMode>,
+    pub console_type: EnumParam<Choice<duro_process::ConsoleMode>>,
+
+    #[id = "console_seed"]
+    pub console_seed: IntParam,
+
+    #[id = "console_tilt"]
+    pub console_tilt: FloatParam,
+
+    #[id = "variance"]
+    pub variance: BoolParam,
+
+    #[id = "channel_number"]
+    pub channel_number: IntParam,
+
+    #[id = "variance_amount"]
+    pub variance_amount: FloatParam,
+
+    #[id = "bias"]
+    pub bias: FloatParam,
+
+    #[id = "slew_rate"]
+    pub slew_rate: FloatParam,
+
+    #[id = "bit_depth"]
+    pub bit_depth: FloatParam,
+
+    #[id = "dither"]
+    pub dither: BoolParam,
+
+    #[id = "downsample"]
+    pub downsample: FloatParam,
+
+    #[id = "anti_alias"]
+    pub anti_alias: EnumParam<Choice<lofi::AntiAliasMode>>,
+
+    #[id = "math_mode"]
+    pub math_mode: EnumParam<Choice<fast_math::MathMode>>,
+
+    #[nested(array, group = "Harmonic")]
+    pub harmonics: [harmonics::HarmonicParams; harmonics::NUM_HARMONICS],
 
     #[id = "output_gain"]
     pub output_gain: FloatParam,
 
     #[id = "dry_wet"]
     pub dry_wet: FloatParam,
+
+    #[id = "comp_detector"]
+    pub comp_detector: EnumParam<Choice<bus_compressor::DetectorMode>>,
+
+    #[id = "comp_threshold"]
+    pub comp_threshold: FloatParam,
+
+    #[id = "comp_ratio"]
+    pub comp_ratio: FloatParam,
+
+    #[id = "comp_attack"]
+    pub comp_attack: FloatParam,
+
+    #[id = "comp_release"]
+    pub comp_release: FloatParam,
+
+    #[id = "comp_auto_release"]
+    pub comp_auto_release: BoolParam,
+
+    #[id = "comp_knee"]
+    pub comp_knee: FloatParam,
+
+    #[id = "comp_makeup"]
+    pub comp_makeup: FloatParam,
+
+    #[id = "ceiling_mode"]
+    pub ceiling_mode: EnumParam<output_ceiling::CeilingMode>,
+
+    #[id = "ceiling"]
+    pub ceiling: FloatParam,
 }
 
 impl Default for Gain {
     fn default() -> Self {
+        let params = Arc::new(GainParams::default());
+        let generated_from = (params.console_seed.value(), params.console_tilt.value());
         Self {
-            params: Arc::new(GainParams::default()),
-            console:duro_process::Console::new(0.0,4,crate::duro_process::ConsoleMode::BYPASS,44100.0),
-            out_meter_decay_weight: 1.0,
-            out_meter: Arc::new(AtomicF32::new(util::MINUS_INFINITY_DB)),
-            in_meter: Arc::new(AtomicF32::new(util::MINUS_INFINITY_DB)),
+            params,
+            engine: engine::Engine::new(44100.0, engine::Handoff::new(generated_from.0, generated_from.1)),
+            generated_from: Arc::new(Mutex::new(generated_from)),
+            generated_request: generated_from,
+            process_mode: ProcessMode::Realtime,
+            ir_status: Arc::new(RwLock::new(String::new())),
+            dynamic_status: Arc::new(RwLock::new(String::new())),
+            sample_rate: Arc::new(AtomicF32::new(44100.0)),
         }
     }
 }
@@ -87,6 +236,11 @@ impl Default for GainParams {
     fn default() -> Self {
         Self {
             editor_state: EguiState::from_size(WIDTH, HEIGHT),
+            seed_favorites: Arc::new(RwLock::new(Vec::new())),
+            custom_curve: Arc::new(RwLock::new(custom_curve::default_points())),
+            formula: Arc::new(RwLock::new(formula::DEFAULT_FORMULA.to_string())),
+            ir_path: Arc::new(RwLock::new(String::new())),
+            kernel_folder: Arc::new(RwLock::new(String::new())),
 
             // Input gain dB parameter (free as in unrestricted nums)
             free_gain: FloatParam::new(
@@ -103,20 +257,51 @@ impl Default for GainParams {
             .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
             .with_string_to_value(formatters::s2v_f32_gain_to_db()),
 
-            // Drive Parameter
+            // Saturation Drive Parameter - kept on the old "drive" id so sessions still load
             drive: FloatParam::new(
-                "Drive",
-                0.0,
+                "Saturation Drive",
+                DEFAULT_DRIVE,
                 FloatRange::Linear {
                     min: 0.0,
                     max: 2.0,
                 },
             )
             .with_smoother(SmoothingStyle::Linear(50.0))
-            .with_unit(" % Drive")
+            .with_unit(" % Sat Drive")
             .with_value_to_string(formatters::v2s_f32_percentage(2))
             .with_string_to_value(formatters::s2v_f32_percentage()),
 
+            // Console Drive Parameter, how hard the console delay lines are fed
+            console_drive: FloatParam::new(
+                "Console Drive",
+                DEFAULT_DRIVE,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 2.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" % Console Drive")
+            .with_value_to_string(formatters::v2s_f32_percentage(2))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // Console Threshold Parameter, where the console taps' level dependent part starts. The console
+            // never read the saturation threshold, so sessions from before it get it off (0, -inf dB), which
+            // is how the console always sounded, instead of a copy of the saturation threshold
+            console_threshold: FloatParam::new(
+                "Console Threshold",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 1.0,
+                    factor: FloatRange::gain_skew_factor(-60.0, 0.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" dB Console Threshold")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+
             // Threshold dB parameter
             threshold: FloatParam::new(
                 "Threshold",
@@ -147,11 +332,126 @@ impl Default for GainParams {
             .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
             .with_string_to_value(formatters::s2v_f32_gain_to_db()),
 
-            // Compressor Type parameter
-            console_type: EnumParam::new("name",crate::duro_process::ConsoleMode::BYPASS),
+            // Console Type parameter
+            console_type: EnumParam::new("name",Choice(crate::duro_process::ConsoleMode::BYPASS)),
+
+            // Generated console seed parameter
+            console_seed: IntParam::new(
+                "Seed",
+                12463,
+                IntRange::Linear {
+                    min: 0,
+                    max: 99999,
+                },
+            )
+            .with_unit(" Seed"),
+
+            // Generated console tone tilt parameter, negative is darker and positive brighter
+            console_tilt: FloatParam::new(
+                "Tilt",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_unit(" Tilt")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            // Channel variance parameter, perturbs the console and saturation like real part tolerances
+            variance: BoolParam::new("Channel Variance", false),
+
+            // Channel number parameter, each number is a different but repeatable voicing
+            channel_number: IntParam::new(
+                "Channel",
+                1,
+                IntRange::Linear {
+                    min: 1,
+                    max: 64,
+                },
+            )
+            .with_unit(" Channel"),
+
+            // Channel variance tolerance parameter
+            variance_amount: FloatParam::new(
+                "Tolerance",
+                1.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 10.0,
+                },
+            )
+            .with_unit("% Tolerance")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
 
             // Saturation Type parameter
-            sat_type: EnumParam::new("name",crate::duro_process::SaturationModeEnum::NONESAT),
+            sat_type: EnumParam::new("name",Choice(crate::duro_process::SaturationModeEnum::NONESAT)),
+
+            // Bias parameter, offsets the signal into the saturation curve for even harmonics
+            bias: FloatParam::new(
+                "Bias",
+                0.0,
+                FloatRange::Linear {
+                    min: -0.5,
+                    max: 0.5,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" Bias")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            // Slew rate of the Slew Limit saturation in V/us, 0.5 is a 741 and 13 an NE5534
+            slew_rate: FloatParam::new(
+                "Slew Rate",
+                0.5,
+                FloatRange::Skewed {
+                    min: 0.1,
+                    max: 20.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit(" V/us Slew")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            // Bit depth of the Bitcrush and companding modes, fractional depths sweep smoothly
+            bit_depth: FloatParam::new(
+                "Bit Depth",
+                8.0,
+                FloatRange::Linear {
+                    min: 1.0,
+                    max: 24.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" Bits")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            // TPDF dither before quantizing
+            dither: BoolParam::new("Dither", false),
+
+            // How many samples the Sample Rate Reduce mode holds each value for
+            downsample: FloatParam::new(
+                "Downsample",
+                4.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 64.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit("x Down")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            // Anti-alias filtering around the sample and hold
+            anti_alias: EnumParam::new("Anti-Alias", Choice(crate::lofi::AntiAliasMode::PRE)),
+
+            // Approximate tanh, atan and exp in the saturation curves
+            math_mode: EnumParam::new("Math", Choice(crate::fast_math::MathMode::AUTO)),
+
+            // Level and phase of harmonics 2 to 16 for the harmonic generator modes
+            harmonics: std::array::from_fn(|i| harmonics::HarmonicParams::new(i + harmonics::FIRST_HARMONIC)),
 
             // Dry/Wet parameter
             dry_wet: FloatParam::new(
@@ -166,8 +466,413 @@ impl Default for GainParams {
             .with_unit("% Wet")
             .with_value_to_string(formatters::v2s_f32_percentage(2))
             .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // Bus compressor detector parameter
+            comp_detector: EnumParam::new("Detector",Choice(crate::bus_compressor::DetectorMode::RMS)),
+
+            // Bus compressor threshold parameter
+            comp_threshold: FloatParam::new(
+                "Comp Threshold",
+                0.0,
+                FloatRange::Linear {
+                    min: -40.0,
+                    max: 0.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" dB Comp Threshold")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            // Bus compressor ratio parameter, 1:1 leaves the signal alone
+            comp_ratio: FloatParam::new(
+                "Ratio",
+                1.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 20.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(":1 Ratio")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            // Bus compressor attack parameter
+            comp_attack: FloatParam::new(
+                "Attack",
+                10.0,
+                FloatRange::Skewed {
+                    min: 0.1,
+                    max: 100.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms Attack")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            // Bus compressor release parameter
+            comp_release: FloatParam::new(
+                "Release",
+                100.0,
+                FloatRange::Skewed {
+                    min: 10.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms Release")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+
+            // Bus compressor program dependent release
+            comp_auto_release: BoolParam::new("Auto Release", false),
+
+            // Bus compressor knee width parameter
+            comp_knee: FloatParam::new(
+                "Knee",
+                6.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" dB Knee")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            // Bus compressor makeup gain parameter
+            comp_makeup: FloatParam::new(
+                "Makeup",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 24.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" dB Makeup")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            // Output ceiling mode parameter
+            ceiling_mode: EnumParam::new("Ceiling Mode",crate::output_ceiling::CeilingMode::OFF),
+
+            // Output ceiling level parameter
+            ceiling: FloatParam::new(
+                "Ceiling",
+                util::db_to_gain(-0.3),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-12.0),
+                    max: util::db_to_gain(0.0),
+                    factor: FloatRange::gain_skew_factor(-12.0, 0.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit(" dB Ceiling")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+        }
+    }
+}
+
+impl GainParams {
+    // Advance every smoother one sample and gather the values for the signal chain
+    pub fn next_settings(&self) -> ChainSettings {
+        self.gather(true)
+    }
+
+    // Current values without touching the smoothers, for rendering outside of a host
+    pub fn settings(&self) -> ChainSettings {
+        self.gather(false)
+    }
+
+    fn gather(&self, smoothed: bool) -> ChainSettings {
+        let float = |param: &FloatParam| if smoothed { param.smoothed.next() } else { param.value() };
+        ChainSettings {
+            free_gain: float(&self.free_gain),
+            threshold: float(&self.threshold),
+            drive: float(&self.drive),
+            console_drive: float(&self.console_drive),
+            console_threshold: float(&self.console_threshold),
+            sat_type: self.sat_type.value().0,
+            console_type: self.console_type.value().0,
+            console_seed: self.console_seed.value(),
+            console_tilt: self.console_tilt.value(),
+            variance: self.variance.value(),
+            channel_number: self.channel_number.value(),
+            variance_amount: self.variance_amount.value(),
+            bias: float(&self.bias),
+            slew_rate: float(&self.slew_rate),
+            bit_depth: float(&self.bit_depth),
+            dither: self.dither.value(),
+            downsample: float(&self.downsample),
+            anti_alias: self.anti_alias.value().0,
+            math_mode: self.math_mode.value().0,
+            harmonic_levels: std::array::from_fn(|i| float(&self.harmonics[i].level)),
+            harmonic_phases: std::array::from_fn(|i| self.harmonics[i].phase.value()),
+            output_gain: float(&self.output_gain),
+            dry_wet: self.dry_wet.value(),
+            comp_detector: self.comp_detector.value().0,
+            comp_threshold: float(&self.comp_threshold),
+            comp_ratio: self.comp_ratio.value(),
+            comp_attack: self.comp_attack.value(),
+            comp_release: self.comp_release.value(),
+            comp_auto_release: self.comp_auto_release.value(),
+            comp_knee: self.comp_knee.value(),
+            comp_makeup: float(&self.comp_makeup),
+            ceiling_mode: self.ceiling_mode.value(),
+            ceiling: float(&self.ceiling),
+        }
+    }
+
+    // Set one parameter of the settings from text, parsed the same way as typing it into the host.
+    // Harmonics are h2_level to h16_level and h2_phase to h16_phase
+    pub fn set_from_string(&self, settings: &mut ChainSettings, id: &str, value: &str) -> Result<(), String> {
+        let normalized = self.parse_normalized(id, value)?;
+        self.set_normalized(settings, id, normalized)
+    }
+
+    // Text to the 0 to 1 value a host automates
+    pub fn parse_normalized(&self, id: &str, value: &str) -> Result<f32, String> {
+        let param = self.param(id)?;
+        param.parse(value).ok_or_else(|| format!("'{value}' is not a valid {}", param.display_name()))
+    }
+
+    // Modes, switches and whole numbers jump between values instead of ramping
+    pub fn is_stepped(&self, id: &str) -> Result<bool, String> {
+        Ok(self.param(id)?.is_stepped())
+    }
+
+    pub fn set_normalized(&self, settings: &mut ChainSettings, id: &str, normalized: f32) -> Result<(), String> {
+        match id {
+            "free_gain" => settings.free_gain = self.free_gain.preview_plain(normalized),
+            "threshold" => settings.threshold = self.threshold.preview_plain(normalized),
+            "drive" => settings.drive = self.drive.preview_plain(normalized),
+            "console_drive" => settings.console_drive = self.console_drive.preview_plain(normalized),
+            "console_threshold" => settings.console_threshold = self.console_threshold.preview_plain(normalized),
+            "type" => settings.sat_type = self.sat_type.preview_plain(normalized).0,
+            "console_type" => settings.console_type = self.console_type.preview_plain(normalized).0,
+            "console_seed" => settings.console_seed = self.console_seed.preview_plain(normalized),
+            "console_tilt" => settings.console_tilt = self.console_tilt.preview_plain(normalized),
+            "variance" => settings.variance = self.variance.preview_plain(normalized),
+            "channel_number" => settings.channel_number = self.channel_number.preview_plain(normalized),
+            "variance_amount" => settings.variance_amount = self.variance_amount.preview_plain(normalized),
+            "bias" => settings.bias = self.bias.preview_plain(normalized),
+            "slew_rate" => settings.slew_rate = self.slew_rate.preview_plain(normalized),
+            "bit_depth" => settings.bit_depth = self.bit_depth.preview_plain(normalized),
+            "dither" => settings.dither = self.dither.preview_plain(normalized),
+            "downsample" => settings.downsample = self.downsample.preview_plain(normalized),
+            "anti_alias" => settings.anti_alias = self.anti_alias.preview_plain(normalized).0,
+            "math_mode" => settings.math_mode = self.math_mode.preview_plain(normalized).0,
+            "output_gain" => settings.output_gain = self.output_gain.preview_plain(normalized),
+            "dry_wet" => settings.dry_wet = self.dry_wet.preview_plain(normalized),
+            "comp_detector" => settings.comp_detector = self.comp_detector.preview_plain(normalized).0,
+            "comp_threshold" => settings.comp_threshold = self.comp_threshold.preview_plain(normalized),
+            "comp_ratio" => settings.comp_ratio = self.comp_ratio.preview_plain(normalized),
+            "comp_attack" => settings.comp_attack = self.comp_attack.preview_plain(normalized),
+            "comp_release" => settings.comp_release = self.comp_release.preview_plain(normalized),
+            "comp_auto_release" => settings.comp_auto_release = self.comp_auto_release.preview_plain(normalized),
+            "comp_knee" => settings.comp_knee = self.comp_knee.preview_plain(normalized),
+            "comp_makeup" => settings.comp_makeup = self.comp_makeup.preview_plain(normalized),
+            "ceiling_mode" => settings.ceiling_mode = self.ceiling_mode.preview_plain(normalized),
+            "ceiling" => settings.ceiling = self.ceiling.preview_plain(normalized),
+            _ => {
+                let index = harmonic_index(id).ok_or_else(|| format!("Unknown parameter '{id}'"))?;
+                if id.ends_with("_level") {
+                    settings.harmonic_levels[index] = self.harmonics[index].level.preview_plain(normalized);
+                } else {
+                    settings.harmonic_phases[index] = self.harmonics[index].phase.preview_plain(normalized);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn param(&self, id: &str) -> Result<&dyn AnyParam, String> {
+        Ok(match id {
+            "free_gain" => &self.free_gain,
+            "threshold" => &self.threshold,
+            "drive" => &self.drive,
+            "console_drive" => &self.console_drive,
+            "console_threshold" => &self.console_threshold,
+            "type" => &self.sat_type,
+            "console_type" => &self.console_type,
+            "console_seed" => &self.console_seed,
+            "console_tilt" => &self.console_tilt,
+            "variance" => &self.variance,
+            "channel_number" => &self.channel_number,
+            "variance_amount" => &self.variance_amount,
+            "bias" => &self.bias,
+            "slew_rate" => &self.slew_rate,
+            "bit_depth" => &self.bit_depth,
+            "dither" => &self.dither,
+            "downsample" => &self.downsample,
+            "anti_alias" => &self.anti_alias,
+            "math_mode" => &self.math_mode,
+            "output_gain" => &self.output_gain,
+            "dry_wet" => &self.dry_wet,
+            "comp_detector" => &self.comp_detector,
+            "comp_threshold" => &self.comp_threshold,
+            "comp_ratio" => &self.comp_ratio,
+            "comp_attack" => &self.comp_attack,
+            "comp_release" => &self.comp_release,
+            "comp_auto_release" => &self.comp_auto_release,
+            "comp_knee" => &self.comp_knee,
+            "comp_makeup" => &self.comp_makeup,
+            "ceiling_mode" => &self.ceiling_mode,
+            "ceiling" => &self.ceiling,
+            _ => {
+                let index = harmonic_index(id).ok_or_else(|| format!("Unknown parameter '{id}'"))?;
+                if id.ends_with("_level") {
+                    &self.harmonics[index].level
+                } else {
+                    &self.harmonics[index].phase
+                }
+            }
+        })
+    }
+
+    // Put every smoother straight at these settings, like a host does when a session loads
+    pub fn reset_smoothers(&self, settings: &ChainSettings) {
+        for (param, value) in self.smoothed_params(settings) {
+            param.smoothed.reset(value);
         }
     }
+
+    // Same as next_settings but for values that didn't come from the host: any smoothed value that
+    // moved from the previous target starts smoothing towards the new one, the way a host's
+    // automation would set it
+    pub fn next_settings_towards(&self, previous: &ChainSettings, target: &ChainSettings, sample_rate: f32) -> ChainSettings {
+        for ((param, previous), (_, value)) in self.smoothed_params(previous).zip(self.smoothed_params(target)) {
+            if value != previous {
+                param.smoothed.set_target(sample_rate, value);
+            }
+        }
+        let smoothed = self.next_settings();
+        ChainSettings {
+            free_gain: smoothed.free_gain,
+            threshold: smoothed.threshold,
+            drive: smoothed.drive,
+            console_drive: smoothed.console_drive,
+            console_threshold: smoothed.console_threshold,
+            bias: smoothed.bias,
+            slew_rate: smoothed.slew_rate,
+            bit_depth: smoothed.bit_depth,
+            downsample: smoothed.downsample,
+            harmonic_levels: smoothed.harmonic_levels,
+            output_gain: smoothed.output_gain,
+            comp_threshold: smoothed.comp_threshold,
+            comp_makeup: smoothed.comp_makeup,
+            ceiling: smoothed.ceiling,
+            ..*target
+        }
+    }
+
+    // The parameters next_settings reads through their smoothers, with their value in the settings.
+    // next_settings_towards runs this every sample, so it doesn't allocate
+    fn smoothed_params(&self, settings: &ChainSettings) -> impl Iterator<Item = (&FloatParam, f32)> {
+        let params = [
+            (&self.free_gain, settings.free_gain),
+            (&self.threshold, settings.threshold),
+            (&self.drive, settings.drive),
+            (&self.console_drive, settings.console_drive),
+            (&self.console_threshold, settings.console_threshold),
+            (&self.bias, settings.bias),
+            (&self.slew_rate, settings.slew_rate),
+            (&self.bit_depth, settings.bit_depth),
+            (&self.downsample, settings.downsample),
+            (&self.output_gain, settings.output_gain),
+            (&self.comp_threshold, settings.comp_threshold),
+            (&self.comp_makeup, settings.comp_makeup),
+            (&self.ceiling, settings.ceiling),
+        ];
+        let harmonics = self.harmonics.iter().zip(settings.harmonic_levels).map(|(harmonic, level)| (&harmonic.level, level));
+        params.into_iter().chain(harmonics)
+    }
+
+    // Every id set_from_string takes with its display name and default value
+    pub fn describe(&self) -> Vec<(String, String, String)> {
+        let mut described = vec![
+            describe_param("free_gain", &self.free_gain),
+            describe_param("threshold", &self.threshold),
+            describe_param("drive", &self.drive),
+            describe_param("console_drive", &self.console_drive),
+            describe_param("console_threshold", &self.console_threshold),
+            describe_enum("type", "Saturation Type", &self.sat_type, Choice::<duro_process::SaturationModeEnum>::variants()),
+            describe_enum("console_type", "Console Type", &self.console_type, Choice::<duro_process::ConsoleMode>::variants()),
+            describe_param("console_seed", &self.console_seed),
+            describe_param("console_tilt", &self.console_tilt),
+            describe_param("variance", &self.variance),
+            describe_param("channel_number", &self.channel_number),
+            describe_param("variance_amount", &self.variance_amount),
+            describe_param("bias", &self.bias),
+            describe_param("slew_rate", &self.slew_rate),
+            describe_param("bit_depth", &self.bit_depth),
+            describe_param("dither", &self.dither),
+            describe_param("downsample", &self.downsample),
+            describe_enum("anti_alias", "Anti-Alias", &self.anti_alias, Choice::<lofi::AntiAliasMode>::variants()),
+            describe_enum("math_mode", "Math", &self.math_mode, Choice::<fast_math::MathMode>::variants()),
+            describe_param("output_gain", &self.output_gain),
+            describe_param("dry_wet", &self.dry_wet),
+            describe_enum("comp_detector", "Detector", &self.comp_detector, Choice::<bus_compressor::DetectorMode>::variants()),
+            describe_param("comp_threshold", &self.comp_threshold),
+            describe_param("comp_ratio", &self.comp_ratio),
+            describe_param("comp_attack", &self.comp_attack),
+            describe_param("comp_release", &self.comp_release),
+            describe_param("comp_auto_release", &self.comp_auto_release),
+            describe_param("comp_knee", &self.comp_knee),
+            describe_param("comp_makeup", &self.comp_makeup),
+            describe_enum("ceiling_mode", "Ceiling Mode", &self.ceiling_mode, output_ceiling::CeilingMode::variants()),
+            describe_param("ceiling", &self.ceiling),
+        ];
+        for (i, harmonic) in self.harmonics.iter().enumerate() {
+            let number = i + harmonics::FIRST_HARMONIC;
+            described.push(describe_param(&format!("h{number}_level"), &harmonic.level));
+            described.push(describe_param(&format!("h{number}_phase"), &harmonic.phase));
+        }
+        described
+    }
+}
+
+// The parts of a parameter that don't depend on its plain type, so any of them can be looked up by id
+trait AnyParam {
+    fn parse(&self, value: &str) -> Option<f32>;
+    fn is_stepped(&self) -> bool;
+    fn display_name(&self) -> &str;
+}
+
+impl<P: Param> AnyParam for P {
+    fn parse(&self, value: &str) -> Option<f32> {
+        self.string_to_normalized_value(value)
+    }
+
+    fn is_stepped(&self) -> bool {
+        self.step_count().is_some()
+    }
+
+    fn display_name(&self) -> &str {
+        self.name()
+    }
+}
+
+fn describe_param<P: Param>(id: &str, param: &P) -> (String, String, String) {
+    let default = param.normalized_value_to_string(param.default_normalized_value(), true);
+    (id.to_string(), param.name().to_string(), default)
+}
+
+// The saturation and console type parameters have no useful name, and the choices are worth listing
+fn describe_enum<P: Param>(id: &str, name: &str, param: &P, variants: &[&str]) -> (String, String, String) {
+    let default = param.normalized_value_to_string(param.default_normalized_value(), false);
+    (id.to_string(), format!("{name} ({})", variants.join(", ")), default)
+}
+
+// Index into the harmonic arrays from an id like h3_level
+fn harmonic_index(id: &str) -> Option<usize> {
+    let number: usize = id
+        .strip_prefix('h')?
+        .strip_suffix("_level")
+        .or_else(|| id.strip_prefix('h')?.strip_suffix("_phase"))?
+        .parse()
+        .ok()?;
+    number
+        .checked_sub(harmonics::FIRST_HARMONIC)
+        .filter(|index| *index < harmonics::NUM_HARMONICS)
 }
 
 impl Plugin for Gain {
@@ -187,21 +892,26 @@ impl Plugin for Gain {
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
 
     type SysExMessage = ();
-    type BackgroundTask = ();
+    type BackgroundTask = DuroTask;
 
     fn params(&self) -> Arc<dyn Params> {
         self.params.clone()
     }
 
-    fn editor(&self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
+    fn editor(&self, async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
         let params = self.params.clone();
-        let in_meter = self.in_meter.clone();
-        let out_meter = self.out_meter.clone();
+        let in_meter = self.engine.meters.input.clone();
+        let out_meter = self.engine.meters.output.clone();
+        let gr_meter = self.engine.meters.gain_reduction.clone();
+        let custom_curve = self.engine.handoff.custom_curve.clone();
+        let shared_formula = self.engine.handoff.formula.clone();
+        let ir_status = self.ir_status.clone();
+        let dynamic_status = self.dynamic_status.clone();
         create_egui_editor(
             self.params.editor_state.clone(),
-            (),
+            EditorMemory::new(&self.params.formula.read().unwrap()),
             |_, _| {},
-            move |egui_ctx, setter, _state| {
+            move |egui_ctx, setter, memory| {
                 egui::CentralPanel::default()
                     .show(egui_ctx, |ui| {
                         // Change colors - there's probably a better way to do this
@@ -271,8 +981,18 @@ impl Plugin for Gain {
                             out_meter_obj.set_border_color(Color32::BLACK);
                             ui.add(out_meter_obj);
 
+                            let gr_meter = gr_meter.load(std::sync::atomic::Ordering::Relaxed);
+                            let gr_meter_text = format!("{gr_meter:.1} dB Gain Reduction");
+                            let gr_meter_normalized = gr_meter / GR_METER_RANGE_DB;
+                            ui.allocate_space(egui::Vec2::splat(2.0));
+                            let mut gr_meter_obj = db_meter::DBMeter::new(gr_meter_normalized).text(gr_meter_text);
+                            gr_meter_obj.set_background_color(METERBACKGROUND);
+                            gr_meter_obj.set_bar_color(TEAL);
+                            gr_meter_obj.set_border_color(Color32::BLACK);
+                            ui.add(gr_meter_obj);
+
                             ui.horizontal(|ui| {
-                                let knob_size = 44.0;
+                                let knob_size = 40.0;
 
                                 let mut gain_knob = ui_knob::ArcKnob::for_param(&params.free_gain, setter, knob_size);
                                 gain_knob.preset_style(ui_knob::KnobStyle::SmallTogether);
@@ -303,6 +1023,12 @@ impl Plugin for Gain {
                                 console_knob.set_fill_color(MACARONI);
                                 console_knob.set_line_color(ORANGE);
                                 ui.add(console_knob);
+
+                                let mut console_drive_knob = ui_knob::ArcKnob::for_param(&params.console_drive, setter, knob_size);
+                                console_drive_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                console_drive_knob.set_fill_color(MACARONI);
+                                console_drive_knob.set_line_color(ORANGE);
+                                ui.add(console_drive_knob);
     
                                 let mut output_knob = ui_knob::ArcKnob::for_param(&params.output_gain, setter, knob_size);
                                 output_knob.preset_style(ui_knob::KnobStyle::SmallTogether);
@@ -316,6 +1042,246 @@ impl Plugin for Gain {
                                 dry_wet_knob.set_line_color(LIGHTTEAL);
                                 ui.add(dry_wet_knob);
                             });
+
+                            // Saturation mode specific controls
+                            ui.horizontal(|ui| {
+                                let knob_size = 40.0;
+
+                                let mut bias_knob = ui_knob::ArcKnob::for_param(&params.bias, setter, knob_size);
+                                bias_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                bias_knob.set_fill_color(MACARONI);
+                                bias_knob.set_line_color(ORANGE);
+                                ui.add(bias_knob);
+
+                                let mut slew_knob = ui_knob::ArcKnob::for_param(&params.slew_rate, setter, knob_size);
+                                slew_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                slew_knob.set_fill_color(MACARONI);
+                                slew_knob.set_line_color(ORANGE);
+                                ui.add(slew_knob);
+
+                                let mut bit_depth_knob = ui_knob::ArcKnob::for_param(&params.bit_depth, setter, knob_size);
+                                bit_depth_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                bit_depth_knob.set_fill_color(MACARONI);
+                                bit_depth_knob.set_line_color(ORANGE);
+                                ui.add(bit_depth_knob);
+
+                                let mut dither_knob = ui_knob::ArcKnob::for_param(&params.dither, setter, knob_size);
+                                dither_knob.preset_style(ui_knob::KnobStyle::SmallTogether);
+                                dither_knob.set_fill_color(MACARONI);
+                                dither_knob.set_line_color(ORANGE);
+                                ui.add(dither_knob);
+
+                                let mut downsample_knob = ui_knob::ArcKnob::for_param(&params.downsample, setter, knob_size);
+                                downsample_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                downsample_knob.set_fill_color(MACARONI);
+                                downsample_knob.set_line_color(ORANGE);
+                                ui.add(downsample_knob);
+
+                                let mut anti_alias_knob = ui_knob::ArcKnob::for_param(&params.anti_alias, setter, knob_size);
+                                anti_alias_knob.preset_style(ui_knob::KnobStyle::SmallTogether);
+                                anti_alias_knob.set_fill_color(MACARONI);
+                                anti_alias_knob.set_line_color(ORANGE);
+                                ui.add(anti_alias_knob);
+
+                                let mut math_mode_knob = ui_knob::ArcKnob::for_param(&params.math_mode, setter, knob_size);
+                                math_mode_knob.preset_style(ui_knob::KnobStyle::SmallTogether);
+                                math_mode_knob.set_fill_color(MACARONI);
+                                math_mode_knob.set_line_color(ORANGE);
+                                ui.add(math_mode_knob);
+                            });
+
+                            ui.horizontal(|ui| {
+                                // Harmonic generator spectrum
+                                let mut harmonic_graph = harmonic_graph::HarmonicGraph::for_params(&params.harmonics, setter, egui::vec2(WIDTH as f32 - 170.0, 120.0));
+                                harmonic_graph.set_background_color(METERBACKGROUND);
+                                harmonic_graph.set_bar_color(MACARONI);
+                                harmonic_graph.set_line_color(ORANGE);
+                                ui.add(harmonic_graph);
+
+                                // Custom Curve transfer function
+                                let mut curve_editor = curve_editor::CurveEditor::new(&params.custom_curve, &custom_curve, egui::vec2(120.0, 120.0));
+                                curve_editor.set_background_color(METERBACKGROUND);
+                                curve_editor.set_line_color(MACARONI);
+                                curve_editor.set_point_color(ORANGE);
+                                ui.add(curve_editor);
+                            });
+
+                            // Formula saturation, only a formula that parses reaches the audio thread
+                            ui.horizontal(|ui| {
+                                ui.label(RichText::new("Formula").color(MACARONI));
+                                let mut text = params.formula.write().unwrap();
+                                ui.add(egui::TextEdit::singleline(&mut *text).desired_width(WIDTH as f32 / 2.0));
+                                // Typed in or loaded with a session
+                                if *text != memory.formula_text {
+                                    memory.formula_text.clone_from(&text);
+                                    memory.formula = formula::compile(&text);
+                                    if let Ok(program) = &memory.formula {
+                                        shared_formula.write(program);
+                                    }
+                                }
+                                if let Err(error) = &memory.formula {
+                                    ui.label(RichText::new(error.to_string()).color(Color32::LIGHT_RED));
+                                }
+                            });
+
+                            // Bus compressor controls
+                            ui.horizontal(|ui| {
+                                let knob_size = 40.0;
+
+                                let mut detector_knob = ui_knob::ArcKnob::for_param(&params.comp_detector, setter, knob_size);
+                                detector_knob.preset_style(ui_knob::KnobStyle::SmallTogether);
+                                detector_knob.set_fill_color(TEAL);
+                                detector_knob.set_line_color(LIGHTTEAL);
+                                ui.add(detector_knob);
+
+                                let mut comp_threshold_knob = ui_knob::ArcKnob::for_param(&params.comp_threshold, setter, knob_size);
+                                comp_threshold_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                comp_threshold_knob.set_fill_color(TEAL);
+                                comp_threshold_knob.set_line_color(LIGHTTEAL);
+                                ui.add(comp_threshold_knob);
+
+                                let mut ratio_knob = ui_knob::ArcKnob::for_param(&params.comp_ratio, setter, knob_size);
+                                ratio_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                ratio_knob.set_fill_color(TEAL);
+                                ratio_knob.set_line_color(LIGHTTEAL);
+                                ui.add(ratio_knob);
+
+                                let mut attack_knob = ui_knob::ArcKnob::for_param(&params.comp_attack, setter, knob_size);
+                                attack_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                attack_knob.set_fill_color(MACARONI);
+                                attack_knob.set_line_color(ORANGE);
+                                ui.add(attack_knob);
+
+                                let mut release_knob = ui_knob::ArcKnob::for_param(&params.comp_release, setter, knob_size);
+                                release_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                release_knob.set_fill_color(MACARONI);
+                                release_knob.set_line_color(ORANGE);
+                                ui.add(release_knob);
+
+                                let mut auto_release_knob = ui_knob::ArcKnob::for_param(&params.comp_auto_release, setter, knob_size);
+                                auto_release_knob.preset_style(ui_knob::KnobStyle::SmallTogether);
+                                auto_release_knob.set_fill_color(MACARONI);
+                                auto_release_knob.set_line_color(ORANGE);
+                                ui.add(auto_release_knob);
+
+                                let mut knee_knob = ui_knob::ArcKnob::for_param(&params.comp_knee, setter, knob_size);
+                                knee_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                knee_knob.set_fill_color(TEAL);
+                                knee_knob.set_line_color(LIGHTTEAL);
+                                ui.add(knee_knob);
+
+                                let mut makeup_knob = ui_knob::ArcKnob::for_param(&params.comp_makeup, setter, knob_size);
+                                makeup_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                makeup_knob.set_fill_color(TEAL);
+                                makeup_knob.set_line_color(LIGHTTEAL);
+                                ui.add(makeup_knob);
+                            });
+
+                            // Output ceiling controls
+                            ui.horizontal(|ui| {
+                                let knob_size = 40.0;
+
+                                let mut ceiling_mode_knob = ui_knob::ArcKnob::for_param(&params.ceiling_mode, setter, knob_size);
+                                ceiling_mode_knob.preset_style(ui_knob::KnobStyle::SmallTogether);
+                                ceiling_mode_knob.set_fill_color(TEAL);
+                                ceiling_mode_knob.set_line_color(LIGHTTEAL);
+                                ui.add(ceiling_mode_knob);
+
+                                let mut ceiling_knob = ui_knob::ArcKnob::for_param(&params.ceiling, setter, knob_size);
+                                ceiling_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                ceiling_knob.set_fill_color(TEAL);
+                                ceiling_knob.set_line_color(LIGHTTEAL);
+                                ui.add(ceiling_knob);
+
+                                let mut console_threshold_knob = ui_knob::ArcKnob::for_param(&params.console_threshold, setter, knob_size);
+                                console_threshold_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                console_threshold_knob.set_fill_color(MACARONI);
+                                console_threshold_knob.set_line_color(ORANGE);
+                                ui.add(console_threshold_knob);
+
+                                // Channel variance controls
+                                let mut variance_knob = ui_knob::ArcKnob::for_param(&params.variance, setter, knob_size);
+                                variance_knob.preset_style(ui_knob::KnobStyle::SmallTogether);
+                                variance_knob.set_fill_color(TEAL);
+                                variance_knob.set_line_color(LIGHTTEAL);
+                                ui.add(variance_knob);
+
+                                let mut channel_number_knob = ui_knob::ArcKnob::for_param(&params.channel_number, setter, knob_size);
+                                channel_number_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                channel_number_knob.set_fill_color(TEAL);
+                                channel_number_knob.set_line_color(LIGHTTEAL);
+                                ui.add(channel_number_knob);
+
+                                let mut variance_amount_knob = ui_knob::ArcKnob::for_param(&params.variance_amount, setter, knob_size);
+                                variance_amount_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                variance_amount_knob.set_fill_color(TEAL);
+                                variance_amount_knob.set_line_color(LIGHTTEAL);
+                                ui.add(variance_amount_knob);
+
+                                // Generated console controls
+                                let mut seed_knob = ui_knob::ArcKnob::for_param(&params.console_seed, setter, knob_size);
+                                seed_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                seed_knob.set_fill_color(TEAL);
+                                seed_knob.set_line_color(LIGHTTEAL);
+                                ui.add(seed_knob);
+
+                                let mut tilt_knob = ui_knob::ArcKnob::for_param(&params.console_tilt, setter, knob_size);
+                                tilt_knob.preset_style(ui_knob::KnobStyle::SmallMedium);
+                                tilt_knob.set_fill_color(TEAL);
+                                tilt_knob.set_line_color(LIGHTTEAL);
+                                ui.add(tilt_knob);
+                            });
+
+                            // Saved seeds, click one to load it into the Generated Console or right click to forget it
+                            ui.horizontal(|ui| {
+                                let seed = params.console_seed.value();
+                                if ui.button(RichText::new("Save Seed").color(LIGHTTEAL)).clicked() {
+                                    let mut favorites = params.seed_favorites.write().unwrap();
+                                    if !favorites.contains(&seed) {
+                                        favorites.push(seed);
+                                    }
+                                }
+
+                                let favorites = params.seed_favorites.read().unwrap().clone();
+                                for favorite in favorites {
+                                    let button = ui.button(RichText::new(favorite.to_string()).color(MACARONI));
+                                    if button.clicked() {
+                                        setter.begin_set_parameter(&params.console_seed);
+                                        setter.set_parameter(&params.console_seed, favorite);
+                                        setter.end_set_parameter(&params.console_seed);
+                                        setter.begin_set_parameter(&params.console_type);
+                                        setter.set_parameter(&params.console_type, Choice(duro_process::ConsoleMode::GENERATED));
+                                        setter.end_set_parameter(&params.console_type);
+                                    }
+                                    if button.secondary_clicked() {
+                                        params.seed_favorites.write().unwrap().retain(|saved| *saved != favorite);
+                                    }
+                                }
+                            });
+
+                            // Impulse Response console, loading happens in the background
+                            ui.horizontal(|ui| {
+                                ui.label(RichText::new("IR File").color(LIGHTTEAL));
+                                let mut path = params.ir_path.write().unwrap();
+                                ui.add(egui::TextEdit::singleline(&mut *path).desired_width(WIDTH as f32 / 2.0));
+                                if ui.button(RichText::new("Load IR").color(LIGHTTEAL)).clicked() {
+                                    async_executor.execute_background(DuroTask::LoadImpulse(path.clone()));
+                                }
+                                drop(path);
+                                ui.label(RichText::new(ir_status.read().unwrap().as_str()).color(MACARONI));
+                            });
+
+                            // Dynamic Response console, a folder of kernels named by their capture level
+                            ui.horizontal(|ui| {
+                                ui.label(RichText::new("Kernels").color(LIGHTTEAL));
+                                let mut folder = params.kernel_folder.write().unwrap();
+                                ui.add(egui::TextEdit::singleline(&mut *folder).desired_width(WIDTH as f32 / 2.0));
+                                if ui.button(RichText::new("Load Kernels").color(LIGHTTEAL)).clicked() {
+                                    async_executor.execute_background(DuroTask::LoadKernels(folder.clone()));
+                                }
+                                drop(folder);
+                                ui.label(RichText::new(dynamic_status.read().unwrap().as_str()).color(MACARONI));
+                            });
                         });
                     });
                 }
@@ -326,11 +1292,44 @@ impl Plugin for Gain {
         &mut self,
         _audio_io_layout: &AudioIOLayout,
         buffer_config: &BufferConfig,
-        _context: &mut impl InitContext<Self>,
+        context: &mut impl InitContext<Self>,
     ) -> bool {
-        // After `PEAK_METER_DECAY_MS` milliseconds of pure silence, the peak meter's value should
-        // have dropped by 12 dB
-        self.out_meter_decay_weight = 0.25f64.powf((buffer_config.sample_rate as f64 * PEAK_METER_DECAY_MS / 1000.0).recip()) as f32;
+        self.engine.meters.set_sample_rate(buffer_config.sample_rate);
+
+        self.process_mode = buffer_config.process_mode;
+
+        // The impulse response is resampled to the session rate so load it again now
+        self.sample_rate.store(buffer_config.sample_rate, std::sync::atomic::Ordering::Relaxed);
+        let ir_path = self.params.ir_path.read().unwrap().clone();
+        if !ir_path.is_empty() {
+            context.execute(DuroTask::LoadImpulse(ir_path));
+        }
+        let kernel_folder = self.params.kernel_folder.read().unwrap().clone();
+        if !kernel_folder.is_empty() {
+            context.execute(DuroTask::LoadKernels(kernel_folder));
+        }
+
+        // Build the Generated Console for whatever seed and tilt came in with the plugin state
+        self.generated_request = (self.params.console_seed.value(), self.params.console_tilt.value());
+        context.execute(DuroTask::GenerateConsole);
+
+        // The ceiling's lookahead depends on the sample rate
+        self.engine.chain.set_sample_rate(buffer_config.sample_rate);
+        self.engine.chain.update_ceiling(self.params.ceiling_mode.value(), self.params.ceiling.value());
+        context.set_latency_samples(self.engine.chain.latency_samples());
+
+        // Bake whatever curve came in with the plugin state
+        let mut points = self.params.custom_curve.write().unwrap();
+        custom_curve::sanitize_points(&mut points);
+        self.engine.handoff.custom_curve.write(&custom_curve::build_table(&points));
+        drop(points);
+
+        // Same for the formula, one that doesn't parse leaves the last good formula running
+        let text = self.params.formula.write().unwrap();
+        if let Ok(program) = formula::compile(&text) {
+            self.engine.handoff.formula.write(&program);
+        }
+        drop(text);
 
         true
     }
@@ -339,66 +1338,39 @@ impl Plugin for Gain {
         &mut self,
         buffer: &mut Buffer,
         _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
+        context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
 
         //widgets::ParamEvent
+        let realtime = matches!(self.process_mode, ProcessMode::Realtime);
+        let block_settings = self.params.settings();
+        self.engine.start_block(&block_settings, realtime);
+
+        // A new seed or tilt is built in the background and picked up at the start of a later buffer
+        let generated_request = (block_settings.console_seed, block_settings.console_tilt);
+        if generated_request != self.generated_request {
+            self.generated_request = generated_request;
+            context.execute_background(DuroTask::GenerateConsole);
+        }
+
+        // To save resources, a plugin can (and probably should!) only perform expensive
+        // calculations that are only displayed on the GUI while the GUI is open
+        let metering = self.params.editor_state.is_open();
+
         // Buffer level
-        for channel_samples in buffer.iter_samples() {
-            let mut out_amplitude = 0.0;
-            let mut in_amplitude = 0.0;
-            let mut processed_sample;
-            let num_samples = channel_samples.len();
-
-            let gain = util::gain_to_db(self.params.free_gain.smoothed.next());
-            let mut num_gain: f32;
-            let drive = self.params.drive.smoothed.next();
-            let threshold = self.params.threshold.smoothed.next();
-            let output_gain = self.params.output_gain.smoothed.next();
-            let sat_type = self.params.sat_type.value();
-            let console_type = self.params.console_type.value();
-            let dry_wet = self.params.dry_wet.value();
-
-            // Create the compressor object
-            self.console.update_vals(threshold,drive,console_type,_context.transport().sample_rate);
-
-            for sample in channel_samples {
-                num_gain = gain;
-                
-                //nih_log!("{}  {}",gain,num_gain);
-                
-                *sample *= util::db_to_gain(num_gain);
-                
-                in_amplitude += *sample;
-
-                // Perform processing on the sample
-                processed_sample = self.console.duro_process(*sample, sat_type, console_type);
-
-                // Calculate dry/wet mix (no compression but saturation possible)
-                let wet_gain = dry_wet;
-                let dry_gain = 1.0 - dry_wet;
-                processed_sample = *sample * dry_gain + processed_sample * wet_gain;
-
-                // get the output amplitude here
-                processed_sample = processed_sample*output_gain;
-                *sample = processed_sample;
-                out_amplitude += processed_sample;
+        for mut channel_samples in buffer.iter_samples() {
+            let mut frame = [0.0; duro_process::MAX_CHANNELS];
+            let frame_channels = channel_samples.len().min(duro_process::MAX_CHANNELS);
+            for (sample, frame_sample) in channel_samples.iter_mut().zip(frame.iter_mut()) {
+                *frame_sample = *sample;
             }
 
-            // To save resources, a plugin can (and probably should!) only perform expensive
-            // calculations that are only displayed on the GUI while the GUI is open
-            if self.params.editor_state.is_open() {
-                // Input gain meter
-                in_amplitude = (in_amplitude / num_samples as f32).abs();
-                let current_in_meter = self.in_meter.load(std::sync::atomic::Ordering::Relaxed);
-                let new_in_meter = if in_amplitude > current_in_meter {in_amplitude}                                else {current_in_meter * self.out_meter_decay_weight + in_amplitude * (1.0 - self.out_meter_decay_weight)};
-                self.in_meter.store(new_in_meter, std::sync::atomic::Ordering::Relaxed);
-
-                // Output gain meter
-                out_amplitude = (out_amplitude / num_samples as f32).abs();
-                let current_out_meter = self.out_meter.load(std::sync::atomic::Ordering::Relaxed);
-                let new_out_meter = if out_amplitude > current_out_meter {out_amplitude}                            else {current_out_meter * self.out_meter_decay_weight + out_amplitude * (1.0 - self.out_meter_decay_weight)};
-                self.out_meter.store(new_out_meter, std::sync::atomic::Ordering::Relaxed);
+            // Switching the ceiling mode changes our latency
+            if self.engine.process_frame(&mut frame[..frame_channels], &self.params.next_settings(), metering) {
+                context.set_latency_samples(self.engine.chain.latency_samples());
+            }
+            for (sample, processed_sample) in channel_samples.iter_mut().zip(frame) {
+                *sample = processed_sample;
             }
         }
 
@@ -412,15 +1384,78 @@ impl Plugin for Gain {
     const HARD_REALTIME_ONLY: bool = false;
 
     fn task_executor(&self) -> TaskExecutor<Self> {
-        // In the default implementation we can simply ignore the value
-        Box::new(|_| ())
+        let params = self.params.clone();
+        let generated = self.engine.handoff.generated.clone();
+        let generated_from = self.generated_from.clone();
+        let convolver_slot = self.engine.handoff.convolver_slot.clone();
+        let dynamic_slot = self.engine.handoff.dynamic_slot.clone();
+        let ir_status = self.ir_status.clone();
+        let dynamic_status = self.dynamic_status.clone();
+        let sample_rate = self.sample_rate.clone();
+        Box::new(move |task| match task {
+            DuroTask::LoadImpulse(path) => {
+                let sample_rate = sample_rate.load(std::sync::atomic::Ordering::Relaxed);
+                match convolution::load_impulse(&path, sample_rate) {
+                    Ok(impulse) => {
+                        let convolver = Box::new(convolution::Convolver::new(&impulse));
+                        *ir_status.write().unwrap() = format!("Loaded {:.2} s", impulse.len() as f32 / sample_rate);
+                        // Freeing the convolver the audio thread retired happens here too
+                        convolver_slot.lock().unwrap().offer(convolver);
+                    }
+                    Err(error) => *ir_status.write().unwrap() = error,
+                }
+            }
+            DuroTask::LoadKernels(folder) => {
+                let sample_rate = sample_rate.load(std::sync::atomic::Ordering::Relaxed);
+                match dynamic_convolution::load_kernels(&folder, sample_rate) {
+                    Ok(kernels) => {
+                        let dynamic = Box::new(dynamic_convolution::DynamicConvolver::new(&kernels, sample_rate));
+                        let levels: Vec<String> = kernels.iter().map(|(level, _)| format!("{level}")).collect();
+                        *dynamic_status.write().unwrap() = format!("Loaded {} kernels at {} dBFS", kernels.len(), levels.join(", "));
+                        dynamic_slot.lock().unwrap().offer(dynamic);
+                    }
+                    Err(error) => *dynamic_status.write().unwrap() = error,
+                }
+            }
+            DuroTask::GenerateConsole => {
+                // Read the parameters now rather than when the task was queued, so a task dropped from a
+                // full queue during automation is made up for by any later one
+                let wanted = (params.console_seed.value(), params.console_tilt.value());
+                let mut built = generated_from.lock().unwrap();
+                if *built != wanted {
+                    generated.write(&console_models::generate_taps(wanted.0, wanted.1));
+                    *built = wanted;
+                }
+            }
+        })
     }
 
-    fn filter_state(_state: &mut PluginState) {}
+    // Sessions saved before the drive was split fed the console with their one drive, give the console
+    // drive the same value so they still sound the way they were saved
+    fn filter_state(state: &mut PluginState) {
+        if !state.params.contains_key("console_drive") {
+            if let Some(drive) = state.params.get("drive").cloned() {
+                state.params.insert("console_drive".to_string(), drive);
+            }
+        }
+        // Off, as the console played before it had a threshold of its own
+        if !state.params.contains_key("console_threshold") {
+            state.params.insert("console_threshold".to_string(), ParamValue::F32(0.0));
+        }
+    }
 
-    fn reset(&mut self) {}
+    // Called after every activation and on transport jumps: start from silence in the current modes
+    // instead of fading in from bypass or from whatever was playing before
+    fn reset(&mut self) {
+        self.engine.chain.reset();
+        self.engine.chain.jump_to(&self.params.settings());
+        self.engine.meters.clear();
+    }
 
-    fn deactivate(&mut self) {}
+    // Nothing is processed until the next activation, so don't leave the meters frozen on the last block
+    fn deactivate(&mut self) {
+        self.engine.meters.clear();
+    }
 }
 
 impl ClapPlugin for Gain {
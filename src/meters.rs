@@ -0,0 +1,62 @@
+// meters.rs - Ardura 2023
+// Input, output and gain reduction levels for the editor's meters, written from the audio thread
+
+use std::sync::{atomic::Ordering, Arc};
+use atomic_float::AtomicF32;
+use nih_plug::util;
+
+/// The time it takes for the peak meter to decay by 12 dB after switching to complete silence.
+const PEAK_METER_DECAY_MS: f64 = 100.0;
+
+pub struct Meters {
+    pub input: Arc<AtomicF32>,
+    pub output: Arc<AtomicF32>,
+    pub gain_reduction: Arc<AtomicF32>,
+    // normalize the peak meter's response based on the sample rate with this
+    decay_weight: f32,
+}
+
+impl Meters {
+    pub fn new() -> Self {
+        Self {
+            input: Arc::new(AtomicF32::new(util::MINUS_INFINITY_DB)),
+            output: Arc::new(AtomicF32::new(util::MINUS_INFINITY_DB)),
+            gain_reduction: Arc::new(AtomicF32::new(0.0)),
+            decay_weight: 1.0,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        // After `PEAK_METER_DECAY_MS` milliseconds of pure silence, the peak meter's value should
+        // have dropped by 12 dB
+        self.decay_weight = 0.25f64.powf((sample_rate as f64 * PEAK_METER_DECAY_MS / 1000.0).recip()) as f32;
+    }
+
+    // Back to what the meters show before anything has played
+    pub fn clear(&self) {
+        self.input.store(util::MINUS_INFINITY_DB, Ordering::Relaxed);
+        self.output.store(util::MINUS_INFINITY_DB, Ordering::Relaxed);
+        self.gain_reduction.store(0.0, Ordering::Relaxed);
+    }
+
+    // One sample frame: the input and output summed over its channels, and the compressor's gain
+    // reduction which is already smoothed
+    pub fn update(&self, in_amplitude: f32, out_amplitude: f32, channels: usize, gain_reduction_db: f32) {
+        Self::follow(&self.input, (in_amplitude / channels as f32).abs(), self.decay_weight);
+        Self::follow(&self.output, (out_amplitude / channels as f32).abs(), self.decay_weight);
+        self.gain_reduction.store(gain_reduction_db, Ordering::Relaxed);
+    }
+
+    // Jump up to a new peak, decay towards anything lower
+    fn follow(meter: &AtomicF32, amplitude: f32, decay_weight: f32) {
+        let current = meter.load(Ordering::Relaxed);
+        let new = if amplitude > current { amplitude } else { current * decay_weight + amplitude * (1.0 - decay_weight) };
+        meter.store(new, Ordering::Relaxed);
+    }
+}
+
+impl Default for Meters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
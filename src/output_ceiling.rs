@@ -0,0 +1,232 @@
+// output_ceiling.rs - Ardura 2023
+// Safety stage after the output gain: a true peak aware soft clipper or a short lookahead limiter
+
+use std::f32::consts::PI;
+use nih_plug::prelude::Enum;
+use crate::duro_process::MAX_CHANNELS;
+
+#[derive(Enum, PartialEq, Eq, Debug, Copy, Clone)]
+pub enum CeilingMode {
+    #[name = "Ceiling Off"]
+    OFF,
+    #[name = "True Peak Clipper"]
+    CLIPPER,
+    #[name = "Lookahead Limiter"]
+    LIMITER,
+}
+
+// Oversampling factor used by the clipper and the true peak detector
+const OVERSAMPLE: usize = 4;
+// Odd length linear phase lowpass so the group delay is a whole number of samples
+const FIR_TAPS: usize = 49;
+// Taps per polyphase branch of the upsampler
+const PHASE_TAPS: usize = FIR_TAPS.div_ceil(OVERSAMPLE);
+// Group delay of one filter in samples at the original rate
+const FILTER_DELAY: usize = (FIR_TAPS - 1) / 2 / OVERSAMPLE;
+// Upsampling and decimating each add one filter delay
+const CLIPPER_LATENCY: usize = 2 * FILTER_DELAY;
+const LOOKAHEAD_MS: f32 = 1.5;
+const RELEASE_MS: f32 = 60.0;
+// Enough lookahead for 1.5ms at 192kHz with some room to spare
+const MAX_LOOKAHEAD: usize = 384;
+const MAX_DELAY: usize = MAX_LOOKAHEAD + FILTER_DELAY;
+// Where the clipper leaves the signal alone, relative to the ceiling
+const CLIPPER_KNEE: f32 = 0.8;
+
+pub struct OutputCeiling {
+    mode: CeilingMode,
+    ceiling: f32,
+    sample_rate: f32,
+    lookahead: usize,
+    release_coeff: f32,
+    fir: [f32; FIR_TAPS],
+    // Input history for the polyphase upsampler
+    up_history: [[f32; PHASE_TAPS]; MAX_CHANNELS],
+    // Oversampled history for the decimator
+    down_history: [[f32; FIR_TAPS]; MAX_CHANNELS],
+    down_pos: usize,
+    // Limiter state
+    delay_line: [[f32; MAX_DELAY]; MAX_CHANNELS],
+    delay_pos: usize,
+    required_gain: [f32; MAX_LOOKAHEAD],
+    held_gain: [f32; MAX_LOOKAHEAD],
+    held_sum: f64,
+    gain_pos: usize,
+    gain: f32,
+}
+
+impl OutputCeiling {
+    pub fn new(sample_rate: f32) -> Self {
+        // Windowed sinc lowpass at 90% of the original Nyquist, running at the oversampled rate
+        let mut fir = [0.0; FIR_TAPS];
+        let cutoff = 0.9 * 0.5 / OVERSAMPLE as f32;
+        let center = (FIR_TAPS - 1) as f32 / 2.0;
+        for (k, tap) in fir.iter_mut().enumerate() {
+            let t = k as f32 - center;
+            let sinc = if t == 0.0 { 2.0 * cutoff } else { (2.0 * PI * cutoff * t).sin() / (PI * t) };
+            let phase = 2.0 * PI * k as f32 / (FIR_TAPS - 1) as f32;
+            let blackman = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+            *tap = sinc * blackman;
+        }
+        let sum: f32 = fir.iter().sum();
+        for tap in fir.iter_mut() {
+            *tap /= sum;
+        }
+
+        let mut ceiling = Self {
+            mode: CeilingMode::OFF,
+            ceiling: 1.0,
+            sample_rate,
+            lookahead: 1,
+            release_coeff: 0.0,
+            fir,
+            up_history: [[0.0; PHASE_TAPS]; MAX_CHANNELS],
+            down_history: [[0.0; FIR_TAPS]; MAX_CHANNELS],
+            down_pos: 0,
+            delay_line: [[0.0; MAX_DELAY]; MAX_CHANNELS],
+            delay_pos: 0,
+            required_gain: [1.0; MAX_LOOKAHEAD],
+            held_gain: [1.0; MAX_LOOKAHEAD],
+            held_sum: 0.0,
+            gain_pos: 0,
+            gain: 1.0,
+        };
+        ceiling.set_sample_rate(sample_rate);
+        ceiling
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.lookahead = ((LOOKAHEAD_MS * 0.001 * sample_rate).ceil() as usize).clamp(1, MAX_LOOKAHEAD);
+        self.release_coeff = (-1.0 / (RELEASE_MS * 0.001 * sample_rate)).exp();
+        self.reset();
+    }
+
+    // Returns true when the mode changed so the caller can report the new latency
+    pub fn update_vals(&mut self, mode: CeilingMode, ceiling: f32) -> bool {
+        self.ceiling = ceiling;
+        if mode != self.mode {
+            self.mode = mode;
+            self.reset();
+            return true;
+        }
+        false
+    }
+
+    // Latency of the current mode in samples
+    pub fn latency_samples(&self) -> u32 {
+        match self.mode {
+            CeilingMode::OFF => 0,
+            CeilingMode::CLIPPER => CLIPPER_LATENCY as u32,
+            CeilingMode::LIMITER => (self.lookahead - 1 + FILTER_DELAY) as u32,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.up_history = [[0.0; PHASE_TAPS]; MAX_CHANNELS];
+        self.down_history = [[0.0; FIR_TAPS]; MAX_CHANNELS];
+        self.down_pos = 0;
+        self.delay_line = [[0.0; MAX_DELAY]; MAX_CHANNELS];
+        self.delay_pos = 0;
+        self.required_gain = [1.0; MAX_LOOKAHEAD];
+        self.held_gain = [1.0; MAX_LOOKAHEAD];
+        self.held_sum = self.lookahead as f64;
+        self.gain_pos = 0;
+        self.gain = 1.0;
+    }
+
+    // Process one sample frame in place, channels are linked so the stereo image holds
+    pub fn process_frame(&mut self, frame: &mut [f32]) {
+        match self.mode {
+            CeilingMode::OFF => {}
+            CeilingMode::CLIPPER => self.clip_frame(frame),
+            CeilingMode::LIMITER => self.limit_frame(frame),
+        }
+    }
+
+    // Push a sample into the upsampler and get the OVERSAMPLE interpolated samples back
+    fn upsample(&mut self, channel: usize, sample: f32) -> [f32; OVERSAMPLE] {
+        let history = &mut self.up_history[channel];
+        history.copy_within(0..PHASE_TAPS - 1, 1);
+        history[0] = sample;
+
+        let mut upsampled = [0.0; OVERSAMPLE];
+        for (phase, value) in upsampled.iter_mut().enumerate() {
+            let mut sum = 0.0;
+            for (k, tap) in self.fir.iter().skip(phase).step_by(OVERSAMPLE).enumerate() {
+                sum += tap * history[k];
+            }
+            // Zero stuffing loses this much gain
+            *value = sum * OVERSAMPLE as f32;
+        }
+        upsampled
+    }
+
+    fn clip_frame(&mut self, frame: &mut [f32]) {
+        let knee = self.ceiling * CLIPPER_KNEE;
+        let headroom = self.ceiling - knee;
+        for (channel, sample) in frame.iter_mut().enumerate().take(MAX_CHANNELS) {
+            let upsampled = self.upsample(channel, *sample);
+
+            // Soft clip at the oversampled rate so the curve doesn't alias back down
+            let history = &mut self.down_history[channel];
+            let mut pos = self.down_pos;
+            let mut decimated = 0.0;
+            for (phase, value) in upsampled.into_iter().enumerate() {
+                let magnitude = value.abs();
+                let clipped = if magnitude <= knee {
+                    value
+                } else {
+                    value.signum() * (knee + headroom * ((magnitude - knee) / headroom).tanh())
+                };
+                history[pos] = clipped;
+
+                // Decimate on the first phase since it lines up with the original samples
+                if phase == 0 {
+                    for (k, tap) in self.fir.iter().enumerate() {
+                        decimated += tap * history[(pos + FIR_TAPS - k) % FIR_TAPS];
+                    }
+                }
+                pos = (pos + 1) % FIR_TAPS;
+            }
+            *sample = decimated.clamp(-self.ceiling, self.ceiling);
+        }
+        self.down_pos = (self.down_pos + OVERSAMPLE) % FIR_TAPS;
+    }
+
+    fn limit_frame(&mut self, frame: &mut [f32]) {
+        let delay = self.lookahead - 1 + FILTER_DELAY;
+
+        // Find the true peak of this frame through the upsampler
+        let mut true_peak: f32 = 0.0;
+        for (channel, sample) in frame.iter().enumerate().take(MAX_CHANNELS) {
+            for value in self.upsample(channel, *sample) {
+                true_peak = true_peak.max(value.abs());
+            }
+            self.delay_line[channel][self.delay_pos] = *sample;
+        }
+        let required = if true_peak > self.ceiling { self.ceiling / true_peak } else { 1.0 };
+
+        // Hold the lowest gain over the lookahead window, then average it over the same window so the
+        // gain has fully ramped down by the time the peak comes out of the delay line
+        self.required_gain[self.gain_pos] = required;
+        let held = self.required_gain[..self.lookahead].iter().fold(1.0_f32, |a, &b| a.min(b));
+        self.held_sum += held as f64 - self.held_gain[self.gain_pos] as f64;
+        self.held_gain[self.gain_pos] = held;
+        self.gain_pos = (self.gain_pos + 1) % self.lookahead;
+        let averaged = (self.held_sum / self.lookahead as f64) as f32;
+
+        // Let the gain come back up slowly
+        self.gain = if averaged < self.gain {
+            averaged
+        } else {
+            averaged + self.release_coeff * (self.gain - averaged)
+        };
+
+        let read_pos = (self.delay_pos + MAX_DELAY - delay) % MAX_DELAY;
+        for (channel, sample) in frame.iter_mut().enumerate().take(MAX_CHANNELS) {
+            *sample = (self.delay_line[channel][read_pos] * self.gain).clamp(-self.ceiling, self.ceiling);
+        }
+        self.delay_pos = (self.delay_pos + 1) % MAX_DELAY;
+    }
+}
@@ -0,0 +1,530 @@
+// clap_host.rs - Ardura 2023
+// A bare bones CLAP host that runs the plugin in process through the clap_entry nih_export_clap! exports
+//  Enough of a host to pick a layout, activate at any sample rate and buffer size, process audio with
+//  parameter changes at any sample, save and load state and open the editor on Linux, so the real
+//  Gain::process runs without a DAW
+
+use std::{
+    collections::HashMap,
+    ffi::{c_char, c_void, CStr},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Once,
+    },
+    time::{Duration, Instant},
+};
+
+use clap_sys::{
+    audio_buffer::clap_audio_buffer,
+    entry::clap_plugin_entry,
+    events::{
+        clap_event_header, clap_event_param_value, clap_input_events, clap_output_events, CLAP_CORE_EVENT_SPACE_ID,
+        CLAP_EVENT_PARAM_VALUE,
+    },
+    ext::{
+        audio_ports_config::{clap_audio_ports_config, clap_plugin_audio_ports_config, CLAP_EXT_AUDIO_PORTS_CONFIG},
+        gui::{clap_plugin_gui, clap_window, CLAP_EXT_GUI},
+        latency::{clap_host_latency, clap_plugin_latency, CLAP_EXT_LATENCY},
+        params::{clap_param_info, clap_plugin_params, CLAP_EXT_PARAMS},
+        render::{clap_plugin_render, CLAP_EXT_RENDER, CLAP_RENDER_OFFLINE, CLAP_RENDER_REALTIME},
+        state::{clap_plugin_state, CLAP_EXT_STATE},
+        thread_check::{clap_host_thread_check, CLAP_EXT_THREAD_CHECK},
+    },
+    factory::plugin_factory::{clap_plugin_factory, CLAP_PLUGIN_FACTORY_ID},
+    host::clap_host,
+    plugin::clap_plugin,
+    process::{clap_process, CLAP_PROCESS_ERROR},
+    stream::{clap_istream, clap_ostream},
+    version::CLAP_VERSION,
+};
+use nih_plug::prelude::Params;
+use Duro_Console::GainParams;
+
+const PLUGIN_ID: &CStr = c"com.ardura.duro.console";
+
+// One of the plugin's audio port configurations
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    pub id: u32,
+    pub channels: usize,
+}
+
+// How one process call went: how long it took, and how often it waited on something, where the system
+// counts that
+pub struct Processed {
+    pub elapsed: Duration,
+    pub waits: Option<u64>,
+}
+
+// What the plugin asked of the host, the plugin may call in from its audio thread
+#[derive(Default)]
+struct HostState {
+    processing: AtomicBool,
+    callback_requested: AtomicBool,
+    restart_requested: AtomicBool,
+    latency_changed: AtomicBool,
+}
+
+pub struct Host {
+    // Boxed so the plugin's pointers to them stay put when the Host moves
+    host: Box<clap_host>,
+    state: Box<HostState>,
+    plugin: *const clap_plugin,
+    // Parameter id string to CLAP id and the CLAP value of a normalized 1.0
+    params: HashMap<String, (u32, f64)>,
+    channels: usize,
+    max_frames: usize,
+    active: bool,
+    // The window the editor is open in
+    editor: Option<ParentWindow>,
+}
+
+impl Host {
+    pub fn new() -> Self {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| unsafe {
+            assert!(entry().init.unwrap()(c"".as_ptr()), "clap_entry init failed");
+        });
+
+        let state = Box::new(HostState::default());
+        let host = Box::new(clap_host {
+            clap_version: CLAP_VERSION,
+            host_data: &*state as *const HostState as *mut c_void,
+            name: c"duro-test-host".as_ptr(),
+            vendor: c"Ardura".as_ptr(),
+            url: c"https://github.com/ardura".as_ptr(),
+            version: c"0.1.0".as_ptr(),
+            get_extension: Some(host_get_extension),
+            request_restart: Some(host_request_restart),
+            request_process: Some(host_request_process),
+            request_callback: Some(host_request_callback),
+        });
+
+        let plugin = unsafe {
+            let factory = entry().get_factory.unwrap()(CLAP_PLUGIN_FACTORY_ID.as_ptr()) as *const clap_plugin_factory;
+            assert!(!factory.is_null(), "no plugin factory");
+            let plugin = (*factory).create_plugin.unwrap()(factory, &*host, PLUGIN_ID.as_ptr());
+            assert!(!plugin.is_null(), "create_plugin failed");
+            assert!((*plugin).init.unwrap()(plugin), "plugin init failed");
+            plugin
+        };
+
+        let mut host = Self {
+            host,
+            state,
+            plugin,
+            params: HashMap::new(),
+            channels: 2,
+            max_frames: 0,
+            active: false,
+            editor: None,
+        };
+        host.map_params();
+        host
+    }
+
+    // The CLAP ids are hashes of the id strings, listed in the same order as the plugin's param map
+    fn map_params(&mut self) {
+        let ids: Vec<String> = GainParams::default().param_map().into_iter().map(|(id, _, _)| id).collect();
+        let params: &clap_plugin_params = self.extension(CLAP_EXT_PARAMS);
+        let count = unsafe { params.count.unwrap()(self.plugin) } as usize;
+        assert_eq!(count, ids.len(), "the plugin lists a different number of parameters than its param map");
+        let mapped = ids
+            .into_iter()
+            .enumerate()
+            .map(|(index, id)| {
+                let mut info: clap_param_info = unsafe { std::mem::zeroed() };
+                assert!(unsafe { params.get_info.unwrap()(self.plugin, index as u32, &mut info) });
+                (id, (info.id, info.max_value))
+            })
+            .collect();
+        self.params = mapped;
+    }
+
+    fn extension<T>(&self, id: &CStr) -> &T {
+        let extension = unsafe { (*self.plugin).get_extension.unwrap()(self.plugin, id.as_ptr()) } as *const T;
+        unsafe { extension.as_ref() }.unwrap_or_else(|| panic!("the plugin has no {id:?} extension"))
+    }
+
+    fn param(&self, id: &str) -> (u32, f64) {
+        *self.params.get(id).unwrap_or_else(|| panic!("no parameter {id}"))
+    }
+
+    // Parameter id strings in the order the plugin lists them
+    pub fn param_ids(&self) -> Vec<String> {
+        GainParams::default().param_map().into_iter().map(|(id, _, _)| id).collect()
+    }
+
+    pub fn layouts(&self) -> Vec<Layout> {
+        let configs: &clap_plugin_audio_ports_config = self.extension(CLAP_EXT_AUDIO_PORTS_CONFIG);
+        let count = unsafe { configs.count.unwrap()(self.plugin) };
+        (0..count)
+            .map(|index| {
+                let mut config: clap_audio_ports_config = unsafe { std::mem::zeroed() };
+                assert!(unsafe { configs.get.unwrap()(self.plugin, index, &mut config) });
+                Layout { id: config.id, channels: config.main_input_channel_count as usize }
+            })
+            .collect()
+    }
+
+    // Only while deactivated, like any host
+    pub fn select_layout(&mut self, layout: Layout) {
+        assert!(!self.active, "select a layout before activating");
+        let configs: &clap_plugin_audio_ports_config = self.extension(CLAP_EXT_AUDIO_PORTS_CONFIG);
+        assert!(unsafe { configs.select.unwrap()(self.plugin, layout.id) }, "could not select layout {}", layout.id);
+        self.channels = layout.channels;
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    // Activate and start processing, offline is a bounce where Auto math goes exact
+    pub fn activate(&mut self, sample_rate: f32, max_frames: usize, offline: bool) {
+        let render: &clap_plugin_render = self.extension(CLAP_EXT_RENDER);
+        let mode = if offline { CLAP_RENDER_OFFLINE } else { CLAP_RENDER_REALTIME };
+        assert!(unsafe { render.set.unwrap()(self.plugin, mode) });
+        unsafe {
+            assert!((*self.plugin).activate.unwrap()(self.plugin, sample_rate as f64, 1, max_frames as u32), "activate failed");
+            assert!((*self.plugin).start_processing.unwrap()(self.plugin), "start_processing failed");
+        }
+        self.max_frames = max_frames;
+        self.active = true;
+    }
+
+    pub fn deactivate(&mut self) {
+        if self.active {
+            unsafe {
+                (*self.plugin).stop_processing.unwrap()(self.plugin);
+                (*self.plugin).deactivate.unwrap()(self.plugin);
+            }
+            self.active = false;
+        }
+    }
+
+    // What a host does on a transport jump
+    pub fn reset(&mut self) {
+        unsafe { (*self.plugin).reset.unwrap()(self.plugin) };
+    }
+
+    // Process one block in place, one buffer per channel all the same length. Changes are (sample, id,
+    // normalized value) and land at their sample like host automation. Returns how long the plugin's
+    // process call took and how often it waited
+    pub fn process(&mut self, buffers: &mut [Vec<f32>], changes: &[(u32, &str, f64)]) -> Processed {
+        assert!(self.active, "activate before processing");
+        assert_eq!(buffers.len(), self.channels, "one buffer per channel");
+        let frames = buffers[0].len();
+        assert!(frames <= self.max_frames, "{frames} frames is more than the {} the plugin was activated with", self.max_frames);
+
+        let mut events: Vec<clap_event_param_value> = changes
+            .iter()
+            .map(|(time, id, normalized)| {
+                let (param_id, max_value) = self.param(id);
+                param_event(*time, param_id, normalized * max_value)
+            })
+            .collect();
+        events.sort_by_key(|event| event.header.time);
+        let in_events = clap_input_events {
+            ctx: &events as *const Vec<clap_event_param_value> as *mut c_void,
+            size: Some(events_size),
+            get: Some(events_get),
+        };
+        let out_events = clap_output_events { ctx: ptr::null_mut(), try_push: Some(events_try_push) };
+
+        // Separate input buffers, so the plugin copies them over like it would in most hosts
+        let mut inputs = buffers.to_vec();
+        let mut input_pointers: Vec<*mut f32> = inputs.iter_mut().map(|channel| channel.as_mut_ptr()).collect();
+        let mut output_pointers: Vec<*mut f32> = buffers.iter_mut().map(|channel| channel.as_mut_ptr()).collect();
+        let audio_input = clap_audio_buffer {
+            data32: input_pointers.as_mut_ptr(),
+            data64: ptr::null_mut(),
+            channel_count: self.channels as u32,
+            latency: 0,
+            constant_mask: 0,
+        };
+        let mut audio_output = clap_audio_buffer {
+            data32: output_pointers.as_mut_ptr(),
+            data64: ptr::null_mut(),
+            channel_count: self.channels as u32,
+            latency: 0,
+            constant_mask: 0,
+        };
+        let process = clap_process {
+            steady_time: -1,
+            frames_count: frames as u32,
+            transport: ptr::null(),
+            audio_inputs: &audio_input,
+            audio_outputs: &mut audio_output,
+            audio_inputs_count: 1,
+            audio_outputs_count: 1,
+            in_events: &in_events,
+            out_events: &out_events,
+        };
+
+        self.state.processing.store(true, Ordering::SeqCst);
+        let switches = voluntary_switches();
+        let start = Instant::now();
+        let status = unsafe { (*self.plugin).process.unwrap()(self.plugin, &process) };
+        let elapsed = start.elapsed();
+        let waits = voluntary_switches().zip(switches).map(|(after, before)| after - before);
+        self.state.processing.store(false, Ordering::SeqCst);
+        assert_ne!(status, CLAP_PROCESS_ERROR, "process failed");
+
+        // Whatever the plugin left for the main thread, like telling us its latency moved
+        self.idle();
+        Processed { elapsed, waits }
+    }
+
+    // Run the plugin's main thread work if it asked for it
+    pub fn idle(&mut self) {
+        if self.state.callback_requested.swap(false, Ordering::SeqCst) {
+            unsafe { (*self.plugin).on_main_thread.unwrap()(self.plugin) };
+        }
+    }
+
+    // Normalized value of a parameter as the plugin reports it
+    pub fn param_value(&self, id: &str) -> f64 {
+        let (param_id, max_value) = self.param(id);
+        let params: &clap_plugin_params = self.extension(CLAP_EXT_PARAMS);
+        let mut value = 0.0;
+        assert!(unsafe { params.get_value.unwrap()(self.plugin, param_id, &mut value) });
+        value / max_value
+    }
+
+    pub fn latency(&self) -> u32 {
+        let latency: &clap_plugin_latency = self.extension(CLAP_EXT_LATENCY);
+        unsafe { latency.get.unwrap()(self.plugin) }
+    }
+
+    // True once if the plugin told us its latency changed since the last call
+    pub fn take_latency_changed(&self) -> bool {
+        self.state.latency_changed.swap(false, Ordering::SeqCst)
+    }
+
+    pub fn take_restart_requested(&self) -> bool {
+        self.state.restart_requested.swap(false, Ordering::SeqCst)
+    }
+
+    // Open the editor embedded in a window of our own like a DAW would, false if there's no display
+    // to open it on. Gain::process only runs the meters while the editor is open
+    pub fn open_editor(&mut self) -> bool {
+        if self.editor.is_some() {
+            return true;
+        }
+        if !cfg!(target_os = "linux") {
+            return false;
+        }
+        let gui: &clap_plugin_gui = self.extension(CLAP_EXT_GUI);
+        let api = ParentWindow::API.as_ptr();
+        let (mut width, mut height) = (0, 0);
+        unsafe {
+            assert!(gui.is_api_supported.unwrap()(self.plugin, api, false), "the editor doesn't support {:?}", ParentWindow::API);
+            assert!(gui.create.unwrap()(self.plugin, api, false), "the plugin could not create its editor");
+            assert!(gui.get_size.unwrap()(self.plugin, &mut width, &mut height));
+        }
+        let Some(parent) = ParentWindow::new(width, height) else {
+            unsafe { gui.destroy.unwrap()(self.plugin) };
+            return false;
+        };
+        let opened = unsafe { gui.set_parent.unwrap()(self.plugin, &parent.clap_window()) && gui.show.unwrap()(self.plugin) };
+        assert!(opened, "the plugin could not open its editor");
+        self.editor = Some(parent);
+        true
+    }
+
+    pub fn close_editor(&mut self) {
+        if self.editor.take().is_some() {
+            let gui: &clap_plugin_gui = self.extension(CLAP_EXT_GUI);
+            unsafe { gui.destroy.unwrap()(self.plugin) };
+        }
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let state: &clap_plugin_state = self.extension(CLAP_EXT_STATE);
+        let mut data: Vec<u8> = Vec::new();
+        let stream = clap_ostream { ctx: &mut data as *mut Vec<u8> as *mut c_void, write: Some(stream_write) };
+        assert!(unsafe { state.save.unwrap()(self.plugin, &stream) }, "saving state failed");
+        data
+    }
+
+    // Returns false when the plugin rejects the data
+    pub fn load_state(&mut self, data: &[u8]) -> bool {
+        let state: &clap_plugin_state = self.extension(CLAP_EXT_STATE);
+        let mut reader = StreamReader { data, position: 0 };
+        let stream = clap_istream { ctx: &mut reader as *mut StreamReader as *mut c_void, read: Some(stream_read) };
+        let loaded = unsafe { state.load.unwrap()(self.plugin, &stream) };
+        self.idle();
+        loaded
+    }
+}
+
+impl Drop for Host {
+    fn drop(&mut self) {
+        self.close_editor();
+        self.deactivate();
+        unsafe { (*self.plugin).destroy.unwrap()(self.plugin) };
+    }
+}
+
+// An empty X11 window for the editor to embed itself in
+#[cfg(target_os = "linux")]
+struct ParentWindow {
+    // Closing the connection closes the window
+    _connection: x11rb::rust_connection::RustConnection,
+    window: u32,
+}
+
+#[cfg(target_os = "linux")]
+impl ParentWindow {
+    const API: &'static CStr = clap_sys::ext::gui::CLAP_WINDOW_API_X11;
+
+    fn new(width: u32, height: u32) -> Option<Self> {
+        use x11rb::{
+            connection::Connection,
+            protocol::xproto::{ConnectionExt, CreateWindowAux, WindowClass},
+            COPY_DEPTH_FROM_PARENT,
+        };
+
+        let (connection, screen) = x11rb::connect(None).ok()?;
+        let root = connection.setup().roots[screen].root;
+        let window = connection.generate_id().ok()?;
+        connection
+            .create_window(COPY_DEPTH_FROM_PARENT, window, root, 0, 0, width as u16, height as u16, 0, WindowClass::INPUT_OUTPUT, 0, &CreateWindowAux::new())
+            .ok()?;
+        connection.map_window(window).ok()?;
+        connection.flush().ok()?;
+        Some(Self { _connection: connection, window })
+    }
+
+    fn clap_window(&self) -> clap_window {
+        use clap_sys::ext::gui::{clap_window_handle, clap_xwnd};
+        clap_window { api: Self::API.as_ptr(), specific: clap_window_handle { x11: self.window as clap_xwnd } }
+    }
+}
+
+// Only X11 is supported here, elsewhere the editor stays closed
+#[cfg(not(target_os = "linux"))]
+struct ParentWindow;
+
+#[cfg(not(target_os = "linux"))]
+impl ParentWindow {
+    const API: &'static CStr = clap_sys::ext::gui::CLAP_WINDOW_API_X11;
+
+    fn new(_width: u32, _height: u32) -> Option<Self> {
+        None
+    }
+
+    fn clap_window(&self) -> clap_window {
+        unreachable!()
+    }
+}
+
+// How often this thread has given up the CPU by itself so far, which it only does to wait: on a lock
+// someone else holds, a condition variable, a sleep or the disk. None where the system doesn't count it
+pub fn voluntary_switches() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/thread-self/status").ok()?;
+    status.lines().find_map(|line| line.strip_prefix("voluntary_ctxt_switches:"))?.trim().parse().ok()
+}
+
+// The struct nih_export_clap! puts at the crate root, where a host would look it up in the library
+fn entry() -> &'static clap_plugin_entry {
+    unsafe { &*(ptr::addr_of!(Duro_Console::clap_entry) as *const clap_plugin_entry) }
+}
+
+fn param_event(time: u32, param_id: u32, value: f64) -> clap_event_param_value {
+    clap_event_param_value {
+        header: clap_event_header {
+            size: std::mem::size_of::<clap_event_param_value>() as u32,
+            time,
+            space_id: CLAP_CORE_EVENT_SPACE_ID,
+            type_: CLAP_EVENT_PARAM_VALUE,
+            flags: 0,
+        },
+        param_id,
+        cookie: ptr::null_mut(),
+        note_id: -1,
+        port_index: -1,
+        channel: -1,
+        key: -1,
+        value,
+    }
+}
+
+unsafe fn host_state<'a>(host: *const clap_host) -> &'a HostState {
+    &*((*host).host_data as *const HostState)
+}
+
+static THREAD_CHECK: clap_host_thread_check = clap_host_thread_check {
+    is_main_thread: Some(host_is_main_thread),
+    is_audio_thread: Some(host_is_audio_thread),
+};
+
+static LATENCY: clap_host_latency = clap_host_latency { changed: Some(host_latency_changed) };
+
+unsafe extern "C" fn host_get_extension(_host: *const clap_host, extension_id: *const c_char) -> *const c_void {
+    let id = CStr::from_ptr(extension_id);
+    if id == CLAP_EXT_THREAD_CHECK {
+        &THREAD_CHECK as *const clap_host_thread_check as *const c_void
+    } else if id == CLAP_EXT_LATENCY {
+        &LATENCY as *const clap_host_latency as *const c_void
+    } else {
+        ptr::null()
+    }
+}
+
+unsafe extern "C" fn host_request_restart(host: *const clap_host) {
+    host_state(host).restart_requested.store(true, Ordering::SeqCst);
+}
+
+unsafe extern "C" fn host_request_process(_host: *const clap_host) {}
+
+unsafe extern "C" fn host_request_callback(host: *const clap_host) {
+    host_state(host).callback_requested.store(true, Ordering::SeqCst);
+}
+
+// Everything runs on the test's thread, it is the audio thread while process is running
+unsafe extern "C" fn host_is_main_thread(host: *const clap_host) -> bool {
+    !host_state(host).processing.load(Ordering::SeqCst)
+}
+
+unsafe extern "C" fn host_is_audio_thread(host: *const clap_host) -> bool {
+    host_state(host).processing.load(Ordering::SeqCst)
+}
+
+unsafe extern "C" fn host_latency_changed(host: *const clap_host) {
+    host_state(host).latency_changed.store(true, Ordering::SeqCst);
+}
+
+unsafe extern "C" fn events_size(list: *const clap_input_events) -> u32 {
+    (*((*list).ctx as *const Vec<clap_event_param_value>)).len() as u32
+}
+
+unsafe extern "C" fn events_get(list: *const clap_input_events, index: u32) -> *const clap_event_header {
+    let events = &*((*list).ctx as *const Vec<clap_event_param_value>);
+    events.get(index as usize).map_or(ptr::null(), |event| &event.header as *const clap_event_header)
+}
+
+// Parameter changes the plugin reports back are accepted and dropped
+unsafe extern "C" fn events_try_push(_list: *const clap_output_events, _event: *const clap_event_header) -> bool {
+    true
+}
+
+unsafe extern "C" fn stream_write(stream: *const clap_ostream, buffer: *const c_void, size: u64) -> i64 {
+    let data = &mut *((*stream).ctx as *mut Vec<u8>);
+    data.extend_from_slice(std::slice::from_raw_parts(buffer as *const u8, size as usize));
+    size as i64
+}
+
+struct StreamReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+unsafe extern "C" fn stream_read(stream: *const clap_istream, buffer: *mut c_void, size: u64) -> i64 {
+    let reader = &mut *((*stream).ctx as *mut StreamReader);
+    let count = (size as usize).min(reader.data.len() - reader.position);
+    ptr::copy_nonoverlapping(reader.data[reader.position..].as_ptr(), buffer as *mut u8, count);
+    reader.position += count;
+    count as i64
+}
@@ -0,0 +1,65 @@
+// common/mod.rs - Ardura 2023
+// Helpers shared by the integration tests: every mode, default settings and a headless render
+
+#![allow(dead_code)]
+
+pub mod clap_host;
+
+use duro_dsp::Mode;
+use Duro_Console::{
+    chain::{ChainSettings, DuroChain},
+    duro_process::{ConsoleMode, SaturationModeEnum},
+    GainParams,
+};
+
+pub const SAMPLE_RATE: f32 = 48000.0;
+
+pub fn sat_types() -> Vec<SaturationModeEnum> {
+    (0..SaturationModeEnum::COUNT).map(SaturationModeEnum::from_index).collect()
+}
+
+pub fn console_types() -> Vec<ConsoleMode> {
+    (0..ConsoleMode::COUNT).map(ConsoleMode::from_index).collect()
+}
+
+// Display name of a mode without the quotes some of them have
+pub fn name<T: Mode>(mode: T) -> String {
+    T::variants()[mode.to_index()].replace('"', "")
+}
+
+// What the plugin starts with
+pub fn default_settings() -> ChainSettings {
+    GainParams::default().settings()
+}
+
+// Run a signal through a fresh chain that starts in the settings' modes, one channel or two
+// interleaved, with the settings held for the whole signal
+pub fn render(settings: &ChainSettings, signal: &[f32], channels: usize) -> Vec<f32> {
+    let mut chain = DuroChain::new(SAMPLE_RATE);
+    chain.update_ceiling(settings.ceiling_mode, settings.ceiling);
+    chain.jump_to(settings);
+    chain.generate_console(settings);
+    chain.start_block(settings, false);
+    let mut output = signal.to_vec();
+    for frame in output.chunks_mut(channels) {
+        chain.process_frame(frame, settings);
+    }
+    output
+}
+
+// Small deterministic noise source so test signals are the same everywhere
+pub struct Noise(u32);
+
+impl Noise {
+    pub fn new(seed: u32) -> Self {
+        Self(seed.max(1))
+    }
+
+    // Uniform between -1 and 1
+    pub fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
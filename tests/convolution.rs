@@ -0,0 +1,42 @@
+// convolution.rs - Ardura 2023
+// Impulse responses brought to the session rate: the same response has to sound the same at any rate
+//  The console's gain is 1 + the sum of the prepared response, the 1 being the dry sample
+
+use std::f32::consts::PI;
+
+use Duro_Console::convolution;
+
+const IR_RATE: f32 = 48000.0;
+
+// What the console turns DC into with this response loaded at this rate
+fn dc_gain(impulse: &[f32], to_rate: f32) -> f32 {
+    let (peak_index, peak) = convolution::find_peak(impulse).unwrap();
+    1.0 + convolution::prepare_impulse(impulse, peak_index, peak, IR_RATE, to_rate).iter().sum::<f32>()
+}
+
+#[test]
+fn an_impulse_response_keeps_its_gain_at_any_rate() {
+    // A decaying ring with some low end, so its gain at DC isn't just the peak
+    let impulse: Vec<f32> = (0..4800).map(|n| (-(n as f32) / 300.0).exp() * (0.3 + 0.7 * (0.05 * n as f32).cos())).collect();
+    let original = impulse.iter().sum::<f32>();
+    for rate in [44100.0, 96000.0] {
+        let gain = dc_gain(&impulse, rate);
+        assert!((gain - original).abs() < original * 1e-3, "the response has a gain of {gain} at {rate}Hz instead of {original}");
+    }
+}
+
+#[test]
+fn going_down_in_rate_leaves_nothing_above_the_new_nyquist() {
+    // A ring at the source's Nyquist after the peak, which a 44.1k session can't play. It swells in and out
+    // smoothly, a sudden start would have some of itself below the new Nyquist
+    let ring: Vec<f32> = (0..4800).map(|n| 0.5 * (PI * n as f32).cos() * (-((n as f32 - 600.0) / 100.0).powi(2)).exp()).collect();
+    let mut impulse = ring.clone();
+    impulse[0] = 1.0;
+    let (peak_index, peak) = convolution::find_peak(&impulse).unwrap();
+    let response = convolution::prepare_impulse(&impulse, peak_index, peak, IR_RATE * 2.0, 44100.0);
+
+    // The peak lands on a sample on its own, so all that's left of the response is the ring
+    let left: f32 = response.iter().map(|x| x * x).sum();
+    let rung: f32 = ring.iter().map(|x| x * x).sum();
+    assert!(left < rung * 1e-4, "{} dB of the ring aliased down", 10.0 * (left / rung).log10());
+}
@@ -0,0 +1,27 @@
+// dynamic_convolution.rs - Ardura 2023
+// The Dynamic Response console picks its kernels by the level of the signal, not of each sample
+//  Kernels here are already prepared, so they hold what the console adds on top of the dry sample
+
+use std::f32::consts::PI;
+
+use Duro_Console::dynamic_convolution::DynamicConvolver;
+
+const SAMPLE_RATE: f32 = 48000.0;
+
+#[test]
+fn a_steady_sine_stays_on_the_kernel_for_its_level() {
+    // Adds nothing when quiet, takes half the signal away when loud
+    let mut convolver = DynamicConvolver::new(&[(-24.0, vec![0.0]), (-6.0, vec![-0.5])], SAMPLE_RATE);
+    let amplitude = 10f32.powf(-6.0 / 20.0);
+    let sine: Vec<f32> = (0..SAMPLE_RATE as usize).map(|n| amplitude * (2.0 * PI * 1000.0 * n as f32 / SAMPLE_RATE).sin()).collect();
+    let output: Vec<f32> = sine.iter().map(|sample| convolver.process(*sample, 0)).collect();
+
+    // Once the level has settled every sample of the cycle gets the loud kernel, zero crossings included
+    let settled = SAMPLE_RATE as usize / 2;
+    let worst = sine[settled..]
+        .iter()
+        .zip(output[settled..].iter())
+        .map(|(sample, out)| (out + 0.5 * sample).abs())
+        .fold(0.0, f32::max);
+    assert!(worst < amplitude * 0.01, "the sine is {worst} away from the loud kernel");
+}
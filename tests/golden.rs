@@ -0,0 +1,185 @@
+// golden.rs - Ardura 2023
+// Golden file regression tests: every saturation and console mode against checked in reference output
+//  Set DURO_BLESS=1 to write new references after a change to the sound that is meant to happen:
+//  DURO_BLESS=1 cargo test --test golden
+
+mod common;
+
+use std::{collections::BTreeMap, fmt::Write, path::PathBuf};
+
+use common::{console_types, default_settings, name, render, sat_types, Noise, SAMPLE_RATE};
+use Duro_Console::{
+    chain::ChainSettings,
+    duro_process::{ConsoleMode, SaturationModeEnum},
+};
+
+const LENGTH: usize = 4096;
+// Samples kept from the start, where the impulse response and attack transients are
+const HEAD: usize = 16;
+// Every STRIDE-th sample is kept after that, plus the RMS of each STRIDE long block
+const STRIDE: usize = 128;
+// Allowed difference, absolute plus relative to the reference value
+const ABS_TOLERANCE: f32 = 1e-4;
+const REL_TOLERANCE: f32 = 1e-3;
+
+struct Setting {
+    name: &'static str,
+    drive: f32,
+    threshold_db: f32,
+    // Bus compressor ratio, 1 leaves it out
+    comp_ratio: f32,
+}
+
+const SETTINGS: [Setting; 3] = [
+    Setting { name: "gentle", drive: 0.25, threshold_db: -6.0, comp_ratio: 1.0 },
+    Setting { name: "medium", drive: 1.0, threshold_db: -12.0, comp_ratio: 1.0 },
+    Setting { name: "hot", drive: 2.0, threshold_db: -18.0, comp_ratio: 4.0 },
+];
+
+fn signals() -> Vec<(&'static str, Vec<f32>)> {
+    let mut impulse = vec![0.0; LENGTH];
+    impulse[0] = 0.8;
+
+    // Log sweep from 20Hz to 20kHz
+    let (start, end) = (20.0f64, 20000.0f64);
+    let duration = LENGTH as f64 / SAMPLE_RATE as f64;
+    let rate = (end / start).ln();
+    let sweep = (0..LENGTH)
+        .map(|n| {
+            let t = n as f64 / SAMPLE_RATE as f64;
+            let phase = 2.0 * std::f64::consts::PI * start * duration / rate * ((t / duration * rate).exp() - 1.0);
+            (0.7 * phase.sin()) as f32
+        })
+        .collect();
+
+    // Silence, a burst of noise, then silence again for the tails
+    let mut noise = Noise::new(12463);
+    let burst = (0..LENGTH)
+        .map(|n| if (512..2560).contains(&n) { 0.5 * noise.next() } else { 0.0 })
+        .collect();
+
+    vec![("impulse", impulse), ("sweep", sweep), ("noise_burst", burst)]
+}
+
+fn settings_for(setting: &Setting, sat_type: SaturationModeEnum, console_type: ConsoleMode) -> ChainSettings {
+    let mut settings = default_settings();
+    settings.sat_type = sat_type;
+    settings.console_type = console_type;
+    settings.drive = setting.drive;
+    settings.console_drive = setting.drive;
+    settings.threshold = 10f32.powf(setting.threshold_db / 20.0);
+    settings.comp_ratio = setting.comp_ratio;
+    settings.comp_threshold = -20.0;
+    // Some harmonics so the harmonic based modes have something to add
+    settings.harmonic_levels[0] = 0.1;
+    settings.harmonic_levels[1] = 0.05;
+    settings.harmonic_levels[2] = 0.03;
+    settings.harmonic_levels[3] = 0.02;
+    settings
+}
+
+// A smaller picture of a render that still catches any change to the sound
+fn fingerprint(output: &[f32]) -> Vec<f32> {
+    let mut values: Vec<f32> = output[..HEAD].to_vec();
+    values.extend(output.iter().step_by(STRIDE));
+    values.extend(
+        output
+            .chunks(STRIDE)
+            .map(|block| (block.iter().map(|x| x * x).sum::<f32>() / block.len() as f32).sqrt()),
+    );
+    values
+}
+
+// Each saturation on its own then each console on its own, at every setting
+fn renders(signal: &[f32]) -> Vec<(String, Vec<f32>)> {
+    let mut modes = Vec::new();
+    for sat_type in sat_types() {
+        modes.push((format!("saturation:{}", name(sat_type)), sat_type, ConsoleMode::BYPASS));
+    }
+    for console_type in console_types().into_iter().filter(|mode| *mode != ConsoleMode::BYPASS) {
+        modes.push((format!("console:{}", name(console_type)), SaturationModeEnum::NONESAT, console_type));
+    }
+    let mut renders = Vec::new();
+    for (mode, sat_type, console_type) in modes {
+        for setting in SETTINGS.iter() {
+            let output = render(&settings_for(setting, sat_type, console_type), signal, 1);
+            renders.push((format!("{mode}:{}", setting.name), fingerprint(&output)));
+        }
+    }
+    renders
+}
+
+fn golden_path(signal: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{signal}.csv"))
+}
+
+fn read_golden(signal: &str) -> BTreeMap<String, Vec<f32>> {
+    let path = golden_path(signal);
+    let text = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Could not read {}: {e}, run with DURO_BLESS=1 to create it", path.display()));
+    text.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut columns = line.split(',');
+            let key = columns.next().unwrap().to_string();
+            let values = columns.map(|value| value.parse().expect("bad value in golden file")).collect();
+            (key, values)
+        })
+        .collect()
+}
+
+fn write_golden(signal: &str, renders: &[(String, Vec<f32>)]) {
+    let mut text = format!("# {signal} through every mode, regenerate with DURO_BLESS=1 cargo test --test golden\n");
+    for (key, values) in renders {
+        let _ = write!(text, "{key}");
+        for value in values {
+            let _ = write!(text, ",{value}");
+        }
+        text.push('\n');
+    }
+    std::fs::write(golden_path(signal), text).expect("Could not write golden file");
+}
+
+#[test]
+fn every_mode_matches_its_golden_file() {
+    let bless = std::env::var_os("DURO_BLESS").is_some();
+    let mut failures = Vec::new();
+    for (signal_name, signal) in signals() {
+        let renders = renders(&signal);
+        for (key, values) in renders.iter() {
+            assert!(values.iter().all(|x| x.is_finite()), "{signal_name} {key} is not finite");
+        }
+        if bless {
+            write_golden(signal_name, &renders);
+            continue;
+        }
+        let mut golden = read_golden(signal_name);
+        for (key, values) in renders {
+            let Some(expected) = golden.remove(&key) else {
+                failures.push(format!("{signal_name} {key}: no reference"));
+                continue;
+            };
+            if expected.len() != values.len() {
+                failures.push(format!("{signal_name} {key}: {} values, expected {}", values.len(), expected.len()));
+                continue;
+            }
+            if let Some((index, (got, want))) = values
+                .iter()
+                .zip(expected.iter())
+                .enumerate()
+                .find(|(_, (got, want))| (*got - *want).abs() > ABS_TOLERANCE + REL_TOLERANCE * want.abs())
+            {
+                failures.push(format!("{signal_name} {key}: value {index} is {got}, expected {want}"));
+            }
+        }
+        for key in golden.keys() {
+            failures.push(format!("{signal_name} {key}: reference for a mode that no longer exists"));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} renders differ from the golden files, run with DURO_BLESS=1 if the change is intended:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
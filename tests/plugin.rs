@@ -0,0 +1,291 @@
+// plugin.rs - Ardura 2023
+// Integration tests for the plugin itself, loaded through the CLAP host in common/clap_host.rs
+//  Every layout at several sample rates and buffer sizes, sample accurate parameter changes, state
+//  saving and loading, sessions from older versions and the activate, reset and deactivate
+//  lifecycle, without opening a DAW
+
+mod common;
+
+use common::{
+    clap_host::{Host, Layout},
+    Noise,
+};
+use duro_dsp::Mode;
+use nih_plug::prelude::Enum;
+use Duro_Console::{chain::DuroChain, output_ceiling::CeilingMode, GainParams};
+
+const SAMPLE_RATES: [f32; 3] = [44100.0, 48000.0, 96000.0];
+const MAX_BLOCK_SIZES: [usize; 4] = [1, 32, 512, 4096];
+// Long enough for every parameter smoother and mode crossfade to settle
+const SETTLE_SECONDS: f32 = 0.5;
+// Two instances that should sound the same may only differ by rounding
+const TOLERANCE: f32 = 1e-6;
+
+fn layout(host: &Host, channels: usize) -> Layout {
+    *host
+        .layouts()
+        .iter()
+        .find(|layout| layout.channels == channels)
+        .unwrap_or_else(|| panic!("no {channels} channel layout"))
+}
+
+fn started(channels: usize, sample_rate: f32, max_block: usize, offline: bool, state: Option<&[u8]>) -> Host {
+    let mut host = Host::new();
+    let layout = layout(&host, channels);
+    host.select_layout(layout);
+    if let Some(state) = state {
+        assert!(host.load_state(state), "could not load the state");
+    }
+    host.activate(sample_rate, max_block, offline);
+    host
+}
+
+// A different noise on every channel
+fn noise(channels: usize, frames: usize, seed: u32) -> Vec<Vec<f32>> {
+    (0..channels)
+        .map(|channel| {
+            let mut noise = Noise::new(seed + channel as u32);
+            (0..frames).map(|_| 0.5 * noise.next()).collect()
+        })
+        .collect()
+}
+
+// Play a signal in blocks that cycle through a few sizes up to max_block, like a host with a
+// varying buffer. Changes all land on the first sample
+fn play(host: &mut Host, signal: &[Vec<f32>], max_block: usize, changes: &[(&str, f64)]) -> Vec<Vec<f32>> {
+    let frames = signal[0].len();
+    let sizes = [max_block, max_block / 3 + 1, 1];
+    let mut output: Vec<Vec<f32>> = vec![Vec::with_capacity(frames); signal.len()];
+    let (mut start, mut block) = (0, 0);
+    while start < frames {
+        let end = (start + sizes[block % sizes.len()]).min(frames);
+        let mut buffers: Vec<Vec<f32>> = signal.iter().map(|channel| channel[start..end].to_vec()).collect();
+        let block_changes: Vec<(u32, &str, f64)> = if start == 0 {
+            changes.iter().map(|(id, value)| (0, *id, *value)).collect()
+        } else {
+            Vec::new()
+        };
+        host.process(&mut buffers, &block_changes);
+        for (output, buffer) in output.iter_mut().zip(buffers) {
+            output.extend(buffer);
+        }
+        start = end;
+        block += 1;
+    }
+    output
+}
+
+fn settle_frames(sample_rate: f32) -> usize {
+    (sample_rate * SETTLE_SECONDS) as usize
+}
+
+fn assert_same(got: &[Vec<f32>], expected: &[Vec<f32>], what: &str) {
+    for (channel, (got, expected)) in got.iter().zip(expected).enumerate() {
+        if let Some(n) = (0..got.len()).find(|n| (got[*n] - expected[*n]).abs() > TOLERANCE) {
+            panic!("{what}: channel {channel} sample {n} is {}, expected {}", got[n], expected[n]);
+        }
+    }
+}
+
+// Something busy enough that leftover state would be heard: a console with a delay line, the
+// compressor working, a saturation with memory and the lookahead limiter
+fn busy_settings() -> Vec<(&'static str, f64)> {
+    let index = |mode: usize, count: usize| mode as f64 / (count - 1) as f64;
+    vec![
+        ("console_type", index(1, Duro_Console::duro_process::ConsoleMode::COUNT)),
+        ("type", index(10, Duro_Console::duro_process::SaturationModeEnum::COUNT)),
+        ("console_drive", 0.8),
+        ("comp_ratio", 0.6),
+        ("comp_threshold", 0.2),
+        ("comp_auto_release", 1.0),
+        ("ceiling_mode", 1.0),
+        ("bias", 0.7),
+    ]
+}
+
+#[test]
+fn every_layout_processes_at_every_sample_rate_and_block_size() {
+    let channel_counts: Vec<usize> = Host::new().layouts().iter().map(|layout| layout.channels).collect();
+    assert_eq!(channel_counts, vec![2, 1], "expected stereo then mono layouts");
+
+    for channels in channel_counts {
+        for sample_rate in SAMPLE_RATES {
+            for max_block in MAX_BLOCK_SIZES {
+                for offline in [false, true] {
+                    let mut host = started(channels, sample_rate, max_block, offline, None);
+                    let signal = noise(channels, (sample_rate / 10.0) as usize, 1);
+                    let output = play(&mut host, &signal, max_block, &[]);
+                    let what = format!("{channels} channels at {sample_rate}Hz in blocks of {max_block}, offline {offline}");
+                    for channel in output.iter() {
+                        assert_eq!(channel.len(), signal[0].len(), "{what}");
+                        assert!(channel.iter().all(|x| x.is_finite()), "{what} is not finite");
+                        assert!(channel.iter().any(|x| *x != 0.0), "{what} is silent");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn parameter_changes_land_on_their_sample() {
+    let sample_rate = 48000.0;
+    let mut changed = started(2, sample_rate, 512, false, None);
+    let mut unchanged = started(2, sample_rate, 512, false, None);
+    let warmup = noise(2, settle_frames(sample_rate), 2);
+    play(&mut changed, &warmup, 512, &[]);
+    play(&mut unchanged, &warmup, 512, &[]);
+
+    let at = 200;
+    let signal = noise(2, 512, 3);
+    let mut changed_output = signal.clone();
+    let mut unchanged_output = signal.clone();
+    changed.process(&mut changed_output, &[(at, "output_gain", 0.0)]);
+    unchanged.process(&mut unchanged_output, &[]);
+
+    for channel in 0..2 {
+        assert_eq!(changed_output[channel][..at as usize], unchanged_output[channel][..at as usize], "the change was heard early");
+        assert!(
+            changed_output[channel][at as usize..].iter().zip(&unchanged_output[channel][at as usize..]).any(|(a, b)| a != b),
+            "the change was never heard"
+        );
+    }
+    assert_eq!(changed.param_value("output_gain"), 0.0);
+}
+
+#[test]
+fn every_parameter_reads_back_and_moves_only_itself() {
+    let mut host = started(2, 48000.0, 64, false, None);
+    let ids = host.param_ids();
+    let defaults: Vec<f64> = ids.iter().map(|id| host.param_value(id)).collect();
+    for (i, id) in ids.iter().enumerate() {
+        // The end of the range furthest from the default, every kind of parameter can land there exactly
+        let value = if defaults[i] < 0.5 { 1.0 } else { 0.0 };
+        let mut buffers = noise(2, 64, 4);
+        host.process(&mut buffers, &[(0, id.as_str(), value)]);
+        for (j, other) in ids.iter().enumerate() {
+            let expected = if j <= i { if defaults[j] < 0.5 { 1.0 } else { 0.0 } } else { defaults[j] };
+            let got = host.param_value(other);
+            assert!((got - expected).abs() < 1e-6, "after setting {id}, {other} is {got} instead of {expected}");
+        }
+    }
+}
+
+#[test]
+fn state_round_trips_every_parameter_and_the_sound() {
+    let sample_rate = 48000.0;
+    let mut original = started(2, sample_rate, 256, false, None);
+    let ids = original.param_ids();
+    let params = GainParams::default();
+    // Somewhere in the middle of every range, away from the defaults
+    let changes: Vec<(&str, f64)> = ids
+        .iter()
+        .map(|id| (id.as_str(), if params.is_stepped(id).unwrap_or(false) { 0.5 } else { 0.37 }))
+        .collect();
+    play(&mut original, &noise(2, settle_frames(sample_rate), 5), 256, &changes);
+
+    let state = original.save_state();
+    let mut restored = started(2, sample_rate, 256, false, Some(&state));
+    for id in ids.iter() {
+        let (got, expected) = (restored.param_value(id), original.param_value(id));
+        assert!((got - expected).abs() < 1e-6, "{id} is {got} after the round trip instead of {expected}");
+    }
+    assert_eq!(restored.save_state(), state, "saving the restored state gave something different");
+
+    // Both start from silence and should then sound exactly alike
+    original.reset();
+    let probe = noise(2, 4096, 6);
+    let expected = play(&mut original, &probe, 256, &[]);
+    let got = play(&mut restored, &probe, 256, &[]);
+    assert_same(&got, &expected, "restored instance");
+}
+
+#[test]
+fn a_broken_state_is_rejected_and_playback_carries_on() {
+    let mut host = started(2, 48000.0, 256, false, None);
+    let state = host.save_state();
+    assert!(!host.load_state(&state[..state.len() / 2]), "half a state was accepted");
+    let output = play(&mut host, &noise(2, 1024, 7), 256, &[]);
+    assert!(output.iter().flatten().all(|x| x.is_finite()));
+    assert_eq!(host.save_state(), state, "a rejected state still changed the parameters");
+}
+
+#[test]
+fn a_session_from_before_the_drive_split_drives_the_console_too() {
+    // The state nih-plug writes is a length prefixed JSON document, a session saved before the split
+    // only has the one drive in it
+    let state = started(2, 48000.0, 256, false, None).save_state();
+    let mut document: serde_json::Value = serde_json::from_slice(&state[8..]).expect("the state isn't JSON");
+    let params = document["params"].as_object_mut().unwrap();
+    params.remove("console_drive");
+    params.remove("console_threshold");
+    params.insert("drive".to_string(), serde_json::json!(0.7));
+    let json = serde_json::to_vec(&document).unwrap();
+    let mut old_state = (json.len() as u64).to_le_bytes().to_vec();
+    old_state.extend(json);
+
+    let host = started(2, 48000.0, 256, false, Some(&old_state));
+    assert!((host.param_value("drive") - 0.35).abs() < 1e-6, "the drive didn't load");
+    assert_eq!(host.param_value("console_drive"), host.param_value("drive"), "the console drive didn't get the old drive");
+    assert_eq!(host.param_value("console_threshold"), 0.0, "the console threshold isn't off like the console before it");
+}
+
+#[test]
+fn reset_leaves_nothing_of_what_played_before() {
+    let sample_rate = 48000.0;
+    let mut played = started(2, sample_rate, 512, false, None);
+    // Loud enough to fill every delay line and push the compressor and limiter hard
+    let loud: Vec<Vec<f32>> = noise(2, settle_frames(sample_rate), 8).into_iter().map(|c| c.iter().map(|x| x * 6.0).collect()).collect();
+    play(&mut played, &loud, 512, &busy_settings());
+    played.reset();
+
+    let mut fresh = started(2, sample_rate, 512, false, Some(&played.save_state()));
+    let probe = noise(2, 4096, 9);
+    let expected = play(&mut fresh, &probe, 512, &[]);
+    let got = play(&mut played, &probe, 512, &[]);
+    assert_same(&got, &expected, "after reset");
+}
+
+#[test]
+fn deactivating_and_activating_again_starts_clean_in_the_new_setup() {
+    let mut host = started(2, 48000.0, 512, false, None);
+    play(&mut host, &noise(2, settle_frames(48000.0), 10), 512, &busy_settings());
+    host.deactivate();
+
+    // Hosts may come back with a different layout, rate and buffer size
+    let mono = layout(&host, 1);
+    host.select_layout(mono);
+    host.activate(96000.0, 128, true);
+    let mut fresh = started(1, 96000.0, 128, true, Some(&host.save_state()));
+
+    let probe = noise(1, 8192, 11);
+    let expected = play(&mut fresh, &probe, 128, &[]);
+    let got = play(&mut host, &probe, 128, &[]);
+    assert_same(&got, &expected, "after deactivate and activate");
+}
+
+#[test]
+fn ceiling_latency_is_reported_to_the_host() {
+    let sample_rate = 48000.0;
+    let mut host = started(2, sample_rate, 256, false, None);
+    let modes = CeilingMode::variants().len();
+    for index in (0..modes).chain(0..1) {
+        let mode = CeilingMode::from_index(index);
+        let mut chain = DuroChain::new(sample_rate);
+        chain.update_ceiling(mode, 1.0);
+        let expected = chain.latency_samples();
+
+        let before = host.latency();
+        host.take_restart_requested();
+        host.take_latency_changed();
+        let mut buffers = noise(2, 256, 12);
+        host.process(&mut buffers, &[(0, "ceiling_mode", index as f64 / (modes - 1) as f64)]);
+        // While processing the plugin asks for a restart, otherwise it says the latency changed
+        let told = host.take_restart_requested() | host.take_latency_changed();
+        assert_eq!(host.latency(), expected, "wrong latency for {mode:?}");
+        assert_eq!(told, expected != before, "the host was not told about the change to {mode:?}, or told without one");
+        host.deactivate();
+        host.activate(sample_rate, 256, false);
+        assert_eq!(host.latency(), expected, "{mode:?} latency changed across a restart");
+    }
+}
@@ -0,0 +1,310 @@
+// realtime.rs - Ardura 2023
+// Realtime safety of Gain::process through the CLAP host in common/clap_host.rs: every saturation type
+// with every console type, the other modes cycled alongside, in every layout with the editor closed and open
+//  Debug builds run process under nih-plug's allocation checker, which aborts the test with a backtrace
+//  on any allocation. Every build fails on a process call that waited, counted from the audio thread's
+//  voluntary context switches on Linux. The host can't work the editor, so the curve, formula, generated
+//  console and impulse response handoffs get written from another thread straight into the engine
+//  Gain::process runs. The slowest blocks are only reported, wall clock time depends too much on the
+//  machine: cargo test --release --test realtime -- --ignored --nocapture
+
+mod common;
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use common::{
+    clap_host::{voluntary_switches, Host, Processed},
+    Noise,
+};
+use duro_dsp::Mode;
+use nih_plug::prelude::Enum;
+use Duro_Console::{
+    bus_compressor::DetectorMode,
+    choice::Choice,
+    console_models,
+    convolution::{self, Convolver},
+    custom_curve,
+    duro_process::{ConsoleMode, SaturationModeEnum},
+    dynamic_convolution::{self, DynamicConvolver},
+    engine::{Engine, Handoff},
+    fast_math::MathMode,
+    formula,
+    lofi::AntiAliasMode,
+    output_ceiling::CeilingMode,
+    GainParams,
+};
+
+const SAMPLE_RATE: f32 = 48000.0;
+const BLOCK: usize = 512;
+// Enough for a mode switch to crossfade and then run on its own
+const BLOCKS_PER_MODE: usize = 4;
+// Length of the impulse response and kernels loaded for the two consoles that use them
+const IMPULSE_SECONDS: f32 = 0.25;
+
+// Where a combination of modes starts, halfway into a block so the crossfade starts mid block too
+const SWITCH_AT: u32 = BLOCK as u32 / 2;
+// Where the tilt moves within every block, each move asks the background thread for a new Generated Console
+const TILT_AT: [u32; 3] = [BLOCK as u32 / 8, BLOCK as u32 * 3 / 8, BLOCK as u32 * 7 / 8];
+
+fn index<T: Enum>(mode: T) -> f64 {
+    mode.to_index() as f64 / (T::variants().len() - 1) as f64
+}
+
+fn switch(on: bool) -> f64 {
+    if on { 1.0 } else { 0.0 }
+}
+
+// Every saturation and console type against each other. The other mode parameters step through their
+// values alongside so each of them runs with many of the combinations, and every combination builds the
+// Generated Console from another seed
+fn combinations() -> Vec<(String, Vec<(&'static str, f64)>)> {
+    let mut combinations = Vec::new();
+    for sat in 0..SaturationModeEnum::COUNT {
+        for console in 0..ConsoleMode::COUNT {
+            let n = combinations.len();
+            let sat_type = SaturationModeEnum::from_index(sat);
+            let console_type = ConsoleMode::from_index(console);
+            let ceiling_mode = CeilingMode::from_index(n % CeilingMode::variants().len());
+            let math_mode = MathMode::from_index(n % MathMode::COUNT);
+            let anti_alias = AntiAliasMode::from_index(n % AntiAliasMode::COUNT);
+            let detector = DetectorMode::from_index(n % DetectorMode::COUNT);
+            let name = format!("{sat_type:?} into {console_type:?}, {ceiling_mode:?}, {math_mode:?}, {anti_alias:?}, {detector:?}");
+            combinations.push((
+                name,
+                vec![
+                    ("type", index(Choice(sat_type))),
+                    ("console_type", index(Choice(console_type))),
+                    ("ceiling_mode", index(ceiling_mode)),
+                    ("math_mode", index(Choice(math_mode))),
+                    ("anti_alias", index(Choice(anti_alias))),
+                    ("comp_detector", index(Choice(detector))),
+                    ("comp_auto_release", switch(n % 2 == 0)),
+                    ("dither", switch(n % 3 == 0)),
+                    ("variance", switch(n % 5 < 2)),
+                    ("console_seed", (n * 7919 % 100000) as f64 / 99999.0),
+                ],
+            ));
+        }
+    }
+    combinations
+}
+
+// Settings that keep every stage busy: the console driven, the compressor working and the bias on
+fn busy_settings() -> Vec<(u32, &'static str, f64)> {
+    vec![(0, "console_drive", 0.8), (0, "comp_ratio", 0.6), (0, "comp_threshold", 0.2), (0, "bias", 0.6), (0, "slew_rate", 0.5)]
+}
+
+// Decaying noise written as WAVs to a folder of their own, impulse.wav for the impulse response and a
+// kernels folder with two levels
+fn write_impulses(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("duro-realtime-{}-{name}", std::process::id()));
+    let kernels = dir.join("kernels");
+    std::fs::create_dir_all(&kernels).unwrap();
+    let spec = hound::WavSpec { channels: 1, sample_rate: SAMPLE_RATE as u32, bits_per_sample: 32, sample_format: hound::SampleFormat::Float };
+    let write = |path: &PathBuf, gain: f32| {
+        let length = (SAMPLE_RATE * IMPULSE_SECONDS) as usize;
+        let mut noise = Noise::new(3);
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for n in 0..length {
+            let decay = (-6.0 * n as f32 / length as f32).exp();
+            writer.write_sample(if n == 0 { gain } else { gain * 0.3 * decay * noise.next() }).unwrap();
+        }
+        writer.finalize().unwrap();
+    };
+    write(&dir.join("impulse.wav"), 1.0);
+    write(&kernels.join("-24.wav"), 0.5);
+    write(&kernels.join("-6.wav"), 1.0);
+    dir
+}
+
+// The plugin's state with some persistent fields replaced. nih-plug writes a length prefixed JSON
+// document and keeps each persistent field in it as a JSON string of its own
+fn with_fields(state: &[u8], fields: &[(&str, &str)]) -> Vec<u8> {
+    let mut document: serde_json::Value = serde_json::from_slice(&state[8..]).expect("the state isn't JSON");
+    for (key, value) in fields {
+        document["fields"][*key] = serde_json::Value::String(serde_json::to_string(value).unwrap());
+    }
+    let json = serde_json::to_vec(&document).unwrap();
+    let mut state = (json.len() as u64).to_le_bytes().to_vec();
+    state.extend(json);
+    state
+}
+
+// A started plugin with both convolution consoles loaded, or None when the editor should be open but
+// there's no display to open it on
+fn started(channels: usize, editor_open: bool) -> Option<Host> {
+    let mut host = Host::new();
+    let layout = *host.layouts().iter().find(|layout| layout.channels == channels).unwrap();
+    host.select_layout(layout);
+    if editor_open && !host.open_editor() {
+        return None;
+    }
+    // The responses load when the plugin is activated
+    let dir = write_impulses(&format!("{channels}-{editor_open}"));
+    let (impulse, kernels) = (dir.join("impulse.wav"), dir.join("kernels"));
+    let state = with_fields(&host.save_state(), &[("ir-path", impulse.to_str().unwrap()), ("kernel-folder", kernels.to_str().unwrap())]);
+    assert!(host.load_state(&state), "could not load the impulse responses");
+    host.activate(SAMPLE_RATE, BLOCK, false);
+    std::fs::remove_dir_all(dir).unwrap();
+    Some(host)
+}
+
+// Tilt automation moving several times within a block
+fn tilt_moves(block: usize) -> impl Iterator<Item = (u32, &'static str, f64)> {
+    TILT_AT.into_iter().enumerate().map(move |(n, at)| (at, "console_tilt", ((block * TILT_AT.len() + n) % 11) as f64 / 10.0))
+}
+
+// Play every combination of modes in one layout and editor state, with how each block's process call went
+fn play_every_mode(channels: usize, editor_open: bool, mut on_block: impl FnMut(&str, &Processed)) {
+    let what = format!("{channels} channels with the editor {}", if editor_open { "open" } else { "closed" });
+    let Some(mut host) = started(channels, editor_open) else {
+        eprintln!("no display to open the editor on, skipped {what}, run under xvfb-run to cover it");
+        return;
+    };
+    let mut noise = Noise::new(1);
+    let mut changes = busy_settings();
+    let mut blocks = 0;
+    for (name, modes) in combinations() {
+        changes.extend(modes.iter().map(|(id, value)| (SWITCH_AT, *id, *value)));
+        for _ in 0..BLOCKS_PER_MODE {
+            changes.extend(tilt_moves(blocks));
+            blocks += 1;
+            let mut buffers: Vec<Vec<f32>> = (0..channels).map(|_| (0..BLOCK).map(|_| 0.7 * noise.next()).collect()).collect();
+            let processed = host.process(&mut buffers, &changes);
+            changes.clear();
+            assert!(buffers.iter().flatten().all(|x| x.is_finite()), "{name} in {what} is not finite");
+            on_block(&format!("{name} in {what}"), &processed);
+        }
+    }
+}
+
+fn every_layout_and_editor_state(mut on_block: impl FnMut(&str, &Processed)) {
+    let channel_counts: Vec<usize> = Host::new().layouts().iter().map(|layout| layout.channels).collect();
+    for channels in channel_counts {
+        for editor_open in [false, true] {
+            play_every_mode(channels, editor_open, &mut on_block);
+        }
+    }
+}
+
+// A block that waited is a failure, the audio thread has nothing to wait for
+fn assert_no_waits(what: &str, waits: Option<u64>) {
+    if let Some(waits) = waits {
+        assert_eq!(waits, 0, "{what} waited {waits} times in process");
+    }
+}
+
+#[test]
+fn no_mode_allocates_or_waits_in_process() {
+    // Any allocation inside process aborts in a debug build, so getting through is that part of the test
+    if voluntary_switches().is_none() {
+        eprintln!("this system doesn't count context switches, only allocations are checked");
+    }
+    let mut blocks = 0;
+    every_layout_and_editor_state(|what, processed| {
+        assert_no_waits(what, processed.waits);
+        blocks += 1;
+    });
+    assert!(blocks > 0);
+}
+
+// Another curve, formula and console for each write so the audio thread really picks something up
+fn editor_writes(handoff: Handoff, stop: Arc<AtomicBool>, impulse: Vec<f32>, kernels: Vec<(f32, Vec<f32>)>) -> usize {
+    let programs: Vec<formula::Program> =
+        [formula::DEFAULT_FORMULA, "x - x * x * x / 3", "atan(x * 4) / 2"].iter().map(|text| formula::compile(text).unwrap()).collect();
+    // The plugin's background thread holds this around writing the generated taps
+    let generated_from = Mutex::new(());
+    let mut writes = 0;
+    while !stop.load(Ordering::Relaxed) {
+        let mut points = custom_curve::default_points();
+        for (_, y) in points.iter_mut() {
+            *y *= 1.0 - (writes % 4) as f32 * 0.2;
+        }
+        custom_curve::sanitize_points(&mut points);
+        handoff.custom_curve.write(&custom_curve::build_table(&points));
+        handoff.formula.write(&programs[writes % programs.len()]);
+        {
+            let _single_writer = generated_from.lock().unwrap();
+            handoff.generated.write(&console_models::generate_taps(writes as i32, (writes % 21) as f32 / 10.0 - 1.0));
+        }
+        // Loading responses takes a while, the audio thread gets to try the slots while one is held
+        if writes % 8 == 0 {
+            handoff.convolver_slot.lock().unwrap().offer(Box::new(Convolver::new(&impulse)));
+            handoff.dynamic_slot.lock().unwrap().offer(Box::new(DynamicConvolver::new(&kernels, SAMPLE_RATE)));
+        }
+        writes += 1;
+    }
+    writes
+}
+
+#[test]
+fn editor_handoffs_never_make_process_wait() {
+    if voluntary_switches().is_none() {
+        eprintln!("this system doesn't count context switches, skipped");
+        return;
+    }
+    let dir = write_impulses("handoffs");
+    let impulse = convolution::load_impulse(dir.join("impulse.wav").to_str().unwrap(), SAMPLE_RATE).unwrap();
+    let kernels = dynamic_convolution::load_kernels(dir.join("kernels").to_str().unwrap(), SAMPLE_RATE).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+
+    let params = GainParams::default();
+    let mut settings = params.settings();
+    for (_, id, value) in busy_settings() {
+        params.set_normalized(&mut settings, id, value as f32).unwrap();
+    }
+    let handoff = Handoff::new(settings.console_seed, settings.console_tilt);
+    let mut engine = Engine::new(SAMPLE_RATE, handoff.clone());
+    engine.chain.update_ceiling(settings.ceiling_mode, settings.ceiling);
+    engine.chain.jump_to(&settings);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let editor = {
+        let stop = stop.clone();
+        thread::spawn(move || editor_writes(handoff, stop, impulse, kernels))
+    };
+
+    let mut noise = Noise::new(1);
+    let mut frame = [0.0; 2];
+    for (name, modes) in combinations() {
+        for (id, value) in modes {
+            params.set_normalized(&mut settings, id, value as f32).unwrap();
+        }
+        for _ in 0..BLOCKS_PER_MODE {
+            let switches = voluntary_switches();
+            engine.start_block(&settings, true);
+            for _ in 0..BLOCK {
+                frame = [0.7 * noise.next(), 0.7 * noise.next()];
+                engine.process_frame(&mut frame, &settings, true);
+            }
+            let waits = voluntary_switches().zip(switches).map(|(after, before)| after - before);
+            assert!(frame.iter().all(|x| x.is_finite()), "{name} is not finite");
+            assert_no_waits(&name, waits);
+        }
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    assert!(editor.join().unwrap() > 0, "the editor thread never wrote anything");
+}
+
+#[test]
+#[ignore = "wall clock time depends on the machine, run it to see the slowest blocks in a release build"]
+fn report_slowest_blocks() {
+    let budget = Duration::from_secs_f32(BLOCK as f32 / SAMPLE_RATE);
+    let mut times: Vec<(Duration, String)> = Vec::new();
+    every_layout_and_editor_state(|what, processed| times.push((processed.elapsed, what.to_string())));
+    times.sort_by_key(|(elapsed, _)| std::cmp::Reverse(*elapsed));
+
+    println!("slowest blocks of {BLOCK} samples at {SAMPLE_RATE}Hz, {} us available:", budget.as_micros());
+    for (elapsed, what) in times.iter().take(10) {
+        println!("{:>8} us {:>5.1}%  {what}", elapsed.as_micros(), 100.0 * elapsed.as_secs_f32() / budget.as_secs_f32());
+    }
+}
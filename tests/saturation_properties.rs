@@ -0,0 +1,190 @@
+// saturation_properties.rs - Ardura 2023
+// Property tests for the saturation curves and the whole chain across every parameter's range
+//  No NaN, infinity or subnormal may come out, and each curve stays within the bound noted on it.
+//  The bounds are worked out from each curve's code, next to each one. More cases can be run with
+//  PROPTEST_CASES=10000 cargo test --test saturation_properties, the whole chain runs a tenth as many
+
+mod common;
+
+use common::{default_settings, render, Noise, SAMPLE_RATE};
+use duro_dsp::Mode;
+use nih_plug::prelude::Enum;
+use proptest::prelude::*;
+use Duro_Console::{
+    bus_compressor::DetectorMode,
+    chain::ChainSettings,
+    duro_process::{self as curves, ConsoleMode, SaturationModeEnum},
+    fast_math::MathMode,
+    harmonics::NUM_HARMONICS,
+    lofi::AntiAliasMode,
+    output_ceiling::CeilingMode,
+};
+
+// The threshold knob's -30 to 0dB, widened by the most channel variance can move it
+const MIN_THRESHOLD: f32 = 0.031_622_78 * 0.9;
+const MAX_THRESHOLD: f32 = 1.1;
+// duro_process swaps a drive of 0 for this before any curve sees it
+const TINY_DRIVE: f32 = 0.000001;
+// Input gain, bias and the compressor's makeup together can push a full scale signal this far
+const MAX_INPUT: f32 = 64.0;
+const GOLDEN_RATIO: f32 = 1.618_034;
+// Silence after the signal, long enough for every filter in the chain to decay past where it would go subnormal
+const TAIL_SECONDS: f32 = 3.0;
+// A chain case renders seconds of audio, so it runs one case for every this many of a curve
+const CHAIN_CASE_SHARE: u32 = 10;
+
+fn check(output: f32) -> Result<(), TestCaseError> {
+    prop_assert!(output.is_finite(), "{} is not finite", output);
+    prop_assert!(output == 0.0 || output.is_normal(), "{} is subnormal", output);
+    Ok(())
+}
+
+fn drive() -> impl Strategy<Value = f32> {
+    prop_oneof![Just(0.0), Just(TINY_DRIVE), Just(0.9999), Just(1.0), Just(2.0), 0.0f32..=2.0]
+}
+
+fn threshold() -> impl Strategy<Value = f32> {
+    prop_oneof![Just(MIN_THRESHOLD), Just(1.0), Just(MAX_THRESHOLD), MIN_THRESHOLD..=MAX_THRESHOLD]
+}
+
+fn input() -> impl Strategy<Value = f32> {
+    prop_oneof![Just(0.0), Just(1.0), Just(-1.0), -1e-3f32..=1e-3, -MAX_INPUT..=MAX_INPUT]
+}
+
+// Any bias leaves a constant offset for the DC filter to settle on, without one it decays towards 0
+fn bias() -> impl Strategy<Value = f32> {
+    prop_oneof![Just(0.0), -0.5f32..=0.5]
+}
+
+fn sat_type() -> impl Strategy<Value = SaturationModeEnum> {
+    (0..SaturationModeEnum::COUNT).prop_map(SaturationModeEnum::from_index)
+}
+
+fn console_type() -> impl Strategy<Value = ConsoleMode> {
+    (0..ConsoleMode::COUNT).prop_map(ConsoleMode::from_index)
+}
+
+// The default config reads PROPTEST_CASES, the chain follows it at its share
+fn chain_config() -> ProptestConfig {
+    ProptestConfig::with_cases((ProptestConfig::default().cases / CHAIN_CASE_SHARE).max(1))
+}
+
+proptest! {
+    #[test]
+    fn tape_stays_below_minus_12db(x in input(), threshold in threshold(), drive in drive(), fast: bool) {
+        let y = curves::tape_saturation(x, drive, threshold, fast);
+        check(y)?;
+        // The soft clip x / (1 + |x|) stays under 1, then the curve is lowered 12dB
+        prop_assert!(y.abs() <= 0.2512);
+    }
+
+    #[test]
+    fn tape_survives_a_threshold_of_zero(x in input(), drive in drive(), fast: bool) {
+        check(curves::tape_saturation(x, drive, 0.0, fast))?;
+    }
+
+    #[test]
+    fn digital_stays_within_its_mix(x in input(), threshold in threshold(), drive in drive()) {
+        let y = curves::digital_saturation(x, threshold, drive);
+        check(y)?;
+        // x * (1 - drive) + clipped * drive with the clipped signal within the threshold
+        prop_assert!(y.abs() <= x.abs() * (1.0 - drive).abs() + threshold * drive + 1e-6 * (1.0 + x.abs()));
+    }
+
+    #[test]
+    fn chebyshev_stays_within_full_scale(x in input(), threshold in threshold(), drive in drive()) {
+        let y = curves::chebyshev_tape(x, threshold, drive);
+        check(y)?;
+        prop_assert!(y.abs() <= 1.0);
+    }
+
+    #[test]
+    fn golden_cubic_stays_within_its_cube(x in input(), threshold in threshold(), drive in drive(), fast: bool) {
+        let y = curves::golden_cubic(x, threshold, drive, fast);
+        check(y)?;
+        let excess = (x.abs() - threshold).max(0.0);
+        let cubed = x.abs().min(threshold) + threshold * GOLDEN_RATIO * excess.powi(3);
+        // The soft clip adds at most 1 / (1 - 1/e) at no drive, then the curve is lowered 3dB
+        prop_assert!(y.abs() <= (cubed + 1.6) * 0.708 * 1.0001);
+    }
+
+    #[test]
+    fn candle_only_adds_up_to_full_scale(x in input(), threshold in threshold(), drive in drive()) {
+        let y = curves::candle_saturation(x, drive, threshold);
+        check(y)?;
+        // What it adds is the input past the threshold, never negative, through s / (1 + |s|) below 1
+        prop_assert!(y >= x && y <= x + 1.0);
+    }
+
+    #[test]
+    fn leaf_stays_within_threshold(x in input(), threshold in threshold(), drive in drive(), fast: bool) {
+        let y = curves::leaf_saturation(x, threshold, drive, fast);
+        check(y)?;
+        prop_assert!(y.abs() <= threshold.max(1.0) * 1.05);
+    }
+
+    #[test]
+    fn transformer_stays_within_full_scale(x in input(), threshold in threshold(), drive in drive(), fast: bool) {
+        let y = curves::transformer_saturation(x, threshold, drive, fast);
+        check(y)?;
+        prop_assert!(y.abs() <= 1.0);
+    }
+
+    #[test]
+    fn slew_moves_at_most_one_step(x in input(), previous in -1.0f32..=1.0, step in 0.0f32..=1.0) {
+        let y = curves::slew_saturation(x, previous, step);
+        check(y)?;
+        // Give or take the rounding of previous + step, and of taking previous away again here
+        prop_assert!((y - previous).abs() <= step + (previous.abs() + step) * 2.0 * f32::EPSILON);
+        // Never past the input, give or take the rounding of previous + (input - previous)
+        prop_assert!(y >= x.min(previous) - 1e-6 && y <= x.max(previous) + 1e-6);
+    }
+}
+
+// Every knob the chain has, anywhere in its range
+fn chain_settings() -> impl Strategy<Value = ChainSettings> {
+    let levels = (0.0f32..=4.0, threshold(), drive(), drive(), 0.0f32..=1.0, sat_type(), console_type());
+    let color = (0i32..=99999, -1.0f32..=1.0, any::<bool>(), 1i32..=64, 0.0f32..=10.0, bias(), 0.1f32..=20.0);
+    let lofi = (1.0f32..=24.0, any::<bool>(), 1.0f32..=64.0, 0usize..3, 0usize..3, 0.0f32..=0.3, 0.0f32..=360.0);
+    let comp = (0usize..2, -40.0f32..=0.0, 1.0f32..=20.0, 0.1f32..=100.0, 10.0f32..=2000.0, any::<bool>(), 0.0f32..=24.0, 0.0f32..=24.0);
+    let output = (0.25f32..=4.0, 0.0f32..=1.0, 0usize..3, 0.25f32..=1.0);
+    (levels, color, lofi, comp, output).prop_map(|(levels, color, lofi, comp, output)| {
+        let mut settings = default_settings();
+        (settings.free_gain, settings.threshold, settings.drive, settings.console_drive, settings.console_threshold, settings.sat_type, settings.console_type) = levels;
+        (settings.console_seed, settings.console_tilt, settings.variance, settings.channel_number, settings.variance_amount, settings.bias, settings.slew_rate) = color;
+        let (bit_depth, dither, downsample, anti_alias, math_mode, level, phase) = lofi;
+        settings.bit_depth = bit_depth;
+        settings.dither = dither;
+        settings.downsample = downsample;
+        settings.anti_alias = AntiAliasMode::from_index(anti_alias);
+        settings.math_mode = MathMode::from_index(math_mode);
+        settings.harmonic_levels = [level; NUM_HARMONICS];
+        settings.harmonic_phases = [phase; NUM_HARMONICS];
+        let (detector, comp_threshold, ratio, attack, release, auto_release, knee, makeup) = comp;
+        settings.comp_detector = DetectorMode::from_index(detector);
+        (settings.comp_threshold, settings.comp_ratio, settings.comp_attack, settings.comp_release) = (comp_threshold, ratio, attack, release);
+        (settings.comp_auto_release, settings.comp_knee, settings.comp_makeup) = (auto_release, knee, makeup);
+        let (output_gain, dry_wet, ceiling_mode, ceiling) = output;
+        (settings.output_gain, settings.dry_wet, settings.ceiling) = (output_gain, dry_wet, ceiling);
+        settings.ceiling_mode = CeilingMode::from_index(ceiling_mode);
+        settings
+    })
+}
+
+proptest! {
+    #![proptest_config(chain_config())]
+
+    // Noise at up to full scale, then silence for every filter and envelope to decay
+    #[test]
+    fn chain_never_outputs_nan_infinity_or_subnormals(settings in chain_settings(), seed: u32, frames in 1usize..4096) {
+        let mut noise = Noise::new(seed);
+        let tail = (SAMPLE_RATE * TAIL_SECONDS) as usize;
+        let signal: Vec<f32> = (0..(frames + tail) * 2)
+            .map(|n| if n < frames * 2 { noise.next() } else { 0.0 })
+            .collect();
+        for (n, y) in render(&settings, &signal, 2).into_iter().enumerate() {
+            prop_assert!(y.is_finite(), "sample {} is {}", n, y);
+            prop_assert!(y == 0.0 || y.is_normal(), "sample {} is subnormal ({})", n, y);
+        }
+    }
+}